@@ -1,10 +1,11 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use dp_client as ss;
-use curve25519_dalek::constants;
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 use rand_core::OsRng;
 use::dp_client::generic_commitments::{Commitment, CurveCommitment};
+use ss::curve_parameters::CurveParams;
+use ss::transcript::{Transcript, PRIVATE_BIT_LABEL};
 
 pub fn aux_ecc_commitments(com: &CurveCommitment){
         
@@ -15,32 +16,30 @@ pub fn aux_ecc_commitments(com: &CurveCommitment){
 }
 pub fn ecc_commitment(c: &mut Criterion) {
 
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     let com = ss::generic_commitments::CurveCommitment{g, h};    
     c.bench_function("Ristretto Coms", |b| b.iter(|| aux_ecc_commitments(&com)));
 }
 
 pub fn fiat_shamir_sigma_proof_creation(c: &mut Criterion){
 
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     
-    let num_shares = 2;
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
     let r = client.com.sample_randomness();
-    c.bench_function("proof-creation", |b| b.iter(|| client.com.create_proof_0(r)));
+    c.bench_function("proof-creation", |b| b.iter(|| client.com.create_proof_0(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"bench", 0))));
 }
 
 
 pub fn aggregation(c: &mut Criterion){
 
     let num_clients = 100000;
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
    
-    let num_shares = 2;
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
     let mut inputs: Vec<(Scalar, Scalar)>= Vec::new();
     for _ in 0..num_clients{
         let (x,r) = client.send_input_to_sever();
@@ -56,10 +55,9 @@ pub fn aux_com_agg()->bool{
 
 
     let num_clients = 1000;
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;   
-    let num_shares = 2;
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
+    let client = ss::participants::Client::new(g, h);
     let mut inputs: Vec<(Scalar, Scalar)>= Vec::new();
     let mut coms_to_inputs : Vec<RistrettoPoint> = Vec::new();    
     for _ in 0..num_clients{
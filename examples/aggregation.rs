@@ -1,14 +1,15 @@
 use curve25519_dalek::scalar::Scalar;
 use rand_core::OsRng;
-use std::time::{Instant};
+use tracing::{info, info_span};
 
 fn main(){
 
+    dp_client::logging::init_timing();
     let mut csprng = OsRng;
     let n_b = 262144;
     let n = 1000000;
 
-    println!("Creating {} + {} = {} random 256 bit integers",n, n_b, (n + n_b));
+    info!("Creating {} + {} = {} random 256 bit integers",n, n_b, (n + n_b));
     let mut inputs : Vec<Scalar> = Vec::new();
     for _ in 0..(n + n_b){
         let y: Scalar = Scalar::random(&mut csprng);
@@ -16,10 +17,10 @@ fn main(){
     }
 
     let mut answer = Scalar::zero();
-    let now = Instant::now();
-    for i in 0..(n + n_b){
-        answer = answer + inputs[i];
-    }
-    let end = now.elapsed().as_millis();
-    println!("Time Taken to add {} + {} = {} integers; {}ms", n, n_b, (n + n_b), end);
+    // The span logs the time taken when it closes
+    info_span!("add_integers", n, n_b).in_scope(|| {
+        for i in 0..(n + n_b){
+            answer = answer + inputs[i];
+        }
+    });
 }
\ No newline at end of file
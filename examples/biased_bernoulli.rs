@@ -1,29 +1,31 @@
 // use core::num;
 
-use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
-use curve25519_dalek::constants;
+use curve25519_dalek::scalar::Scalar;
 use ss::generic_commitments::Commitment;
 // use ss::participants::Board;
 extern crate dp_client as ss;
 use rand::Rng;
+use ss::curve_parameters::CurveParams;
+use tracing::info;
 
 fn main() {
 
+    ss::logging::init_timing();
     // Public Paramters
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     let k = 10;
     let l = 2;
     let mut bytes: [u8; 32] = [0; 32];
     bytes[0] = l as u8;
     let el_as_scalar: Scalar = Scalar::from_bits(bytes);
     
-    let  num_shares = 2;
-    let mut server = ss::participants::Server::new(num_shares, g, h);
+    let mut server = ss::participants::Server::new(g, h);
     let verifier = ss::participants::Board::new(g,h);
 
     
     // Generate random number in the range [0, 99]
+    let mut openings = Vec::new();
     for opening_idx in 0..100{
 
         
@@ -31,7 +33,7 @@ fn main() {
         
         // Verifier verifies if the bit is 0 or 1
         for i in 0..k{            
-            _ = verifier.verify(&dist_bernoulli_com.or_proofs[i]);
+            _ = verifier.verify(&dist_bernoulli_com.or_proofs[i], &mut ss::participants::Server::bernoulli_transcript(&server.session_id, opening_idx, i));
         }
             
         // Verfier aggregates coms and checks
@@ -44,9 +46,9 @@ fn main() {
     
         let challenge_idx = rand::thread_rng().gen_range(0..k);        
         let tmp = server.get_opening(opening_idx, challenge_idx);
-        print!("{:?} ", tmp);
+        openings.push(tmp);
         
     }
     
-    println!();
+    info!("Openings: {:?}", openings);
 }
\ No newline at end of file
@@ -1,33 +1,32 @@
-use curve25519_dalek::{ristretto::RistrettoPoint};
-use curve25519_dalek::constants;
 use ss::generic_commitments::Commitment;
 use ss::sigma_ff::ProofScalar;
 extern crate dp_client as ss;
-use std::time::{Instant};
+use tracing::info_span;
+use ss::curve_parameters::CurveParams;
+use ss::transcript::{Transcript, PRIVATE_BIT_LABEL};
 
 fn main(){
 
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
-    let num_shares = 2;
+    ss::logging::init_timing();
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
 
     // Clients send input to servers while publicly committing to input
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
     let verifier = ss::participants::Board::new(g, h);
     let n_b = 256;
     // let n_b = 8;
 
     let mut proofs: Vec<ProofScalar> = Vec::new();
-    for _ in 0..n_b{        
+    for i in 0..n_b{        
         let r = client.com.sample_randomness();
-        proofs.push(client.com.create_proof_0(r));
+        proofs.push(client.com.create_proof_0(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", i as u64)));
     }    
 
-    let now = Instant::now();
-    for i in 0..n_b{                
-        _ = verifier.verify(&proofs[i]);
-    }   
-    let end = now.elapsed();
-    println!("Time taken to sequentially verify {} proofs {} ms", n_b, end.as_millis());
-    println!("Time taken to sequentially verify {} proofs {} mu(s)", n_b, end.as_micros());
+    // The span logs the time taken when it closes
+    info_span!("verify_proofs_sequentially", n_b).in_scope(|| {
+        for (i, proof) in proofs.iter().enumerate(){
+            _ = verifier.verify(proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", i as u64));
+        }
+    });
 }
\ No newline at end of file
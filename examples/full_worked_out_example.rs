@@ -1,20 +1,22 @@
 use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
-use curve25519_dalek::constants;
 use ss::generic_commitments::Commitment;
 extern crate dp_client as ss;
-use coinflip::flip;
-// use std::time::{Instant};
+use rand_core::OsRng;
+use ss::rng::flip;
+use ss::curve_parameters::CurveParams;
+use ss::transcript::{Transcript, PRIVATE_BIT_LABEL};
+use tracing::info;
 
 fn main(){
 
+    ss::logging::init_timing();
     // Public Paramters
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     let num_clients = 1000;
-    let num_shares = 2;
 
     // Clients send input to servers while publicly committing to input
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
     let mut inputs: Vec<(Scalar, Scalar)>= Vec::new();
     let mut coms_to_inputs : Vec<RistrettoPoint> = Vec::new();    
     for _ in 0..num_clients{
@@ -38,23 +40,23 @@ fn main(){
     assert_eq!(lhs, rhs);
     
 
-    let server = ss::participants::Server::new(num_shares, g, h);
+    let server = ss::participants::Server::new(g, h);
     let verifier = ss::participants::Board::new(g, h);
 
     let n_b = 100;
 
     let mut private_bits: Vec<(Scalar, Scalar)>= Vec::new();
     let mut coms_to_bits : Vec<RistrettoPoint> = Vec::new();
-    for _ in 0..n_b{
+    for i in 0..n_b{
 
         // Prover commits to a bit
         let r = server.com.sample_randomness();
-        let transcript = server.com.create_proof_0(r);
+        let transcript = server.com.create_proof_0(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", i));
         // Verifier verifies if the bit is 0 or 1
-        _ = verifier.verify(&transcript);
+        _ = verifier.verify(&transcript, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", i));
 
         // Morra
-        let b = flip();
+        let b = flip(&mut OsRng);
         if b{
             private_bits.push((Scalar::one(), Scalar::one() - r));
             let com_one = server.com.commit(Scalar::one(),
@@ -83,7 +85,7 @@ fn main(){
     let rhs = coms_sum + v_coms_sum;
 
     assert_eq!(lhs, rhs);
-    println!("All worked out really nicely");    
+    info!("All worked out really nicely");    
 
     
 }
\ No newline at end of file
@@ -1,8 +1,6 @@
 // use core::num;
 
 
-use curve25519_dalek::ristretto::RistrettoPoint;
-use curve25519_dalek::constants;
 // use sha3::digest::typenum::Pow;
 // use ss::generic_commitments::Commitment;
 // use ss::participants::Board;
@@ -11,16 +9,18 @@ use rand::Rng;
 use ss::generic_commitments::Commitment;
 
 use ss::consants::MGRAIN;
+use ss::curve_parameters::CurveParams;
+use tracing::info;
 
 fn main() {
 
+    ss::logging::init_timing();
     // Public Paramters
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     let base_prob = 0.5276334472589853;
     
-    let  num_shares = 2;
-    let mut server = ss::participants::Server::new(num_shares, g, h);
+    let mut server = ss::participants::Server::new(g, h);
     let verifier = ss::participants::Board::new(g,h);
 
     let precision_bits = 12;
@@ -28,7 +28,7 @@ fn main() {
     for _ in 0..100{
         
         server.clear_openings();
-        println!("Base Geometric Probability Distribution: {}", base_prob);
+        info!("Base Geometric Probability Distribution: {}", base_prob);
         let dist_geom_com = server.distributional_geometric_com(precision_bits, base_prob);
 
         // Verifier picks a challenge bit for each the precision bits, which is picked by p_j(base_prob)
@@ -51,7 +51,7 @@ fn main() {
         let geom_noise_com = verifier.binary_to_exp(bit_coms);    
         let (geom_x, geom_r) = server.geometric_opening(verifier_challenge_indices);
         assert_eq!(verifier.com.commit(geom_x, geom_r), geom_noise_com);
-        println!("{:?}", geom_x.as_bytes());
+        info!("{:?}", geom_x.as_bytes());
         
     }
     
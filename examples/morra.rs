@@ -1,25 +1,25 @@
-use curve25519_dalek::constants;
-use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 use rand_core::OsRng;
 use::dp_client::generic_commitments::{Commitment};
 use dp_client as ss;
-use std::time::{Instant};
+use tracing::{info, info_span};
+use ss::curve_parameters::CurveParams;
 
 fn main(){
 
+    ss::logging::init_timing();
     let mut csprng = OsRng;
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;   
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
     
-    let num_shares = 2;
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
 
     let n_b = 262144;
     // let n_b = 1000;
 
-    let mut b_sum = 0;    
-    let now = Instant::now();    
+    let mut b_sum = 0;
+    // The span logs the time taken when it closes
+    let span = info_span!("generate_coins", n_b).entered();
     for _ in 0..n_b{
         
         let x0 = Scalar::random(&mut csprng);
@@ -40,7 +40,6 @@ fn main(){
             b_sum += 0;
         }
     }
-    let end = now.elapsed().as_millis();
-    println!("Time Taken generate {} coins; {} ms", n_b, end);
-    println!("{:?}", b_sum);
+    span.exit();
+    info!("{:?}", b_sum);
 }
\ No newline at end of file
@@ -1,18 +1,18 @@
 extern crate dp_client as ss;
-use curve25519_dalek::{ristretto::RistrettoPoint};
-use curve25519_dalek::constants;
 use ss::generic_commitments::Commitment;
 use ss::sigma_ff::ProofScalar;
-use std::time::{Instant};
+use tracing::info_span;
+use ss::curve_parameters::CurveParams;
+use ss::transcript::{Transcript, PRIVATE_BIT_LABEL};
 
 fn main(){
 
-    let h = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-    let g = constants::RISTRETTO_BASEPOINT_POINT;
-    let num_shares = 2;
+    ss::logging::init_timing();
+    let params = CurveParams::new();
+    let (g, h) = (params.g, params.h);
 
     // Clients send input to servers while publicly committing to input
-    let client = ss::participants::Client::new(num_shares, g, h);
+    let client = ss::participants::Client::new(g, h);
 
     // epsilon = 10*np.sqrt(1/n*np.log(2/delta))
     // delta = 10^-10
@@ -21,31 +21,29 @@ fn main(){
 
     for i in 0..private_coins.len(){
         let n_b = private_coins[i];
-        let now = Instant::now();
-        for _ in 0..n_b{
-            let r = client.com.sample_randomness();
-            let _ = client.com.create_proof_0(r);
-        }
-        let end = now.elapsed().as_millis();
-        println!("Time taken to sequentially create proofs for epsilon: {} or ({} coins) := {} ms", corresponding_epsilons[i], n_b, end);
+        // The spans log the time taken when they close
+        info_span!("create_proofs_sequentially", epsilon = corresponding_epsilons[i], n_b).in_scope(|| {
+            for j in 0..n_b{
+                let r = client.com.sample_randomness();
+                let _ = client.com.create_proof_0(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", j as u64));
+            }
+        });
     }
 
-    println!();
     let verifier = ss::participants::Board::new(g, h);
     for i in 0..private_coins.len(){
         let n_b = private_coins[i];
         let mut proofs: Vec<ProofScalar> = Vec::new();
-        for _ in 0..n_b{        
+        for j in 0..n_b{        
             let r = client.com.sample_randomness();
-            proofs.push(client.com.create_proof_0(r));
+            proofs.push(client.com.create_proof_0(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", j as u64)));
         }    
     
-        let now = Instant::now();
-        for _ in 0..n_b{                
-            _ = verifier.verify(&proofs[i]);
-        }   
-        let end = now.elapsed().as_millis();
-        println!("Time taken to sequentially verify proofs for epsilon: {} or ({} coins) := {} ms", corresponding_epsilons[i], n_b, end);
+        info_span!("verify_proofs_sequentially", epsilon = corresponding_epsilons[i], n_b).in_scope(|| {
+            for (j, proof) in proofs.iter().enumerate(){
+                _ = verifier.verify(proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"example", j as u64));
+            }
+        });
     }
 
 
@@ -0,0 +1,254 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use openssl::bn::{BigNum, BigNumContext};
+use rand_core::OsRng;
+
+// Common interface of the two arithmetic backends of the crate: OpenSSL BigNums mod q (prio, poplar,
+// finite_field_coms) and the curve25519 scalar field with the Ristretto group (participants, binomial_runner).
+// A Field or Group value describes the structure and does the arithmetic, so the modulus and OpenSSL's context
+// live there rather than in every element.
+
+pub trait Field {
+    type Elem: PartialEq + Debug;
+
+    fn zero(&self) -> Self::Elem;
+    fn element(&self, x: u64) -> Self::Elem; // x mod the order
+    fn add(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+    fn sub(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+    fn mul(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+    fn inv(&self, a: &Self::Elem) -> Option<Self::Elem>; // None for 0
+    fn random(&self) -> Self::Elem; // Uniform, from OsRng
+
+    fn one(&self) -> Self::Elem {
+        self.element(1)
+    }
+
+    fn neg(&self, a: &Self::Elem) -> Self::Elem {
+        self.sub(&self.zero(), a)
+    }
+
+    fn sum<'a, I: IntoIterator<Item = &'a Self::Elem>>(&self, xs: I) -> Self::Elem where Self::Elem: 'a {
+        xs.into_iter().fold(self.zero(), |acc, x| self.add(&acc, x))
+    }
+
+    // Inner product of two vectors of the same length
+    fn dot(&self, a: &[Self::Elem], b: &[Self::Elem]) -> Self::Elem {
+        a.iter().zip(b.iter()).fold(self.zero(), |acc, (x, y)| self.add(&acc, &self.mul(x, y)))
+    }
+}
+
+// Cyclic group of prime order, written multiplicatively, with its exponents in Scalars
+pub trait Group {
+    type Scalars: Field;
+    type Elem: PartialEq + Debug;
+
+    fn scalars(&self) -> &Self::Scalars;
+    fn identity(&self) -> Self::Elem;
+    fn op(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+    fn exp(&self, base: &Self::Elem, k: &ScalarOf<Self>) -> Self::Elem;
+}
+
+pub type ScalarOf<G> = <<G as Group>::Scalars as Field>::Elem;
+
+// Integers mod a prime q with OpenSSL
+pub struct BigNumField {
+    q: BigNum,
+    ctx: RefCell<BigNumContext>,
+}
+
+impl BigNumField {
+
+    pub fn new(q: &BigNum) -> BigNumField {
+        Self { q: (**q).to_owned().unwrap(), ctx: RefCell::new(BigNumContext::new().unwrap()) }
+    }
+
+    pub fn modulus(&self) -> &BigNum {
+        &self.q
+    }
+
+    fn mod_op(&self, op: impl FnOnce(&mut BigNum, &mut BigNumContext) -> Result<(), openssl::error::ErrorStack>) -> BigNum {
+        let mut out = BigNum::new().unwrap();
+        op(&mut out, &mut self.ctx.borrow_mut()).unwrap();
+        out
+    }
+}
+
+impl Field for BigNumField {
+    type Elem = BigNum;
+
+    fn zero(&self) -> BigNum {
+        BigNum::new().unwrap()
+    }
+
+    fn element(&self, x: u64) -> BigNum {
+        let x = BigNum::from_slice(&x.to_be_bytes()).unwrap();
+        self.mod_op(|out, ctx| out.nnmod(&x, &self.q, ctx))
+    }
+
+    fn add(&self, a: &BigNum, b: &BigNum) -> BigNum {
+        self.mod_op(|out, ctx| out.mod_add(a, b, &self.q, ctx))
+    }
+
+    fn sub(&self, a: &BigNum, b: &BigNum) -> BigNum {
+        self.mod_op(|out, ctx| out.mod_sub(a, b, &self.q, ctx))
+    }
+
+    fn mul(&self, a: &BigNum, b: &BigNum) -> BigNum {
+        self.mod_op(|out, ctx| out.mod_mul(a, b, &self.q, ctx))
+    }
+
+    fn inv(&self, a: &BigNum) -> Option<BigNum> {
+        let mut out = BigNum::new().unwrap();
+        out.mod_inverse(a, &self.q, &mut self.ctx.borrow_mut()).ok()?;
+        Some(out)
+    }
+
+    fn random(&self) -> BigNum {
+        let mut out = BigNum::new().unwrap();
+        self.q.rand_range(&mut out).unwrap();
+        out
+    }
+}
+
+// The scalar field of curve25519, pure Rust
+#[derive(Clone, Copy, Default)]
+pub struct ScalarField;
+
+impl Field for ScalarField {
+    type Elem = Scalar;
+
+    fn zero(&self) -> Scalar {
+        Scalar::zero()
+    }
+
+    fn element(&self, x: u64) -> Scalar {
+        Scalar::from(x)
+    }
+
+    fn add(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a + b
+    }
+
+    fn sub(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a - b
+    }
+
+    fn mul(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a * b
+    }
+
+    fn inv(&self, a: &Scalar) -> Option<Scalar> {
+        (*a != Scalar::zero()).then(|| a.invert())
+    }
+
+    fn random(&self) -> Scalar {
+        Scalar::random(&mut OsRng)
+    }
+}
+
+// The Ristretto group over curve25519, the group of CurveCommitment
+#[derive(Clone, Copy, Default)]
+pub struct RistrettoGroup;
+
+impl Group for RistrettoGroup {
+    type Scalars = ScalarField;
+    type Elem = RistrettoPoint;
+
+    fn scalars(&self) -> &ScalarField {
+        &ScalarField
+    }
+
+    fn identity(&self) -> RistrettoPoint {
+        RistrettoPoint::identity()
+    }
+
+    fn op(&self, a: &RistrettoPoint, b: &RistrettoPoint) -> RistrettoPoint {
+        a + b
+    }
+
+    fn exp(&self, base: &RistrettoPoint, k: &Scalar) -> RistrettoPoint {
+        base * k
+    }
+}
+
+// The subgroup of order q of the integers mod p, for a prime q dividing p - 1, with OpenSSL. The group of
+// finite_field_coms::Commitment.
+pub struct SchnorrGroup {
+    p: BigNum,
+    scalars: BigNumField,
+}
+
+impl SchnorrGroup {
+
+    pub fn new(p: &BigNum, q: &BigNum) -> SchnorrGroup {
+        Self { p: (**p).to_owned().unwrap(), scalars: BigNumField::new(q) }
+    }
+
+    pub fn modulus(&self) -> &BigNum {
+        &self.p
+    }
+}
+
+impl Group for SchnorrGroup {
+    type Scalars = BigNumField;
+    type Elem = BigNum;
+
+    fn scalars(&self) -> &BigNumField {
+        &self.scalars
+    }
+
+    fn identity(&self) -> BigNum {
+        BigNum::from_u32(1).unwrap()
+    }
+
+    fn op(&self, a: &BigNum, b: &BigNum) -> BigNum {
+        self.scalars.mod_op(|out, ctx| out.mod_mul(a, b, &self.p, ctx))
+    }
+
+    fn exp(&self, base: &BigNum, k: &BigNum) -> BigNum {
+        self.scalars.mod_op(|out, ctx| out.mod_exp(base, k, &self.p, ctx))
+    }
+}
+
+
+#[test]
+pub fn test_field_backends() {
+    use crate::public_parameters::PublicParams;
+
+    // Field laws on random elements, for any backend
+    fn check_field<F: Field>(field: &F) {
+        let (a, b, c) = (field.random(), field.random(), field.random());
+        assert_eq!(field.add(&a, &b), field.add(&b, &a));
+        assert_eq!(field.mul(&a, &field.add(&b, &c)), field.add(&field.mul(&a, &b), &field.mul(&a, &c)));
+        assert_eq!(field.sub(&field.add(&a, &b), &b), a);
+        assert_eq!(field.add(&a, &field.neg(&a)), field.zero());
+        assert_eq!(field.mul(&a, &field.inv(&a).unwrap()), field.one());
+        assert_eq!(field.inv(&field.zero()), None);
+        assert_eq!(field.sum(&[field.element(2), field.element(3)]), field.element(5));
+        assert_eq!(field.dot(&[field.element(2), field.element(3)], &[field.element(4), field.element(5)]), field.element(23));
+    }
+
+    check_field(&ScalarField);
+    let public_param = PublicParams::new(128, 2).unwrap();
+    check_field(&BigNumField::new(&public_param.q));
+}
+
+#[test]
+pub fn test_group_backends() {
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+
+    fn check_group<G: Group>(group: &G, g: &G::Elem) {
+        let scalars = group.scalars();
+        let (a, b) = (scalars.random(), scalars.random());
+        assert_eq!(group.op(&group.exp(g, &a), &group.exp(g, &b)), group.exp(g, &scalars.add(&a, &b)));
+        assert_eq!(group.exp(g, &scalars.zero()), group.identity());
+    }
+
+    check_group(&RistrettoGroup, &RISTRETTO_BASEPOINT_POINT);
+    // 23 = 2 * 11 + 1, and 4 generates the squares mod 23, of order 11
+    let group = SchnorrGroup::new(&BigNum::from_u32(23).unwrap(), &BigNum::from_u32(11).unwrap());
+    check_group(&group, &BigNum::from_u32(4).unwrap());
+}
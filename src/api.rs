@@ -0,0 +1,1536 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{error, guard, web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use crate::binomial_runner::{BinomialRunner, DEFAULT_DATASET};
+use crate::distributed_noise::{NoiseContribution, NoiseError, NoiseShare, MAX_NOISE_SERVERS};
+use crate::progress::{ProgressEvent, ProgressHook};
+use crate::secrets::Secret;
+use crate::sessions::{now_secs, same_hash, token_hash, CapabilityTokens, ClientToken, Role, SessionError, SessionManager};
+use crate::submissions::{AcceptedInput, InputSubmission, OpeningShare, SubmissionError, MAX_AGGREGATORS};
+
+// Version 1 of the REST API. Reads are GET, steps that change a session are POST, and every response is a JSON
+// envelope: {"data": ...} on success and {"error": {"code": ..., "message": ...}} on failure.
+// The OpenAPI document for it is served at /v1/openapi.json.
+// Creating a session takes the operator token of the server config and returns one bearer token per role, which the
+// operator hands to each party. Every route under /v1/sessions/{session_id} checks
+// the Authorization header against the roles allowed for it before the handler runs.
+// The long steps (creating a session, the randomness and the final check) answer with server-sent events instead
+// when the request has Accept: text/event-stream.
+// A session created with a collection window takes its inputs from the clients instead of from x, one JSON
+// submission per request or many as NDJSON, and the noise steps wait until the window closes. The operator issues
+// each client a token of its own under /clients. The openings of the inputs never reach the server: each aggregator
+// gets its own token and sends its sum of the clients' shares under /inputs/shares once the window closes.
+// A session created with noise servers takes its noise from them instead of from the prover: each server gets its
+// own token, commits to its bits under /noise/contributions and sends its share under /noise/shares.
+// Either way, the public coins the noise bits are XORed with follow from a seed the auditor posts under /coin_seed
+// once the bits are committed. The prover never picks it, so it cannot try seeds until the coins suit it.
+
+#[derive(Serialize, ToSchema)]
+pub struct Envelope<T> {
+    pub data: T,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String, // Stable machine readable code, e.g. "session_not_found"
+    pub message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorEnvelope {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl fmt::Display) -> ApiError {
+        Self { status: StatusCode::BAD_REQUEST, code: "invalid_request", message: message.to_string() }
+    }
+
+    pub fn envelope(&self) -> ErrorEnvelope {
+        ErrorEnvelope { error: ErrorBody { code: self.code.to_string(), message: self.message.clone() } }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self.envelope())
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(e: SessionError) -> Self {
+        let status = match e {
+            SessionError::NotFound => StatusCode::NOT_FOUND,
+            SessionError::TooManySessions(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SessionError::InputTooLarge { .. } | SessionError::NoiseTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            SessionError::Unauthorized => StatusCode::UNAUTHORIZED,
+            SessionError::Forbidden(_) => StatusCode::FORBIDDEN,
+            SessionError::InputsOpen | SessionError::TooManyClients(_) | SessionError::CounterStarted => StatusCode::CONFLICT,
+            SessionError::NoCounter => StatusCode::NOT_FOUND,
+            SessionError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self { status, code: e.code(), message: e.to_string() }
+    }
+}
+
+impl From<SubmissionError> for ApiError {
+    fn from(e: SubmissionError) -> Self {
+        let status = match e {
+            SubmissionError::InputsClosed | SubmissionError::DuplicateClient | SubmissionError::InputsOpen
+                | SubmissionError::NotAggregated | SubmissionError::AlreadyShared => StatusCode::CONFLICT,
+            SubmissionError::InvalidClientId => StatusCode::BAD_REQUEST,
+            SubmissionError::InvalidOpening | SubmissionError::InvalidProof | SubmissionError::InvalidShare => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        Self { status, code: e.code(), message: e.to_string() }
+    }
+}
+
+impl From<NoiseError> for ApiError {
+    fn from(e: NoiseError) -> Self {
+        let status = match e {
+            NoiseError::WrongLength { .. } => StatusCode::BAD_REQUEST,
+            NoiseError::InvalidProof | NoiseError::InvalidShare => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::CONFLICT,
+        };
+        Self { status, code: e.code(), message: e.to_string() }
+    }
+}
+
+impl From<error::BlockingError> for ApiError {
+    fn from(e: error::BlockingError) -> Self {
+        Self { status: StatusCode::INTERNAL_SERVER_ERROR, code: "internal_error", message: e.to_string() }
+    }
+}
+
+// Malformed JSON bodies get the same envelope as every other error
+pub fn json_error_handler(err: error::JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let status = match err {
+        error::JsonPayloadError::OverflowKnownLength { .. } | error::JsonPayloadError::Overflow { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    ApiError { status, ..ApiError::bad_request(err) }.into()
+}
+
+// Settings of the server that the /v1 routes read, registered as web::Data next to the SessionManager
+#[derive(Clone, Debug, Default)]
+pub struct ApiConfig {
+    pub operator_token: Option<Secret<String>>, // Without one, no session can be created
+    pub datasets: Vec<String>, // Datasets a session may name besides "default"
+}
+
+impl ApiConfig {
+
+    // The dataset is a metric label, so only the configured ones are taken
+    pub fn check_dataset(&self, dataset: &str) -> Result<(), ApiError> {
+        if dataset != DEFAULT_DATASET && !self.datasets.iter().any(|d| d == dataset) {
+            return Err(ApiError { code: "unknown_dataset", ..ApiError::bad_request(format!("dataset {:?} is not configured on this server", dataset)) });
+        }
+        Ok(())
+    }
+
+    fn is_operator(&self, token: &str) -> bool {
+        self.operator_token.as_ref().is_some_and(|expected| same_hash(&token_hash(expected), &token_hash(token)))
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers().get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+// Creating sessions and issuing client tokens, which hand out the tokens of every role
+async fn operators_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let allowed = match (req.app_data::<web::Data<ApiConfig>>(), bearer_token(&req)) {
+        (Some(config), Some(token)) => config.is_operator(token),
+        _ => false,
+    };
+    if allowed {
+        next.call(req).await.map(ServiceResponse::map_into_left_body)
+    } else {
+        let refusal = ApiError { status: StatusCode::UNAUTHORIZED, code: "unauthorized", message: "Missing or invalid operator token".to_string() };
+        Ok(req.error_response(refusal).map_into_right_body())
+    }
+}
+
+// Capability check for the routes of one session. A refusal is answered here, the handler never runs.
+// The role is left in the request extensions for handlers that need it, as web::ReqData<Role>, and for a client
+// the ID its token is bound to, as web::ReqData<ClientId>.
+#[derive(Clone)]
+struct ClientId(String);
+
+async fn authorize<B: MessageBody>(allowed: impl Fn(Role) -> bool, req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let role = match (req.app_data::<web::Data<SessionManager>>(), bearer_token(&req)) {
+        (Some(sessions), Some(token)) => sessions.authorize(req.match_info().get("session_id").unwrap_or_default(), token),
+        _ => Err(SessionError::Unauthorized),
+    };
+    match role {
+        Ok(role) if allowed(role) => {
+            if role == Role::Client {
+                let client_id = match (req.app_data::<web::Data<SessionManager>>(), bearer_token(&req)) {
+                    (Some(sessions), Some(token)) => sessions.client_id(req.match_info().get("session_id").unwrap_or_default(), token).ok(),
+                    _ => None,
+                };
+                req.extensions_mut().insert(ClientId(client_id.unwrap_or_default()));
+            }
+            req.extensions_mut().insert(role);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        },
+        Ok(role) => Ok(req.error_response(ApiError::from(SessionError::Forbidden(role))).map_into_right_body()),
+        Err(e) => Ok(req.error_response(ApiError::from(e)).map_into_right_body()),
+    }
+}
+
+// Submitting inputs
+async fn clients_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| [Role::Client].contains(&role), req, next).await
+}
+
+// Supplying randomness and running the mechanism
+async fn prover_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| [Role::Prover].contains(&role), req, next).await
+}
+
+// The seed of the public coins
+async fn auditor_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| [Role::Auditor].contains(&role), req, next).await
+}
+
+// Commitments and transcripts, read-only
+async fn auditors<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| [Role::Prover, Role::Auditor].contains(&role), req, next).await
+}
+
+// The input commitments, which clients may check against their own inputs, and the input status
+async fn any_role<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| matches!(role, Role::Client | Role::Prover | Role::Auditor | Role::Aggregator(_)), req, next).await
+}
+
+// Supplying a share of the opening of the inputs
+async fn aggregators_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| matches!(role, Role::Aggregator(_)), req, next).await
+}
+
+// The accepted clients and their share commitments, which the aggregators sum their shares over
+async fn aggregate_readers<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| matches!(role, Role::Prover | Role::Auditor | Role::Aggregator(_)), req, next).await
+}
+
+// Supplying a share of the noise
+async fn noise_servers_only<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| matches!(role, Role::NoiseServer(_)), req, next).await
+}
+
+// Progress of the distributed noise, which the servers poll for the public coins
+async fn noise_readers<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    authorize(|role| matches!(role, Role::Prover | Role::Auditor | Role::NoiseServer(_)), req, next).await
+}
+
+// Small requests run on a second rayon pool of their own, built on first use. On the global pool their proofs would
+// queue behind every chunk of a large batch that is already running.
+const SMALL_REQUEST_BITS: usize = 4096;
+
+static SMALL_REQUEST_POOL: OnceLock<Option<rayon::ThreadPool>> = OnceLock::new();
+
+pub fn run_sized<R: Send>(len: usize, f: impl FnOnce() -> R + Send) -> R {
+    if len < SMALL_REQUEST_BITS {
+        if let Some(pool) = SMALL_REQUEST_POOL.get_or_init(|| rayon::ThreadPoolBuilder::new().thread_name(|i| format!("small-request-{}", i)).build().ok()) {
+            return pool.install(f);
+        }
+    }
+    f()
+}
+
+// Runs f on the blocking thread pool. Both the crypto and any wait for a busy session happen there,
+// so the actix workers keep serving other sessions.
+pub async fn blocking<R: Send + 'static>(f: impl FnOnce() -> Result<R, SessionError> + Send + 'static) -> Result<R, ApiError> {
+    Ok(web::block(f).await??)
+}
+
+fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(Envelope { data })
+}
+
+fn wants_events(req: &HttpRequest) -> bool {
+    req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("text/event-stream"))
+}
+
+fn sse_frame(event: &str, data: &impl Serialize) -> web::Bytes {
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, serde_json::to_string(data).unwrap_or_default()))
+}
+
+// Runs step on the blocking thread pool like blocking does, and streams it: a "progress" event for each report of the
+// runner, then a "result" event with the usual envelope or an "error" event with the error envelope.
+// The status is 200 once the stream has started, so a failure of the step only shows in the last event.
+fn event_stream<T: Serialize + Send + 'static>(step: impl FnOnce(Option<ProgressHook>) -> Result<T, SessionError> + Send + 'static) -> HttpResponse {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let hook: ProgressHook = {
+        let tx = tx.clone();
+        Arc::new(move |event: ProgressEvent| { let _ = tx.send(sse_frame("progress", &event)); })
+    };
+    // The stream ends when the last sender is gone, i.e. after the final event, once the runner dropped the hook
+    actix_web::rt::spawn(async move {
+        let frame = match blocking(move || step(Some(hook))).await {
+            Ok(data) => sse_frame("result", &Envelope { data }),
+            Err(e) => sse_frame("error", &e.envelope()),
+        };
+        let _ = tx.send(frame);
+    });
+    let events = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|frame| (Ok::<_, Infallible>(frame), rx)) });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .streaming(events)
+}
+
+// The noise steps only start once the clients can no longer change the aggregate
+fn require_inputs_closed(runner: &BinomialRunner) -> Result<(), SessionError> {
+    if runner.inputs_open() {
+        return Err(SessionError::InputsOpen);
+    }
+    Ok(())
+}
+
+// The prover's noise steps, refused when the noise servers supply the noise. The servers are fixed when the
+// session is created, so this can be checked before the step runs.
+async fn require_prover_noise(sessions: &web::Data<SessionManager>, session_id: &str) -> Result<(), ApiError> {
+    let (sessions, session_id) = (sessions.clone(), session_id.to_string());
+    if blocking(move || sessions.read(&session_id, |runner| runner.get_noise_servers())).await? > 0 {
+        return Err(NoiseError::Distributed.into());
+    }
+    Ok(())
+}
+
+// Request bodies
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewSessionRequest {
+    #[serde(default)]
+    pub x: Vec<u8>, // Client input bits, empty when the clients submit them
+    #[serde(default)]
+    pub dataset: Option<String>, // Dataset the privacy budget is counted against, one configured on the server or "default" if absent
+    #[serde(default)]
+    pub collect: Option<InputWindow>, // Take the inputs from client submissions until the window closes
+    #[serde(default)]
+    pub noise_servers: Option<NoiseServers>, // Take the noise from these servers instead of from the prover
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NoiseServers {
+    pub count: usize, // 2 to 16 servers, one token each
+    pub bits: usize, // Noise bits n_b per server
+}
+
+// When input collection closes. At least one of the two must be given.
+#[derive(Deserialize, ToSchema)]
+pub struct InputWindow {
+    pub max_inputs: Option<usize>, // After this many accepted submissions, the per-session input limit if absent
+    pub deadline_secs: Option<u64>, // This many seconds after the session is created
+    #[serde(default = "default_aggregators")]
+    pub aggregators: usize, // 2 to 16 aggregators the openings are shared between, one token each
+}
+
+fn default_aggregators() -> usize {
+    2
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClientsRequest {
+    pub count: usize, // Client tokens to issue, up to max_inputs over all requests
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BitsRequest {
+    pub bits: Vec<u8>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CoinSeedRequest {
+    pub seed: Vec<u8>, // 32 bytes the auditor draws at random after the noise commitments are posted
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RandPInitRequest {
+    pub n: i32, // Number of biased coins
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RandPInputRequest {
+    pub k: u32, // Number of ones among the m bits
+    pub m: i32,
+    pub bits: Vec<u8>,
+}
+
+// Response data
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionCreated {
+    pub session_id: String,
+    pub tokens: CapabilityTokens, // Shown only once. Hand each token to the party playing that role.
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClientsIssued {
+    pub clients: Vec<ClientToken>, // Shown only once. Hand each token to its client.
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AcceptedInputs {
+    pub inputs: Vec<AcceptedInput>, // In the order they were accepted
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Commitments {
+    pub commitments: Vec<String>, // Compressed Ristretto points as decimal integers
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Bits {
+    pub bits: Vec<u8>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Success {
+    pub success: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Sum {
+    pub final_sum: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Randomness {
+    pub z: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Check {
+    pub lhs: String,
+    pub rhs: String,
+    pub valid: bool, // The proofs on the noise and the public coins verify and lhs == rhs, so the noise was added faithfully
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Ack {
+    pub ok: bool,
+}
+
+#[derive(Default, Serialize, ToSchema)]
+pub struct InputStatus {
+    pub inputs: usize, // Accepted so far
+    pub max_inputs: usize,
+    pub deadline: Option<u64>, // Unix seconds
+    pub open: bool,
+    pub shared: Vec<bool>, // Per aggregator, whether it sent its share of the opening
+}
+
+impl InputStatus {
+    fn of(runner: &BinomialRunner) -> InputStatus {
+        Self {
+            inputs: runner.num_inputs(),
+            max_inputs: runner.get_max_inputs(),
+            deadline: runner.get_input_deadline(),
+            open: runner.inputs_open(),
+            shared: runner.input_shares_progress(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NoiseStatus {
+    pub servers: usize, // 0 when the prover supplies the noise
+    pub bits: usize, // Per server
+    pub contributed: Vec<bool>, // Per server, in token order
+    pub shared: Vec<bool>,
+    pub public_bits: Vec<u8>, // Server j XORs its bits with public_bits[j * bits..(j + 1) * bits], empty until the auditor sent the coin seed
+}
+
+impl NoiseStatus {
+    fn of(runner: &BinomialRunner) -> NoiseStatus {
+        let (contributed, shared) = runner.noise_progress();
+        Self { servers: runner.get_noise_servers(), bits: runner.get_noise_server_bits(), contributed, shared, public_bits: runner.get_public_random() }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RejectedSubmission {
+    pub line: usize, // 0-based line of the NDJSON body
+    pub client_id: Option<String>, // None if the line did not parse
+    pub code: String, // As in the error envelope, e.g. "invalid_proof"
+    pub message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubmissionReport {
+    pub accepted: usize,
+    pub rejected: Vec<RejectedSubmission>,
+    pub status: InputStatus, // After the last line
+}
+
+// Handlers
+
+#[utoipa::path(post, path = "/v1/sessions", request_body = NewSessionRequest, security(("bearer" = [])),
+    responses((status = 201, body = Envelope<SessionCreated>), (status = 200, body = ProgressEvent, content_type = "text/event-stream"),
+        (status = 401, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope), (status = 503, body = ErrorEnvelope)))]
+async fn create_session(http: HttpRequest, req: web::Json<NewSessionRequest>, sessions: web::Data<SessionManager>, config: web::Data<ApiConfig>) -> Result<HttpResponse, ApiError> {
+    if let Some(dataset) = &req.dataset {
+        config.check_dataset(dataset)?;
+    }
+    if let Some(window) = &req.collect {
+        if !req.x.is_empty() {
+            return Err(ApiError::bad_request("x must be empty when the clients submit the inputs"));
+        }
+        if window.max_inputs.is_none() && window.deadline_secs.is_none() {
+            return Err(ApiError::bad_request("collect needs max_inputs, deadline_secs or both"));
+        }
+        if !(2..=MAX_AGGREGATORS).contains(&window.aggregators) {
+            return Err(ApiError::bad_request(format!("collect needs 2 to {} aggregators", MAX_AGGREGATORS)));
+        }
+    }
+    if let Some(servers) = &req.noise_servers {
+        if !(2..=MAX_NOISE_SERVERS).contains(&servers.count) || servers.bits == 0 {
+            return Err(ApiError::bad_request(format!("noise_servers needs 2 to {} servers with at least one bit each", MAX_NOISE_SERVERS)));
+        }
+    }
+    let create = move |progress: Option<ProgressHook>| {
+        let mut runner = match &req.collect {
+            Some(window) => {
+                let max_inputs = window.max_inputs.unwrap_or(sessions.limits().max_input_len);
+                sessions.check_input(max_inputs)?;
+                BinomialRunner::collecting(max_inputs, window.deadline_secs.map(|secs| now_secs().saturating_add(secs)), window.aggregators)
+            },
+            None => {
+                sessions.check_input(req.x.len())?; // Refuse before doing the commitments
+                run_sized(req.x.len(), || BinomialRunner::new_with_progress(&req.x, progress.as_ref()))
+            },
+        };
+        if let Some(dataset) = &req.dataset {
+            runner.set_dataset(dataset);
+        }
+        if let Some(servers) = &req.noise_servers {
+            sessions.reserve_noise(&runner.get_session_id(), servers.count.saturating_mul(servers.bits))?;
+            runner.set_noise_servers(servers.count, servers.bits);
+        }
+        sessions.insert(runner).map(|(session_id, tokens)| SessionCreated { session_id, tokens })
+    };
+    if wants_events(&http) {
+        return Ok(event_stream(create));
+    }
+    let data = blocking(move || create(None)).await?;
+    Ok(HttpResponse::Created().json(Envelope { data }))
+}
+
+#[utoipa::path(delete, path = "/v1/sessions/{session_id}", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Ack>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn delete_session(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    blocking(move || sessions.remove(&path)).await?;
+    Ok(ok(Ack { ok: true }))
+}
+
+// One token per client of a collecting session, each bound to a client ID the server picks
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/clients", params(("session_id" = String, Path)), request_body = ClientsRequest, security(("bearer" = [])),
+    responses((status = 200, body = Envelope<ClientsIssued>), (status = 401, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope)))]
+async fn post_clients(path: web::Path<String>, req: web::Json<ClientsRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let clients = blocking(move || sessions.issue_client_tokens(&path, req.count)).await?;
+    Ok(ok(ClientsIssued { clients }))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/commitments", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Commitments>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_commitments(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let commitments = blocking(move || sessions.read(&path, |runner| runner.get_x_commits())).await?;
+    Ok(ok(Commitments { commitments }))
+}
+
+// One submission as application/json, answered with Ack or the error of the submission, or many as
+// application/x-ndjson, answered with a SubmissionReport. The NDJSON form is handled by post_input_stream.
+// A submission is taken only under the client ID of the token it comes with.
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/inputs", params(("session_id" = String, Path)), security(("bearer" = [])),
+    request_body(content((InputSubmission = "application/json"), (String = "application/x-ndjson"))),
+    responses((status = 200, body = Envelope<Ack>, description = "Envelope<SubmissionReport> for an NDJSON body"), (status = 400, body = ErrorEnvelope), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope), (status = 422, body = ErrorEnvelope)))]
+async fn post_input(path: web::Path<String>, client_id: web::ReqData<ClientId>, req: web::Json<InputSubmission>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    if req.client_id != client_id.0 {
+        return Err(SubmissionError::InvalidClientId.into());
+    }
+    let mut results = blocking(move || sessions.update_with(&path, |runner| {
+        let results = runner.submit_inputs(std::slice::from_ref(&req));
+        let accepted = results[0].is_ok();
+        (results, accepted)
+    })).await?;
+    results.remove(0)?;
+    Ok(ok(Ack { ok: true }))
+}
+
+// Submissions of an NDJSON body are checked in batches of this many while the rest of the body is still arriving
+const SUBMISSION_BATCH: usize = 1024;
+
+// A submission is about 2 KiB of JSON
+const MAX_SUBMISSION_LINE: usize = 16 * 1024;
+
+// A line of an NDJSON body, which a relay may send for many clients. Each line carries the token of its client.
+#[derive(Deserialize)]
+struct RelayedSubmission {
+    token: String,
+    #[serde(flatten)]
+    submission: InputSubmission,
+}
+
+// Reads an NDJSON body of submissions as it streams in. Lines that do not parse, whose token is not the one of their
+// client and submissions that fail their checks are reported and skipped, the rest count towards the aggregate.
+struct SubmissionStream {
+    session_id: String,
+    sessions: web::Data<SessionManager>,
+    lines: usize,
+    batch: Vec<(usize, InputSubmission)>,
+    report: SubmissionReport,
+}
+
+impl SubmissionStream {
+    fn reject(&mut self, line: usize, client_id: Option<String>, code: &str, message: impl fmt::Display) {
+        self.report.rejected.push(RejectedSubmission { line, client_id, code: code.to_string(), message: message.to_string() });
+    }
+
+    async fn line(&mut self, text: &[u8]) -> Result<(), ApiError> {
+        let line = self.lines;
+        self.lines += 1;
+        if self.lines > self.sessions.limits().max_input_len {
+            return Err(SessionError::InputTooLarge { len: self.lines, max: self.sessions.limits().max_input_len }.into());
+        }
+        if text.trim_ascii().is_empty() {
+            return Ok(());
+        }
+        match serde_json::from_slice::<RelayedSubmission>(text) {
+            Ok(relayed) if self.sessions.client_id(&self.session_id, &relayed.token).is_ok_and(|id| id == relayed.submission.client_id) => {
+                self.batch.push((line, relayed.submission));
+            },
+            Ok(relayed) => {
+                let e = SubmissionError::InvalidClientId;
+                self.reject(line, Some(relayed.submission.client_id), e.code(), e);
+            },
+            Err(e) => self.reject(line, None, "invalid_request", e),
+        }
+        if self.batch.len() == SUBMISSION_BATCH {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ApiError> {
+        let (lines, submissions): (Vec<usize>, Vec<InputSubmission>) = std::mem::take(&mut self.batch).into_iter().unzip();
+        let (session_id, sessions) = (self.session_id.clone(), self.sessions.clone());
+        // Saved once for the whole stream, see post_input_stream
+        let (results, submissions) = blocking(move || sessions.update_with(&session_id, |runner| {
+            ((run_sized(submissions.len(), || runner.submit_inputs(&submissions)), submissions), false)
+        })).await?;
+        for ((line, submission), result) in lines.into_iter().zip(submissions).zip(results) {
+            match result {
+                Ok(()) => self.report.accepted += 1,
+                Err(e) => self.reject(line, Some(submission.client_id), e.code(), e),
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn post_input_stream(path: web::Path<String>, mut body: web::Payload, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let report = SubmissionReport { accepted: 0, rejected: Vec::new(), status: InputStatus::default() };
+    let mut stream = SubmissionStream { session_id: path.into_inner(), sessions, lines: 0, batch: Vec::new(), report };
+    let streamed = async {
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk.map_err(ApiError::bad_request)?);
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let text: Vec<u8> = buffer.drain(..=end).collect();
+                stream.line(&text).await?;
+            }
+            if buffer.len() > MAX_SUBMISSION_LINE {
+                return Err(ApiError { status: StatusCode::PAYLOAD_TOO_LARGE, ..ApiError::bad_request(format!("Lines are limited to {} bytes", MAX_SUBMISSION_LINE)) });
+            }
+        }
+        stream.line(&buffer).await?; // The last line may lack its newline
+        stream.flush().await
+    }.await;
+
+    // The batches were taken without saving. The session is saved once, also when the stream broke off after some
+    // submissions were accepted.
+    let (session_id, sessions) = (stream.session_id.clone(), stream.sessions.clone());
+    if stream.report.accepted > 0 {
+        let (session_id, sessions) = (session_id.clone(), sessions.clone());
+        blocking(move || sessions.save(&session_id)).await?;
+    }
+    streamed?;
+    stream.report.status = blocking(move || sessions.read(&session_id, InputStatus::of)).await?;
+    Ok(ok(stream.report))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/inputs/status", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<InputStatus>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_input_status(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    Ok(ok(blocking(move || sessions.read(&path, InputStatus::of)).await?))
+}
+
+// The accepted clients with their share commitments. Each aggregator sums its shares over exactly these clients.
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/inputs/accepted", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<AcceptedInputs>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_accepted_inputs(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let inputs = blocking(move || sessions.read(&path, |runner| runner.accepted_inputs())).await?;
+    Ok(ok(AcceptedInputs { inputs }))
+}
+
+// The aggregator index comes from the token, an aggregator can only speak for itself
+fn aggregator(role: &Role) -> usize {
+    match role {
+        Role::Aggregator(j) => *j,
+        _ => unreachable!("Routed through aggregators_only"),
+    }
+}
+
+// An aggregator's sum of the shares of the accepted clients, once the window is closed
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/inputs/shares", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = OpeningShare,
+    responses((status = 200, body = Envelope<InputStatus>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope), (status = 422, body = ErrorEnvelope)))]
+async fn post_input_share(path: web::Path<String>, role: web::ReqData<Role>, req: web::Json<OpeningShare>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let index = aggregator(&role);
+    let status = blocking(move || sessions.update(&path, |runner| runner.add_input_share(index, *req).map(|_| InputStatus::of(runner)))).await??;
+    Ok(ok(status))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/randomness", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = BitsRequest,
+    responses((status = 200, content((Envelope<Ack> = "application/json"), (ProgressEvent = "text/event-stream"))),
+        (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope)))]
+async fn post_randomness(http: HttpRequest, path: web::Path<String>, req: web::Json<BitsRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    require_prover_noise(&sessions, &path).await?;
+    let step = move |progress: Option<ProgressHook>| {
+        sessions.reserve_noise(&path, req.bits.len())?;
+        sessions.update(&path, |runner| -> Result<(), SessionError> {
+            require_inputs_closed(runner)?;
+            runner.set_progress_hook(progress);
+            run_sized(req.bits.len(), || runner.commit_randomness(&req.bits));
+            runner.set_progress_hook(None);
+            Ok(())
+        })??;
+        Ok(Ack { ok: true })
+    };
+    if wants_events(&http) {
+        return Ok(event_stream(step));
+    }
+    Ok(ok(blocking(move || step(None)).await?))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/private_commitments", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Commitments>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_private_commitments(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let commitments = blocking(move || sessions.read(&path, |runner| runner.get_private_random_commits())).await?;
+    Ok(ok(Commitments { commitments }))
+}
+
+// The auditor's seed of the public coins, taken once the noise bits are committed. Afterwards the prover can read
+// its XORed bits and the noise servers their coins.
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/coin_seed", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = CoinSeedRequest,
+    responses((status = 200, body = Envelope<Ack>), (status = 400, body = ErrorEnvelope), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope)))]
+async fn post_coin_seed(path: web::Path<String>, req: web::Json<CoinSeedRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let seed: [u8; 32] = req.seed.as_slice().try_into().map_err(|_| ApiError::bad_request("seed must be 32 bytes"))?;
+    blocking(move || sessions.update(&path, |runner| runner.take_coin_seed(seed))).await??;
+    Ok(ok(Ack { ok: true }))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/public_bits", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Bits>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_public_bits(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let bits = blocking(move || sessions.read(&path, |runner| runner.get_public_random())).await?;
+    Ok(ok(Bits { bits }))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/rand_p/init", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = RandPInitRequest,
+    responses((status = 200, body = Envelope<Ack>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope)))]
+async fn post_rand_p_init(path: web::Path<String>, req: web::Json<RandPInitRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    require_prover_noise(&sessions, &path).await?;
+    blocking(move || {
+        sessions.reserve_noise(&path, req.n.max(0) as usize)?;
+        sessions.update(&path, |runner| {
+            require_inputs_closed(runner)?;
+            runner.rand_p_init(req.n);
+            Ok(())
+        })?
+    }).await?;
+    Ok(ok(Ack { ok: true }))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/rand_p/input", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = RandPInputRequest,
+    responses((status = 200, body = Envelope<Success>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope)))]
+async fn post_rand_p_input(path: web::Path<String>, req: web::Json<RandPInputRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let max = sessions.limits().max_noise_bits;
+    if req.bits.len() > max {
+        return Err(SessionError::NoiseTooLarge { len: req.bits.len(), max }.into());
+    }
+    let success = blocking(move || sessions.update(&path, |runner| runner.random_variable_p_input(req.k, req.m, &req.bits))).await?;
+    Ok(ok(Success { success }))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/rand_p/end", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Success>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn post_rand_p_end(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let success = blocking(move || sessions.update(&path, |runner| runner.random_variable_p_end())).await?;
+    Ok(ok(Success { success }))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/xor_bits", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Bits>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_xor_bits(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let bits = blocking(move || sessions.read(&path, |runner| runner.get_xor_bits())).await?;
+    Ok(ok(Bits { bits }))
+}
+
+#[cfg(feature = "cheat-mode")]
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/xor_bits/overwrite", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = BitsRequest,
+    responses((status = 200, body = Envelope<Ack>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope), (status = 413, body = ErrorEnvelope)))]
+async fn post_overwrite_xor_bits(path: web::Path<String>, req: web::Json<BitsRequest>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    blocking(move || {
+        sessions.reserve_noise(&path, req.bits.len())?;
+        sessions.update(&path, |runner| runner.overwrite_xor_bits(&req.bits))
+    }).await?;
+    Ok(ok(Ack { ok: true }))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/xor_commitments", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Commitments>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_xor_commitments(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let commitments = blocking(move || sessions.read(&path, |runner| runner.get_xor_commits())).await?;
+    Ok(ok(Commitments { commitments }))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/sum", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Sum>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn post_sum(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    // Only the first release changes the session, a repeated sum is not saved again
+    let final_sum = blocking(move || sessions.update_with(&path, |runner| match require_inputs_closed(runner) {
+        Ok(()) => {
+            let released = runner.is_released();
+            let sum = runner.compute_sum();
+            (Ok(sum), !released && runner.is_released())
+        },
+        Err(e) => (Err(e), false),
+    })?).await??;
+    Ok(ok(Sum { final_sum }))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/noise", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<NoiseStatus>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_noise(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    Ok(ok(blocking(move || sessions.read(&path, NoiseStatus::of)).await?))
+}
+
+// The server index comes from the token, a server can only speak for itself
+fn noise_server(role: &Role) -> usize {
+    match role {
+        Role::NoiseServer(j) => *j,
+        _ => unreachable!("Routed through noise_servers_only"),
+    }
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/noise/contributions", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = NoiseContribution,
+    responses((status = 200, body = Envelope<NoiseStatus>), (status = 400, body = ErrorEnvelope), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope), (status = 422, body = ErrorEnvelope)))]
+async fn post_noise_contribution(path: web::Path<String>, role: web::ReqData<Role>, req: web::Json<NoiseContribution>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let server = noise_server(&role);
+    let status = blocking(move || sessions.update(&path, |runner| {
+        require_inputs_closed(runner)?;
+        Ok(run_sized(req.proofs.len(), || runner.add_noise_contribution(server, &req)).map(|_| NoiseStatus::of(runner)))
+    })?).await??;
+    Ok(ok(status))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/noise/shares", params(("session_id" = String, Path)), security(("bearer" = [])), request_body = NoiseShare,
+    responses((status = 200, body = Envelope<NoiseStatus>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope), (status = 409, body = ErrorEnvelope), (status = 422, body = ErrorEnvelope)))]
+async fn post_noise_share(path: web::Path<String>, role: web::ReqData<Role>, req: web::Json<NoiseShare>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let server = noise_server(&role);
+    let status = blocking(move || sessions.update(&path, |runner| runner.add_noise_share(server, *req).map(|_| NoiseStatus::of(runner)))).await??;
+    Ok(ok(status))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/z", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Randomness>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_z(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let z = blocking(move || sessions.read(&path, |runner| runner.get_z())).await?;
+    Ok(ok(Randomness { z }))
+}
+
+#[utoipa::path(post, path = "/v1/sessions/{session_id}/commit_pedersons", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, content((Envelope<Check> = "application/json"), (ProgressEvent = "text/event-stream"))),
+        (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn post_commit_pedersons(http: HttpRequest, path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let step = move |progress: Option<ProgressHook>| sessions.update_with(&path, |runner| {
+        let before = (runner.get_lhs(), runner.get_rhs());
+        runner.set_progress_hook(progress);
+        runner.commit_pedersons();
+        let valid = runner.verify();
+        runner.set_progress_hook(None);
+        let check = Check { lhs: runner.get_lhs(), rhs: runner.get_rhs(), valid };
+        let changed = (&check.lhs, &check.rhs) != (&before.0, &before.1);
+        (check, changed)
+    });
+    if wants_events(&http) {
+        return Ok(event_stream(step));
+    }
+    Ok(ok(blocking(move || step(None)).await?))
+}
+
+#[utoipa::path(get, path = "/v1/sessions/{session_id}/check", params(("session_id" = String, Path)), security(("bearer" = [])),
+    responses((status = 200, body = Envelope<Check>), (status = 401, body = ErrorEnvelope), (status = 403, body = ErrorEnvelope), (status = 404, body = ErrorEnvelope)))]
+async fn get_check(path: web::Path<String>, sessions: web::Data<SessionManager>) -> Result<HttpResponse, ApiError> {
+    let (lhs, rhs, valid) = blocking(move || sessions.read(&path, |runner| (runner.get_lhs(), runner.get_rhs(), runner.is_valid()))).await?;
+    Ok(ok(Check { lhs, rhs, valid }))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Verifiable differential privacy API", version = "1"),
+    paths(
+        create_session, delete_session, post_clients, get_commitments, post_input, get_input_status, get_accepted_inputs, post_input_share, post_randomness, post_coin_seed, get_private_commitments, get_public_bits,
+        post_rand_p_init, post_rand_p_input, post_rand_p_end, get_xor_bits,
+        get_xor_commitments, post_sum, get_noise, post_noise_contribution, post_noise_share, get_z, post_commit_pedersons, get_check,
+    ),
+    components(schemas(SubmissionReport)),
+    modifiers(&BearerAuth),
+)]
+pub struct ApiDoc;
+
+// Routes that only exist with the cheat-mode feature
+#[cfg(feature = "cheat-mode")]
+#[derive(OpenApi)]
+#[openapi(paths(post_overwrite_xor_bits))]
+struct CheatApiDoc;
+
+// The document served at /v1/openapi.json, covering the routes of this build
+pub fn api_doc() -> utoipa::openapi::OpenApi {
+    #[allow(unused_mut)]
+    let mut doc = ApiDoc::openapi();
+    #[cfg(feature = "cheat-mode")]
+    doc.merge(CheatApiDoc::openapi());
+    doc
+}
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        }
+    }
+}
+
+async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(api_doc())
+}
+
+// Mounts the /v1 scope. The SessionManager and the ApiConfig must already be registered as app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/v1")
+            .app_data(web::PathConfig::default().error_handler(|err, _| ApiError::bad_request(err).into()))
+            .route("/openapi.json", web::get().to(openapi))
+            .service(web::resource("/sessions").wrap(from_fn(operators_only)).route(web::post().to(create_session)))
+            .service(web::resource("/sessions/{session_id}").wrap(from_fn(prover_only)).route(web::delete().to(delete_session)))
+            .service(web::resource("/sessions/{session_id}/clients").wrap(from_fn(operators_only)).route(web::post().to(post_clients)))
+            .service(web::resource("/sessions/{session_id}/commitments").wrap(from_fn(any_role)).route(web::get().to(get_commitments)))
+            .service(web::resource("/sessions/{session_id}/inputs").wrap(from_fn(clients_only))
+                .route(web::post().guard(guard::Header("content-type", "application/x-ndjson")).to(post_input_stream))
+                .route(web::post().to(post_input)))
+            .service(web::resource("/sessions/{session_id}/inputs/status").wrap(from_fn(any_role)).route(web::get().to(get_input_status)))
+            .service(web::resource("/sessions/{session_id}/inputs/accepted").wrap(from_fn(aggregate_readers)).route(web::get().to(get_accepted_inputs)))
+            .service(web::resource("/sessions/{session_id}/inputs/shares").wrap(from_fn(aggregators_only)).route(web::post().to(post_input_share)))
+            .service(web::resource("/sessions/{session_id}/randomness").wrap(from_fn(prover_only)).route(web::post().to(post_randomness)))
+            .service(web::resource("/sessions/{session_id}/private_commitments").wrap(from_fn(auditors)).route(web::get().to(get_private_commitments)))
+            .service(web::resource("/sessions/{session_id}/coin_seed").wrap(from_fn(auditor_only)).route(web::post().to(post_coin_seed)))
+            .service(web::resource("/sessions/{session_id}/public_bits").wrap(from_fn(auditors)).route(web::get().to(get_public_bits)))
+            .service(web::resource("/sessions/{session_id}/rand_p/init").wrap(from_fn(prover_only)).route(web::post().to(post_rand_p_init)))
+            .service(web::resource("/sessions/{session_id}/rand_p/input").wrap(from_fn(prover_only)).route(web::post().to(post_rand_p_input)))
+            .service(web::resource("/sessions/{session_id}/rand_p/end").wrap(from_fn(prover_only)).route(web::post().to(post_rand_p_end)))
+            .service(web::resource("/sessions/{session_id}/xor_bits").wrap(from_fn(prover_only)).route(web::get().to(get_xor_bits)))
+            .service(web::resource("/sessions/{session_id}/xor_commitments").wrap(from_fn(auditors)).route(web::get().to(get_xor_commitments)))
+            .service(web::resource("/sessions/{session_id}/sum").wrap(from_fn(prover_only)).route(web::post().to(post_sum)))
+            .service(web::resource("/sessions/{session_id}/noise").wrap(from_fn(noise_readers)).route(web::get().to(get_noise)))
+            .service(web::resource("/sessions/{session_id}/noise/contributions").wrap(from_fn(noise_servers_only)).route(web::post().to(post_noise_contribution)))
+            .service(web::resource("/sessions/{session_id}/noise/shares").wrap(from_fn(noise_servers_only)).route(web::post().to(post_noise_share)))
+            .service(web::resource("/sessions/{session_id}/z").wrap(from_fn(auditors)).route(web::get().to(get_z)))
+            .service(web::resource("/sessions/{session_id}/commit_pedersons").wrap(from_fn(prover_only)).route(web::post().to(post_commit_pedersons)))
+            .service(web::resource("/sessions/{session_id}/check").wrap(from_fn(auditors)).route(web::get().to(get_check)));
+    #[cfg(feature = "cheat-mode")]
+    let scope = scope.service(web::resource("/sessions/{session_id}/xor_bits/overwrite").wrap(from_fn(prover_only)).route(web::post().to(post_overwrite_xor_bits)));
+    cfg.service(scope.default_service(web::to(|| async {
+        ApiError { status: StatusCode::NOT_FOUND, code: "not_found", message: "No such endpoint".to_string() }.error_response()
+    })));
+}
+
+
+#[actix_web::test]
+pub async fn test_v1_api() {
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::Value;
+
+    let limits = SessionLimits { max_input_len: 8, ..SessionLimits::default() };
+    let sessions = web::Data::new(SessionManager::new(limits, Box::new(MemoryStore)).unwrap());
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(
+        App::new()
+            .app_data(sessions)
+            .app_data(web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() }))
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .configure(configure)
+    ).await;
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(serde_json::json!({"x": [1, 0, 1]})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let prover = ("Authorization", format!("Bearer {}", body["data"]["tokens"]["prover"].as_str().unwrap()));
+    let auditor = ("Authorization", format!("Bearer {}", body["data"]["tokens"]["auditor"].as_str().unwrap()));
+
+    let req = test::TestRequest::get().uri(&format!("/v1/sessions/{}/commitments", id)).insert_header(prover.clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["commitments"].as_array().unwrap().len(), 3);
+
+    // The exact sum is never released, nor anything before the auditor's seed is in, and the seed is taken once
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/sum", id)).insert_header(prover.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "noise_not_committed");
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(prover.clone()).set_json(serde_json::json!({"bits": [1, 1, 0, 0]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/sum", id)).insert_header(prover.clone()).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["error"]["code"], "coins_missing");
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor.clone()).set_json(serde_json::json!({"seed": vec![7; 31]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor.clone()).set_json(serde_json::json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor).set_json(serde_json::json!({"seed": vec![8; 32]})).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["error"]["code"], "coins_drawn");
+    for step in ["sum", "commit_pedersons"] {
+        let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/{}", id, step)).insert_header(prover.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+    let req = test::TestRequest::get().uri(&format!("/v1/sessions/{}/check", id)).insert_header(prover.clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["valid"], true);
+
+    // Errors carry the code of the crate error in the same envelope
+    let req = test::TestRequest::get().uri("/v1/sessions/no-such-session/z").insert_header(prover).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "session_not_found");
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(serde_json::json!({"x": vec![1; 9]})).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["error"]["code"], "input_too_large");
+
+    // Only configured datasets become metric labels
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(serde_json::json!({"x": [1], "dataset": "not-configured"})).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["error"]["code"], "unknown_dataset");
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).insert_header(("content-type", "application/json")).set_payload("{\"x\": 3}").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "invalid_request");
+
+    let req = test::TestRequest::get().uri("/v1/openapi.json").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body["paths"]["/v1/sessions/{session_id}/check"]["get"].is_object());
+}
+
+// A noisy sum of 0 is released like any other, and the session keeps answering afterwards
+#[actix_web::test]
+pub async fn test_v1_zero_sum() {
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::{json, Value};
+
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+
+    // With an input of 0 and one noise bit, half the sessions come out at 0
+    for _ in 0..64 {
+        let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"x": [0]})).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let uri = |step: &str| format!("/v1/sessions/{}/{}", body["data"]["session_id"].as_str().unwrap(), step);
+        let bearer = |role: &str| ("Authorization", format!("Bearer {}", body["data"]["tokens"][role].as_str().unwrap()));
+        let req = test::TestRequest::post().uri(&uri("randomness")).insert_header(bearer("prover")).set_json(json!({"bits": [0]})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::post().uri(&uri("coin_seed")).insert_header(bearer("auditor")).set_json(json!({"seed": vec![7; 32]})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let mut sums = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post().uri(&uri("sum")).insert_header(bearer("prover")).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            sums.push(test::read_body_json::<Value, _>(resp).await["data"]["final_sum"].clone());
+        }
+        assert_eq!(sums[0], sums[1]);
+        if sums[0] == 0 {
+            return;
+        }
+    }
+    panic!("No session came out at 0");
+}
+
+// Steps that leave the session as it was do not write it to the store again
+#[actix_web::test]
+pub async fn test_v1_saves_only_changes() {
+    use actix_web::{test, App};
+    use crate::sessions::{SessionLimits, SessionStore, StoredSession, StoredSessionRef};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CountingStore(Arc<AtomicUsize>);
+
+    impl SessionStore for CountingStore {
+        fn save(&self, _session_id: &str, _session: &StoredSessionRef) -> std::io::Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn load_all(&self) -> std::io::Result<Vec<(String, StoredSession)>> {
+            Ok(Vec::new())
+        }
+
+        fn remove(&self, _session_id: &str) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let saves = Arc::new(AtomicUsize::new(0));
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(CountingStore(saves.clone()))).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(("Authorization", "Bearer operator")).set_json(json!({"x": [1, 0, 1]})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let uri = |step: &str| format!("/v1/sessions/{}/{}", body["data"]["session_id"].as_str().unwrap(), step);
+    let bearer = |role: &str| ("Authorization", format!("Bearer {}", body["data"]["tokens"][role].as_str().unwrap()));
+    let req = test::TestRequest::post().uri(&uri("randomness")).insert_header(bearer("prover")).set_json(json!({"bits": [1, 1, 0, 0]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&uri("coin_seed")).insert_header(bearer("auditor")).set_json(json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    for (step, saved) in [("sum", 1), ("sum", 0), ("commit_pedersons", 1), ("commit_pedersons", 0), ("sum", 0)] {
+        let before = saves.load(Ordering::Relaxed);
+        let req = test::TestRequest::post().uri(&uri(step)).insert_header(bearer("prover")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert_eq!(saves.load(Ordering::Relaxed) - before, saved, "{}", step);
+    }
+}
+
+// Every session route, called without a token and with each role's token, and session creation without the operator token
+#[actix_web::test]
+pub async fn test_v1_route_roles() {
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::{json, Value};
+
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"x": [1, 0, 1]})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let token = |role: &str| body["data"]["tokens"][role].as_str().unwrap().to_string();
+    let (prover, auditor) = (token("prover"), token("auditor"));
+
+    // Only the operator creates sessions, not the parties of another one
+    for header in [None, Some("forged"), Some(prover.as_str())] {
+        let mut req = test::TestRequest::post().uri("/v1/sessions").set_json(json!({"x": [1]}));
+        if let Some(token) = header {
+            req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+        }
+        assert_eq!(test::call_service(&app, req.to_request()).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let p = [Role::Prover].as_slice();
+    let pa = [Role::Prover, Role::Auditor].as_slice();
+    let routes: Vec<(&str, &str, Option<Value>, &[Role])> = vec![
+        ("GET", "commitments", None, pa),
+        ("POST", "randomness", Some(json!({"bits": [1, 0, 1, 1]})), p),
+        ("POST", "coin_seed", Some(json!({"seed": vec![7; 32]})), &[Role::Auditor]),
+        ("GET", "private_commitments", None, pa),
+        ("GET", "public_bits", None, pa),
+        ("POST", "rand_p/init", Some(json!({"n": 1})), p),
+        ("POST", "rand_p/input", Some(json!({"k": 1, "m": 2, "bits": [1, 0]})), p),
+        ("POST", "rand_p/end", None, p),
+        ("GET", "xor_bits", None, p),
+        ("GET", "xor_commitments", None, pa),
+        ("POST", "sum", None, p),
+        ("GET", "z", None, pa),
+        ("POST", "commit_pedersons", None, p),
+        ("GET", "check", None, pa),
+        ("DELETE", "", None, p),
+    ];
+    #[cfg(feature = "cheat-mode")]
+    let routes = {
+        let mut routes = routes;
+        routes.insert(routes.len() - 1, ("POST", "xor_bits/overwrite", Some(json!({"bits": [1, 0, 1, 1]})), p));
+        routes
+    };
+    for (method, route, json_body, allowed) in routes {
+        let uri = if route.is_empty() { format!("/v1/sessions/{}", id) } else { format!("/v1/sessions/{}/{}", id, route) };
+        let call = |token: Option<&str>| {
+            let mut req = test::TestRequest::default().method(method.parse().unwrap()).uri(&uri);
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            if let Some(json_body) = &json_body {
+                req = req.set_json(json_body);
+            }
+            req.to_request()
+        };
+        assert_eq!(test::call_service(&app, call(None)).await.status(), StatusCode::UNAUTHORIZED, "{} {}", method, route);
+        assert_eq!(test::call_service(&app, call(Some("forged"))).await.status(), StatusCode::UNAUTHORIZED, "{} {}", method, route);
+        // The prover goes last, so that DELETE only removes the session after the other roles were refused
+        for (role, token) in [(Role::Auditor, &auditor), (Role::Prover, &prover)] {
+            let status = test::call_service(&app, call(Some(token))).await.status();
+            if allowed.contains(&role) {
+                assert!(status.is_success(), "{:?} refused on {} {}: {}", role, method, route, status);
+            } else {
+                assert_eq!(status, StatusCode::FORBIDDEN, "{:?} on {} {}", role, method, route);
+            }
+        }
+    }
+
+    // The input routes of a collecting session, where clients and aggregators have tokens too. Only the
+    // capability check is looked at here, test_v1_client_inputs runs the steps themselves.
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"collect": {"max_inputs": 1}})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let token = |token: &Value| token.as_str().unwrap().to_string();
+    let tokens = &body["data"]["tokens"];
+    let (prover, auditor, aggregator) = (token(&tokens["prover"]), token(&tokens["auditor"]), token(&tokens["aggregators"][0]));
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/clients", id)).insert_header(("Authorization", format!("Bearer {}", prover)))
+        .set_json(json!({"count": 1})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/clients", id)).insert_header(operator).set_json(json!({"count": 1})).to_request();
+    let client = test::call_and_read_body_json::<_, _, Value>(&app, req).await["data"]["clients"][0]["token"].as_str().unwrap().to_string();
+    let submission = serde_json::to_value(InputSubmission::new(&id, "client-1", true, 2).0).unwrap();
+    let share = json!({"value": vec![0; 32], "randomness": vec![0; 32]});
+    let any = [Role::Client, Role::Prover, Role::Auditor, Role::Aggregator(0)].as_slice();
+    let routes: Vec<(&str, &str, Option<Value>, &[Role])> = vec![
+        ("GET", "commitments", None, any),
+        ("GET", "inputs/status", None, any),
+        ("POST", "inputs", Some(submission), &[Role::Client]),
+        ("GET", "inputs/accepted", None, &[Role::Prover, Role::Auditor, Role::Aggregator(0)]),
+        ("POST", "inputs/shares", Some(share), &[Role::Aggregator(0)]),
+    ];
+    for (method, route, json_body, allowed) in routes {
+        let uri = format!("/v1/sessions/{}/{}", id, route);
+        for (role, token) in [(Role::Client, &client), (Role::Aggregator(0), &aggregator), (Role::Auditor, &auditor), (Role::Prover, &prover)] {
+            let mut req = test::TestRequest::default().method(method.parse().unwrap()).uri(&uri).insert_header(("Authorization", format!("Bearer {}", token)));
+            if let Some(json_body) = &json_body {
+                req = req.set_json(json_body);
+            }
+            let status = test::call_service(&app, req.to_request()).await.status();
+            if allowed.contains(&role) {
+                assert!(![StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN].contains(&status), "{:?} refused on {} {}: {}", role, method, route, status);
+            } else {
+                assert_eq!(status, StatusCode::FORBIDDEN, "{:?} on {} {}", role, method, route);
+            }
+        }
+    }
+}
+
+// The long steps with Accept: text/event-stream report progress and end with the envelope they would return as JSON
+#[actix_web::test]
+pub async fn test_v1_event_stream() {
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::{json, Value};
+
+    let limits = SessionLimits { max_noise_bits: 2000, ..SessionLimits::default() };
+    let sessions = web::Data::new(SessionManager::new(limits, Box::new(MemoryStore)).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+
+    // Splits the body into (event, data) pairs
+    let events = |body: web::Bytes| -> Vec<(String, Value)> {
+        String::from_utf8(body.to_vec()).unwrap().split("\n\n").filter(|frame| !frame.is_empty()).map(|frame| {
+            let (event, data) = frame.split_once('\n').unwrap();
+            (event.strip_prefix("event: ").unwrap().to_string(), serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap())
+        }).collect()
+    };
+    let sse = ("Accept", "text/event-stream");
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).insert_header(sse).set_json(json!({"x": vec![1; 500]})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/event-stream");
+    let created = events(test::read_body(resp).await);
+    let (last, data) = created.last().unwrap();
+    assert_eq!(last, "result");
+    let id = data["data"]["session_id"].as_str().unwrap().to_string();
+    let prover = ("Authorization", format!("Bearer {}", data["data"]["tokens"]["prover"].as_str().unwrap()));
+    let auditor = ("Authorization", format!("Bearer {}", data["data"]["tokens"]["auditor"].as_str().unwrap()));
+    let progress: Vec<&Value> = created.iter().filter(|(e, _)| e == "progress").map(|(_, d)| d).collect();
+    assert_eq!(progress.first().unwrap()["done"], 0);
+    assert!(progress.iter().all(|p| p["step"] == "input_commitments" && p["total"] == 500));
+    assert_eq!(progress.last().unwrap()["done"], 500);
+
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(sse).insert_header(prover.clone())
+        .set_json(json!({"bits": vec![1; 1000]})).to_request();
+    let proved = events(test::call_and_read_body(&app, req).await);
+    assert!(proved.iter().any(|(e, d)| e == "progress" && d["step"] == "private_bit_proofs" && d["done"] == 1000));
+    assert_eq!(proved.last().unwrap(), &("result".to_string(), json!({"data": {"ok": true}})));
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor).set_json(json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/sum", id)).insert_header(prover.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/commit_pedersons", id)).insert_header(sse).insert_header(prover.clone()).to_request();
+    let checked = events(test::call_and_read_body(&app, req).await);
+    assert!(checked.iter().any(|(e, d)| e == "progress" && d["step"] == "private_bit_verification" && d["done"] == 1000));
+    assert_eq!(checked.last().unwrap().1["data"]["valid"], true);
+
+    // Refusals of the step itself arrive as the last event
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(sse).insert_header(prover)
+        .set_json(json!({"bits": vec![1; 2001]})).to_request();
+    let refused = events(test::call_and_read_body(&app, req).await);
+    assert_eq!(refused.len(), 1);
+    assert_eq!(refused[0].0, "error");
+    assert_eq!(refused[0].1["error"]["code"], "noise_too_large");
+}
+
+// Clients submit their own commitments under their own tokens, one per request or as NDJSON, the prover waits for
+// collection to close and the aggregators open the sum of the inputs
+#[actix_web::test]
+pub async fn test_v1_client_inputs() {
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use crate::submissions::InputAggregator;
+    use serde_json::{json, Value};
+
+    // Client side: the submission for the session, the shares for the aggregators
+    fn submit(session_id: &str, client_id: &str, x: bool, aggregators: &mut [InputAggregator]) -> InputSubmission {
+        let (submission, shares) = InputSubmission::new(session_id, client_id, x, aggregators.len());
+        for (aggregator, share) in aggregators.iter_mut().zip(shares) {
+            aggregator.receive(client_id, share);
+        }
+        submission
+    }
+
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"x": [1], "collect": {"max_inputs": 3}})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"collect": {"max_inputs": 3, "aggregators": 1}})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"collect": {"max_inputs": 3, "deadline_secs": 600}})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let bearer = |token: &Value| ("Authorization", format!("Bearer {}", token.as_str().unwrap()));
+    let (prover, auditor) = (bearer(&body["data"]["tokens"]["prover"]), bearer(&body["data"]["tokens"]["auditor"]));
+    let aggregator_tokens: Vec<_> = body["data"]["tokens"]["aggregators"].as_array().unwrap().iter().map(bearer).collect();
+    assert_eq!(aggregator_tokens.len(), 2);
+    let inputs = format!("/v1/sessions/{}/inputs", id);
+
+    // One token per client, no more than the session takes inputs
+    let clients_uri = format!("/v1/sessions/{}/clients", id);
+    let req = test::TestRequest::post().uri(&clients_uri).insert_header(operator).set_json(json!({"count": 4})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "too_many_clients");
+    let req = test::TestRequest::post().uri(&clients_uri).insert_header(operator).set_json(json!({"count": 3})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let issued = body["data"]["clients"].as_array().unwrap();
+    assert_eq!(issued.iter().map(|c| c["client_id"].as_str().unwrap()).collect::<Vec<_>>(), ["client-1", "client-2", "client-3"]);
+    let clients: Vec<_> = issued.iter().map(|c| bearer(&c["token"])).collect();
+
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(prover.clone()).set_json(json!({"bits": [1, 0]})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "inputs_open");
+
+    let mut aggregators = [InputAggregator::new(0), InputAggregator::new(1)];
+    let submission = submit(&id, "client-1", true, &mut aggregators);
+    let req = test::TestRequest::post().uri(&inputs).insert_header(prover.clone()).set_json(&submission).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    let req = test::TestRequest::post().uri(&inputs).insert_header(clients[1].clone()).set_json(&submission).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST); // Another client's token
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "invalid_client_id");
+    let req = test::TestRequest::post().uri(&inputs).insert_header(clients[0].clone()).set_json(&submission).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&inputs).insert_header(clients[0].clone()).set_json(&submission).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "duplicate_client");
+
+    // Relayed for the clients: a malformed line, a valid submission, one under another client's token, a valid
+    // submission that fills the session and one too many without a final newline
+    let mut line = |client_id: &str, token: &(&str, String)| {
+        let mut line = serde_json::to_value(submit(&id, client_id, false, &mut aggregators)).unwrap();
+        line["token"] = json!(token.1.trim_start_matches("Bearer "));
+        serde_json::to_string(&line).unwrap()
+    };
+    let lines = [line("client-2", &clients[1]), line("client-3", &clients[1]), line("client-3", &clients[2]), line("client-3", &clients[2])];
+    let ndjson = format!("{{\"client_id\": 7}}\n{}\n\n{}\n{}\n{}", lines[0], lines[1], lines[2], lines[3]);
+    let req = test::TestRequest::post().uri(&inputs).insert_header(clients[1].clone())
+        .insert_header(("content-type", "application/x-ndjson")).set_payload(ndjson).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["accepted"], 2);
+    let rejected: Vec<_> = body["data"]["rejected"].as_array().unwrap().iter().map(|r| (r["line"].clone(), r["code"].clone())).collect();
+    assert_eq!(rejected, [(json!(0), json!("invalid_request")), (json!(3), json!("invalid_client_id")), (json!(5), json!("inputs_closed"))]);
+    assert_eq!(body["data"]["status"]["inputs"], 3);
+    assert_eq!(body["data"]["status"]["open"], false);
+
+    let req = test::TestRequest::get().uri(&format!("{}/status", inputs)).insert_header(clients[0].clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["max_inputs"], 3);
+    let req = test::TestRequest::get().uri(&format!("/v1/sessions/{}/commitments", id)).insert_header(prover.clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["commitments"].as_array().unwrap().len(), 3);
+
+    // Each aggregator sums its shares over the accepted clients, which it reads from the session
+    let req = test::TestRequest::get().uri(&format!("{}/accepted", inputs)).insert_header(aggregator_tokens[0].clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let accepted: Vec<AcceptedInput> = serde_json::from_value(body["data"]["inputs"].clone()).unwrap();
+    assert_eq!(accepted.iter().map(|input| input.client_id.as_str()).collect::<Vec<_>>(), ["client-1", "client-2", "client-3"]);
+    let sums: Vec<OpeningShare> = aggregators.iter().map(|a| a.aggregate(&accepted).unwrap()).collect();
+    let share = |j: usize, sum: &OpeningShare| test::TestRequest::post().uri(&format!("{}/shares", inputs)).insert_header(aggregator_tokens[j].clone()).set_json(sum).to_request();
+    let resp = test::call_service(&app, share(0, &sums[1])).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "invalid_share");
+    let body: Value = test::call_and_read_body_json(&app, share(0, &sums[0])).await;
+    assert_eq!(body["data"]["shared"], json!([true, false]));
+
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(prover.clone()).set_json(json!({"bits": [1, 0]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor).set_json(json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/sum", id)).insert_header(prover.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "input_shares_missing");
+    assert_eq!(test::call_service(&app, share(1, &sums[1])).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/sum", id)).insert_header(prover.clone()).to_request();
+    let sum = test::call_and_read_body_json::<_, _, Value>(&app, req).await["data"]["final_sum"].as_u64().unwrap();
+    assert!((1..=3).contains(&sum));
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/commit_pedersons", id)).insert_header(prover).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["valid"], true);
+}
+
+// Two noise servers supply the noise through their own tokens, the prover's noise steps are refused
+#[actix_web::test]
+pub async fn test_v1_distributed_noise() {
+    use actix_web::{test, App};
+    use crate::distributed_noise::NoiseServer;
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::{json, Value};
+
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let config = web::Data::new(ApiConfig { operator_token: Some("operator".to_string().into()), ..ApiConfig::default() });
+    let operator = ("Authorization", "Bearer operator");
+    let app = test::init_service(App::new().app_data(sessions).app_data(config).configure(configure)).await;
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"x": [1, 0, 1], "noise_servers": {"count": 1, "bits": 8}})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(operator).set_json(json!({"x": [1, 0, 1], "noise_servers": {"count": 2, "bits": 8}})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let bearer = |token: &Value| ("Authorization", format!("Bearer {}", token.as_str().unwrap()));
+    let prover = bearer(&body["data"]["tokens"]["prover"]);
+    let auditor = bearer(&body["data"]["tokens"]["auditor"]);
+    let tokens: Vec<_> = body["data"]["tokens"]["noise_servers"].as_array().unwrap().iter().map(bearer).collect();
+    assert_eq!(tokens.len(), 2);
+    let uri = |route: &str| format!("/v1/sessions/{}/{}", id, route);
+
+    let req = test::TestRequest::post().uri(&uri("randomness")).insert_header(prover.clone()).set_json(json!({"bits": [1, 0]})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "distributed_noise");
+
+    let servers: Vec<NoiseServer> = (0..2).map(|j| NoiseServer::new(&id, j, &[1, 0, 0, 1, 1, 0, 1, j as u8])).collect();
+    let contribution = servers[0].contribution();
+    let req = test::TestRequest::post().uri(&uri("noise/contributions")).insert_header(prover.clone()).set_json(&contribution).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    // Server 1 cannot hand in proofs made for server 0's bits
+    let req = test::TestRequest::post().uri(&uri("noise/contributions")).insert_header(tokens[1].clone()).set_json(&contribution).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "invalid_proof");
+    for (server, token) in servers.iter().zip(&tokens) {
+        let req = test::TestRequest::post().uri(&uri("noise/contributions")).insert_header(token.clone()).set_json(server.contribution()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    let req = test::TestRequest::get().uri(&uri("noise")).insert_header(tokens[0].clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["contributed"], json!([true, true]));
+    assert_eq!(body["data"]["public_bits"], json!([]));
+    // The servers cannot send the seed of their own coins
+    let req = test::TestRequest::post().uri(&uri("coin_seed")).insert_header(tokens[0].clone()).set_json(json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    let req = test::TestRequest::post().uri(&uri("coin_seed")).insert_header(auditor).set_json(json!({"seed": vec![7; 32]})).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri(&uri("noise")).insert_header(tokens[0].clone()).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let coins: Vec<u8> = serde_json::from_value(body["data"]["public_bits"].clone()).unwrap();
+    assert_eq!(coins.len(), 16);
+    let req = test::TestRequest::post().uri(&uri("sum")).insert_header(prover.clone()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(test::read_body_json::<Value, _>(resp).await["error"]["code"], "shares_missing");
+    for (j, (server, token)) in servers.iter().zip(&tokens).enumerate() {
+        let req = test::TestRequest::post().uri(&uri("noise/shares")).insert_header(token.clone()).set_json(server.share(&coins[j * 8..(j + 1) * 8])).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    let req = test::TestRequest::post().uri(&uri("sum")).insert_header(prover.clone()).to_request();
+    let sum = test::call_and_read_body_json::<_, _, Value>(&app, req).await["data"]["final_sum"].as_u64().unwrap();
+    assert!((2..=18).contains(&sum));
+    let req = test::TestRequest::post().uri(&uri("commit_pedersons")).insert_header(prover).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["valid"], true);
+}
@@ -0,0 +1,65 @@
+use openssl::bn::BigNum;
+use crate::algebra::{BigNumField, Field};
+use crate::generic_commitments::{AdditiveSharing, SecretSharing};
+
+// Multiplication triples (a, b, c = ab mod q), additively shared among the servers. With the shares of one triple
+// the servers multiply two shared values while publishing only d = x - a and e = y - b, see prio::check_vote and poplar::Server.
+pub struct BeaverTriple {
+    pub a_shares: Vec<BigNum>,
+    pub b_shares: Vec<BigNum>,
+    pub c_shares: Vec<BigNum>,
+}
+
+impl BeaverTriple {
+
+    // Dealer-generated: a party trusted by the servers samples the triple and hands each server its shares.
+    // The servers must not learn a, b or c, so a fresh triple is needed for every multiplication.
+    pub fn new(num_shares: usize, q: &BigNum) -> BeaverTriple {
+        let field = BigNumField::new(q);
+        let (a, b) = (field.random(), field.random());
+        Self::from_values(&a, &b, &field.mul(&a, &b), num_shares, q)
+    }
+
+    // Client-generated: the client samples the triple along with its vote and keeps (a, b, c) to derive the
+    // correlated values of its input. Poplar's sketch only uses the triple to mask the servers' messages, so it
+    // stays sound if the client picks the triple badly.
+    pub fn client_generated(num_shares: usize, q: &BigNum) -> (BeaverTriple, [BigNum; 3]) {
+        let field = BigNumField::new(q);
+        let (a, b) = (field.random(), field.random());
+        let c = field.mul(&a, &b);
+        let triple = Self::from_values(&a, &b, &c, num_shares, q);
+        (triple, [a, b, c])
+    }
+
+    pub fn from_values(a: &BigNum, b: &BigNum, c: &BigNum, num_shares: usize, q: &BigNum) -> BeaverTriple {
+        let sharing = AdditiveSharing{ field: BigNumField::new(q) };
+        Self {
+            a_shares: sharing.share(a, num_shares),
+            b_shares: sharing.share(b, num_shares),
+            c_shares: sharing.share(c, num_shares),
+        }
+    }
+}
+
+// Sum of additive shares mod q
+pub fn reconstruct(shares: &[BigNum], q: &BigNum) -> BigNum {
+    AdditiveSharing{ field: BigNumField::new(q) }.reconstruct(shares)
+}
+
+
+#[test]
+pub fn test_triples_reconstruct() {
+    use crate::public_parameters::PublicParams;
+
+    let public_param = PublicParams::new(128, 3).unwrap();
+    let q = &public_param.q;
+    let triple = BeaverTriple::new(3, q);
+    let (a, b, c) = (reconstruct(&triple.a_shares, q), reconstruct(&triple.b_shares, q), reconstruct(&triple.c_shares, q));
+    assert_eq!(BigNumField::new(q).mul(&a, &b), c);
+
+    let (triple, [a, b, c]) = BeaverTriple::client_generated(3, q);
+    assert_eq!(triple.a_shares.len(), 3);
+    assert_eq!(reconstruct(&triple.a_shares, q), a);
+    assert_eq!(reconstruct(&triple.b_shares, q), b);
+    assert_eq!(reconstruct(&triple.c_shares, q), c);
+}
@@ -1,62 +1,175 @@
-use curve25519_dalek::{constants, ristretto::RistrettoPoint, scalar::Scalar};
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
 use crate::participants;
 use crate::generic_commitments::Commitment;
-use coinflip::flip;
 use num_bigint::BigUint;
 use rayon::prelude::*;
-use rand_core::OsRng;
+use rand_core::RngCore;
+use uuid::Builder;
+use crate::sigma_ff::ProofScalar;
+use crate::transcript::{Transcript, PUBLIC_COIN_LABEL, VECTOR_NOISE_LABEL};
+use crate::vector_commitments::{BitVectorProof, VectorCommitment};
 #[cfg(test)]
 use crate::rand::Rng;
-
-// Helper function to unzip after parallel iters
-trait Unzip4<A, B, C, D> {
-    fn unzip_n(self) -> (Vec<A>, Vec<B>, Vec<C>, Vec<D>);
-}
-
-impl<I, A, B, C, D> Unzip4<A, B, C, D> for I
-where
-    I: Iterator<Item = (A, B, C, D)>,
-{
-    fn unzip_n(self) -> (Vec<A>, Vec<B>, Vec<C>, Vec<D>) {
-        let mut a = Vec::new();
-        let mut b = Vec::new();
-        let mut c = Vec::new();
-        let mut d = Vec::new();
-        for (x, y, z, w) in self {
-            a.push(x);
-            b.push(y);
-            c.push(z);
-            d.push(w);
-        }
-        (a, b, c, d)
-    }
-}
-
+#[cfg(test)]
+use crate::submissions::InputAggregator;
+use crate::curve_parameters::CurveParams;
+use crate::metrics::METRICS;
+use crate::progress::{ProgressHook, StepProgress, INPUT_COMMITMENTS, PRIVATE_BIT_PROOFS, PRIVATE_BIT_VERIFICATION};
+use crate::sessions::now_secs;
+use crate::distributed_noise::{noise_bit_transcript, NoiseContribution, NoiseError, NoiseShare};
+use subtle::ConstantTimeEq;
+use crate::rng::{index, random_scalar, ProtocolRng};
+use crate::secrets::{bit_to_scalar, scalar_from_choice, scalar_to_bit, xor_bit, xor_randomness, Secret};
+use crate::submissions::{input_transcript, AcceptedInput, InputSubmission, OpeningShare, SubmissionError, MAX_CLIENT_ID_LEN};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{debug, info_span, Span};
+
+// The client and the verifier only hold the public generators, so they are rebuilt from CurveParams when a saved
+// runner is resumed. The server is saved, it also holds the openings of its coins.
+#[derive(Serialize, Deserialize)]
 pub struct BinomialRunner {
+    session_id: String,
+    #[serde(skip, default = "default_client")]
     client: participants::Client,
     input_commitments: Vec<RistrettoPoint>,
     coms_sum: RistrettoPoint,
-    x_sum: Scalar,
-    r_sum: Scalar,
+    x_sum: Secret<Scalar>,
+    r_sum: Secret<Scalar>,
+    #[serde(default = "default_server")] // Sessions saved before the server was
     server: participants::Server,
+    #[serde(skip, default = "default_verifier")]
     verifier: participants::Board,
-    private_bits: Vec<Scalar>,
+    private_bits: Secret<Vec<Scalar>>,
+    private_randomness: Secret<Vec<Scalar>>,
     private_commits: Vec<RistrettoPoint>,
+    private_proofs: Vec<ProofScalar>,
+    coin_seed: [u8; 32],
     public_bits: Vec<Scalar>,
-    xor_bits: Vec<Scalar>,
+    xor_bits: Secret<Vec<Scalar>>,
     xor_commits: Vec<RistrettoPoint>,
     final_x: Scalar,
-    final_z: Scalar,
+    final_z: Secret<Scalar>,
     lhs: RistrettoPoint,
     rhs: RistrettoPoint,
     result_output: Scalar,
     var_p_in_count: i32,
     var_p_n: i32,
-    var_p_bits: Vec<Vec<Scalar>>,
+    var_p_bits: Secret<Vec<Vec<Scalar>>>,
     var_p_coms: Vec<Vec<RistrettoPoint>>,
-    var_p_randomness: Vec<Vec<Scalar>>,
-    var_p_randomness_final: Vec<Scalar>,
+    var_p_randomness: Secret<Vec<Vec<Scalar>>>,
+    var_p_randomness_final: Secret<Vec<Scalar>>,
     var_p: bool,
+    vector_noise: bool,
+    noise_vector_com: RistrettoPoint,
+    noise_sum_com: RistrettoPoint,
+    noise_proof: Option<BitVectorProof>,
+    noise_randomness: Secret<Scalar>,
+    #[serde(default = "default_dataset")]
+    dataset: String,
+    #[serde(default)]
+    released: bool, // compute_sum ran on the current noise
+    #[serde(skip)]
+    progress: Option<ProgressHook>,
+    #[serde(default)]
+    client_inputs: bool, // The inputs are submitted by the clients, see submissions.rs
+    #[serde(default)]
+    inputs_closed: bool, // Set once the noise steps start
+    #[serde(default)]
+    max_inputs: usize,
+    #[serde(default)]
+    input_deadline: Option<u64>, // Unix seconds
+    #[serde(default)]
+    input_client_ids: Vec<String>, // Parallel to input_commitments
+    #[serde(default)]
+    input_proofs: Vec<ProofScalar>,
+    #[serde(skip)]
+    client_id_set: HashSet<String>, // Rebuilt from input_client_ids when a resumed session takes inputs again
+    #[serde(default)]
+    aggregators: usize, // The openings of the client inputs are shared between this many aggregators
+    #[serde(default)]
+    input_share_coms: Vec<Vec<RistrettoPoint>>, // Parallel to input_commitments, one per aggregator
+    #[serde(default)]
+    opening_shares: Vec<Option<OpeningShare>>,
+    #[serde(default)]
+    noise_servers: usize, // The noise comes from this many servers instead of the prover, see distributed_noise.rs
+    #[serde(default)]
+    noise_server_bits: usize, // n_b of each server
+    #[serde(default)]
+    noise_contributions: Vec<Option<Vec<ProofScalar>>>,
+    #[serde(default)]
+    noise_shares: Vec<Option<NoiseShare>>,
+    #[serde(default)]
+    folded_input_coms: Vec<RistrettoPoint>, // Per noise server, its share of the input, opened together with its noise
+    #[serde(skip)]
+    rng: ProtocolRng, // A resumed session draws from OsRng even if it was seeded
+    #[serde(default)]
+    seeded: bool, // Kept apart from rng so a resumed seeded run is still never released
+}
+
+// delta at which the epsilon of a released result is reported
+pub const RELEASE_DELTA: f64 = 1e-6;
+
+// Where a run is in the protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    CollectingInputs, // Clients are still submitting their inputs
+    InputsCommitted,
+    AwaitingCoins, // The noise bits are committed, the verifier's seed for the public coins is not in yet
+    NoiseCommitted, // The noise bits are in and XORed with the public coins, or selected for variable p
+    Released, // compute_sum gave out the noisy result
+    Checked, // commit_pedersons computed lhs and rhs
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [Phase::CollectingInputs, Phase::InputsCommitted, Phase::AwaitingCoins, Phase::NoiseCommitted, Phase::Released, Phase::Checked];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::CollectingInputs => "collecting_inputs",
+            Phase::InputsCommitted => "inputs_committed",
+            Phase::AwaitingCoins => "awaiting_coins",
+            Phase::NoiseCommitted => "noise_committed",
+            Phase::Released => "released",
+            Phase::Checked => "checked",
+        }
+    }
+}
+
+// Dataset of a session that names none
+pub const DEFAULT_DATASET: &str = "default";
+
+fn default_dataset() -> String {
+    DEFAULT_DATASET.to_string()
+}
+
+pub(crate) fn default_client() -> participants::Client {
+    let params = CurveParams::new();
+    participants::Client::new(params.g, params.h)
+}
+
+fn default_server() -> participants::Server {
+    let params = CurveParams::new();
+    participants::Server::new(params.g, params.h)
+}
+
+pub(crate) fn default_verifier() -> participants::Board {
+    let params = CurveParams::new();
+    participants::Board::new(params.g, params.h)
+}
+
+// n public coins from a transcript that already holds the verifier's seed and the commitments the coins apply to
+pub(crate) fn derive_coins(transcript: &mut Transcript, n: usize) -> Vec<Scalar> {
+    let mut coins = Vec::with_capacity(n);
+    while coins.len() < n {
+        let e = transcript.challenge_scalar(b"coins");
+        // The low 248 bits of a wide reduction are uniform up to a negligible bias
+        for byte in &e.as_bytes()[..31] {
+            coins.extend((0..8).map(|k| if (byte >> k) & 1 == 1 { Scalar::one() } else { Scalar::zero() }));
+        }
+    }
+    coins.truncate(n);
+    coins
 }
 
 impl BinomialRunner {
@@ -64,43 +177,52 @@ impl BinomialRunner {
     // <===== Step 1 =====>
     // Initialization function. Takes in number of bits, and raw x_i bits. Chooses h and j arbitrarily. 
     pub fn new(x: &[u8]) -> BinomialRunner {
-        let h: RistrettoPoint = RistrettoPoint::from_uniform_bytes(b"this is another secret that should never be disclosed to anyone ");
-        let g: RistrettoPoint = constants::RISTRETTO_BASEPOINT_POINT;
-        let x_new: Vec<Scalar> = x.iter().map(
-            |x| match *x{
-                1 => {
-                    Scalar::one()
-                },
-                _ => {
-                    Scalar::zero()
-                }
-            }
-        ).collect();
-        let r: Vec<Scalar> = vec![0; x_new.len()].iter().map(
-            |_| {
-                let mut csprng = OsRng;
-                Scalar::random(&mut csprng)
-            }
-        ).collect();
-        let client = participants::Client::new(2, g, h);
+        Self::new_with_progress(x, None)
+    }
+
+    // Same as new, reporting the input commitments to progress as they are computed
+    pub fn new_with_progress(x: &[u8], progress: Option<&ProgressHook>) -> BinomialRunner {
+        Self::new_with_rng(x, progress, ProtocolRng::default())
+    }
+
+    // Same as new_with_progress, with every random value of the run drawn from rng: the session id, the
+    // randomness of the commitments, the proofs, the verifier's coin seed and the variable p choices.
+    // ProtocolRng::seeded replays a run exactly, for tests and known-answer vectors only: compute_sum refuses to
+    // release such a run, replay_sum gives its result.
+    pub fn new_with_rng(x: &[u8], progress: Option<&ProgressHook>, mut rng: ProtocolRng) -> BinomialRunner {
+        let mut id_bytes = [0; 16];
+        rng.fill_bytes(&mut id_bytes);
+        let session_id = Builder::from_random_bytes(id_bytes).into_uuid().to_string();
+        let _span = info_span!("step", step = "new", session_id = %session_id, inputs = x.len()).entered();
+        let params = CurveParams::new();
+        let (g, h) = (params.g, params.h);
+        let x_new: Secret<Vec<Scalar>> = Secret::new(x.iter().map(|x| bit_to_scalar(*x)).collect());
+        let r: Secret<Vec<Scalar>> = Secret::new(vec![0; x_new.len()].iter().map(
+            |_| random_scalar(&mut rng)
+        ).collect());
+        let client = participants::Client::new(g, h);
+        let step = StepProgress::new(progress, INPUT_COMMITMENTS, x_new.len());
         let input_coms: Vec<RistrettoPoint> = x_new.par_iter()
                                                         .zip(r.par_iter())
-                                                        .map(|(&x_i, &r_i)| 
-                                                                        client.com.commit(x_i, r_i)
-                                                            ).collect();
-
-        let x_sum: Scalar = x_new.iter().sum();
-        let r_sum: Scalar = r.iter().sum();
+                                                        .map(|(&x_i, &r_i)| {
+                                                                        let com = client.com.commit(x_i, r_i);
+                                                                        step.tick();
+                                                                        com
+                                                            }).collect();
+
+        let x_sum = Secret::new(x_new.iter().sum::<Scalar>());
+        let r_sum = Secret::new(r.iter().sum::<Scalar>());
         let coms_sum: RistrettoPoint = input_coms.iter().sum();
 
-        let lhs = client.com.commit(x_sum, r_sum);
+        let lhs = client.com.commit(*x_sum, *r_sum);
         let rhs = coms_sum;
         assert_eq!(lhs, rhs);
 
-        let server = participants::Server::new(2, g, h);
+        let server = participants::Server::new(g, h);
         let verifier = participants::Board::new(g, h);
 
         BinomialRunner {
+            session_id,
             client,
             input_commitments: input_coms,
             coms_sum,
@@ -108,24 +230,383 @@ impl BinomialRunner {
             r_sum,
             server,
             verifier,
-            private_bits: Vec::new(),
+            private_bits: Secret::default(),
+            private_randomness: Secret::default(),
             private_commits: Vec::new(),
+            private_proofs: Vec::new(),
+            coin_seed: [0; 32],
             public_bits: Vec::new(),
-            xor_bits: Vec::new(),
+            xor_bits: Secret::default(),
             xor_commits: Vec::new(),
             final_x: Scalar::zero(),
-            final_z: Scalar::zero(),
+            final_z: Secret::default(),
             lhs: RistrettoPoint::default(),
             rhs: RistrettoPoint::default(),
             result_output: Scalar::zero(),
             var_p_in_count: 0,
             var_p_n: 0,
-            var_p_bits: Vec::new(),
+            var_p_bits: Secret::default(),
             var_p_coms: Vec::new(),
-            var_p_randomness: Vec::new(),
-            var_p_randomness_final: Vec::new(),
+            var_p_randomness: Secret::default(),
+            var_p_randomness_final: Secret::default(),
             var_p: false,
+            vector_noise: false,
+            noise_vector_com: RistrettoPoint::default(),
+            noise_sum_com: RistrettoPoint::default(),
+            noise_proof: None,
+            noise_randomness: Secret::default(),
+            dataset: default_dataset(),
+            released: false,
+            progress: None,
+            client_inputs: false,
+            inputs_closed: false,
+            max_inputs: 0,
+            input_deadline: None,
+            input_client_ids: Vec::new(),
+            input_proofs: Vec::new(),
+            client_id_set: HashSet::new(),
+            aggregators: 0,
+            input_share_coms: Vec::new(),
+            opening_shares: Vec::new(),
+            noise_servers: 0,
+            noise_server_bits: 0,
+            noise_contributions: Vec::new(),
+            noise_shares: Vec::new(),
+            folded_input_coms: Vec::new(),
+            seeded: rng.is_seeded(),
+            rng,
+        }
+    }
+
+    // <===== Alternate Step 1 =====>
+    // Starts without inputs and takes them from the clients through submit_inputs. Collection closes once
+    // max_inputs submissions are accepted or at the deadline (Unix seconds), whichever comes first. The openings
+    // come in afterwards as one share from each of the aggregators, through add_input_share.
+    pub fn collecting(max_inputs: usize, deadline: Option<u64>, aggregators: usize) -> BinomialRunner {
+        Self::collecting_with_rng(max_inputs, deadline, aggregators, ProtocolRng::default())
+    }
+
+    pub fn collecting_with_rng(max_inputs: usize, deadline: Option<u64>, aggregators: usize, rng: ProtocolRng) -> BinomialRunner {
+        let mut runner = Self::new_with_rng(&[], None, rng);
+        runner.client_inputs = true;
+        runner.max_inputs = max_inputs;
+        runner.input_deadline = deadline;
+        runner.aggregators = aggregators;
+        runner.opening_shares = vec![None; aggregators];
+        runner
+    }
+
+    // <===== Alternate Step 1 =====>
+    // Starts from an aggregate that count noise servers hold in additive shares, server j having committed to its
+    // share with share_coms[j], such as the count of a prefix in heavy_hitters.rs. Each server adds its share of the
+    // aggregate to its noise share, so the aggregate is only ever opened with the noise of every server in it.
+    pub fn shared_aggregate(share_coms: &[RistrettoPoint], bits: usize) -> BinomialRunner {
+        let mut runner = Self::new(&[]);
+        runner.coms_sum = share_coms.iter().sum();
+        runner.folded_input_coms = share_coms.to_vec();
+        runner.set_noise_servers(share_coms.len(), bits);
+        runner
+    }
+
+    // Whether client submissions are still taken
+    pub fn inputs_open(&self) -> bool {
+        self.client_inputs
+            && !self.inputs_closed
+            && self.num_inputs() < self.max_inputs
+            && self.input_deadline.is_none_or(|deadline| now_secs() < deadline)
+    }
+
+    // Inputs the session holds or may still take, which is what the session limits count
+    pub fn input_capacity(&self) -> usize {
+        if self.inputs_open() { self.max_inputs } else { self.num_inputs() }
+    }
+
+    pub fn get_input_deadline(&self) -> Option<u64> {
+        self.input_deadline
+    }
+
+    pub fn get_max_inputs(&self) -> usize {
+        self.max_inputs
+    }
+
+    // Checks the submissions in parallel, then adds the valid ones to the aggregate in order until collection
+    // closes. Returns one result per submission.
+    pub fn submit_inputs(&mut self, submissions: &[InputSubmission]) -> Vec<Result<(), SubmissionError>> {
+        let _span = self.step_span("submit_inputs").entered();
+        if !self.inputs_open() {
+            return vec![Err(SubmissionError::InputsClosed); submissions.len()];
+        }
+        let checked: Vec<Result<(), SubmissionError>> = submissions.par_iter().map(|s| self.check_submission(s)).collect();
+        if self.client_id_set.len() != self.input_client_ids.len() {
+            self.client_id_set = self.input_client_ids.iter().cloned().collect();
+        }
+
+        let mut results = Vec::with_capacity(submissions.len());
+        for (submission, check) in submissions.iter().zip(checked) {
+            results.push(check.and_then(|_| self.accept_submission(submission)));
+        }
+        let accepted = results.iter().filter(|r| r.is_ok()).count();
+        debug!(accepted, rejected = results.len() - accepted, inputs = self.num_inputs(), "Took client submissions");
+        results
+    }
+
+    // Everything about a submission that does not depend on the ones before it
+    fn check_submission(&self, submission: &InputSubmission) -> Result<(), SubmissionError> {
+        if submission.client_id.is_empty() || submission.client_id.len() > MAX_CLIENT_ID_LEN {
+            return Err(SubmissionError::InvalidClientId);
+        }
+        if submission.share_coms.len() != self.aggregators || submission.share_coms.iter().sum::<RistrettoPoint>() != submission.proof.com {
+            METRICS.check("client_opening", false);
+            return Err(SubmissionError::InvalidOpening);
+        }
+        let valid = self.verifier.verify(&submission.proof, &mut input_transcript(&self.session_id, &submission.client_id));
+        METRICS.proofs_verified("client_input", 1);
+        if !METRICS.check("client_input", valid) {
+            return Err(SubmissionError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    fn accept_submission(&mut self, submission: &InputSubmission) -> Result<(), SubmissionError> {
+        if !self.inputs_open() {
+            return Err(SubmissionError::InputsClosed);
+        }
+        if !self.client_id_set.insert(submission.client_id.clone()) {
+            return Err(SubmissionError::DuplicateClient);
+        }
+        self.input_commitments.push(submission.proof.com);
+        self.input_client_ids.push(submission.client_id.clone());
+        self.input_proofs.push(submission.proof.clone());
+        self.input_share_coms.push(submission.share_coms.clone());
+        self.coms_sum += submission.proof.com;
+        Ok(())
+    }
+
+    // Accepted clients with their share commitments, which the aggregators sum their shares over
+    pub fn accepted_inputs(&self) -> Vec<AcceptedInput> {
+        self.input_client_ids.iter().zip(self.input_share_coms.iter())
+            .map(|(client_id, share_coms)| AcceptedInput { client_id: client_id.clone(), share_coms: share_coms.clone() })
+            .collect()
+    }
+
+    pub fn get_aggregators(&self) -> usize {
+        self.aggregators
+    }
+
+    // Aggregator index's sum of the clients' shares, checked against the sum of the share commitments at index.
+    // Once every aggregator has sent its share, their sum opens the sum of the inputs.
+    pub fn add_input_share(&mut self, index: usize, share: OpeningShare) -> Result<(), SubmissionError> {
+        if index >= self.aggregators {
+            return Err(SubmissionError::NotAggregated);
+        }
+        if self.inputs_open() {
+            return Err(SubmissionError::InputsOpen);
+        }
+        if self.opening_shares[index].is_some() {
+            return Err(SubmissionError::AlreadyShared);
+        }
+        let expected: RistrettoPoint = self.input_share_coms.iter().map(|share_coms| share_coms[index]).sum();
+        if !METRICS.check("input_share", self.client.com.open(share.value, share.randomness, expected)) {
+            return Err(SubmissionError::InvalidShare);
+        }
+        self.opening_shares[index] = Some(share);
+        if self.opening_shares.iter().all(Option::is_some) {
+            let (x_sum, r_sum) = self.opening_shares.iter().flatten()
+                .fold((Scalar::zero(), Scalar::zero()), |(x, r), share| (x + share.value, r + share.randomness));
+            self.x_sum = Secret::new(x_sum);
+            self.r_sum = Secret::new(r_sum);
+        }
+        Ok(())
+    }
+
+    // Which aggregators have sent their share, in aggregator order
+    pub fn input_shares_progress(&self) -> Vec<bool> {
+        self.opening_shares.iter().map(Option::is_some).collect()
+    }
+
+    // Whether the opening of the client inputs is in, always for inputs the prover holds
+    pub fn input_shares_complete(&self) -> bool {
+        !self.client_inputs || self.opening_shares.iter().all(Option::is_some)
+    }
+
+    // Auditor side check that every input commitment carries a valid OR-proof from its client
+    pub fn verify_input_proofs(&self) -> bool {
+        METRICS.proofs_verified("client_input", self.input_proofs.len());
+        METRICS.check("client_input", self.input_proofs_valid())
+    }
+
+    fn input_proofs_valid(&self) -> bool {
+        let valid = self.input_proofs.len() == self.input_commitments.len()
+            && self.input_proofs.par_iter().zip(self.input_commitments.par_iter()).zip(self.input_client_ids.par_iter())
+                .all(|((proof, com), client_id)| proof.com == *com && self.verifier.verify(proof, &mut input_transcript(&self.session_id, client_id)))
+            && self.input_share_coms.iter().zip(self.input_commitments.iter())
+                .all(|(share_coms, com)| share_coms.iter().sum::<RistrettoPoint>() == *com);
+        debug!(proofs = self.input_proofs.len(), valid, "Verified the OR-proofs of the client inputs");
+        valid
+    }
+
+    // <===== Distributed Step 4 =====>
+    // Takes the noise from count servers with bits private bits each instead of from the prover
+    pub fn set_noise_servers(&mut self, count: usize, bits: usize) {
+        self.noise_servers = count;
+        self.noise_server_bits = bits;
+        self.noise_contributions = vec![None; count];
+        self.noise_shares = vec![None; count];
+    }
+
+    pub fn get_noise_servers(&self) -> usize {
+        self.noise_servers
+    }
+
+    pub fn get_noise_server_bits(&self) -> usize {
+        self.noise_server_bits
+    }
+
+    // Which servers committed to their bits and which sent their share
+    pub fn noise_progress(&self) -> (Vec<bool>, Vec<bool>) {
+        (self.noise_contributions.iter().map(Option::is_some).collect(), self.noise_shares.iter().map(Option::is_some).collect())
+    }
+
+    // Checks the OR-proofs of one server's bits. The public coins follow from the auditor's seed, which is only
+    // taken once the last server is in, so none of them can pick its bits knowing the coins.
+    pub fn add_noise_contribution(&mut self, server: usize, contribution: &NoiseContribution) -> Result<(), NoiseError> {
+        let _span = self.step_span("add_noise_contribution").entered();
+        if server >= self.noise_servers {
+            return Err(NoiseError::NotDistributed);
+        }
+        if self.noise_contributions[server].is_some() {
+            return Err(NoiseError::AlreadyContributed);
+        }
+        let (bits, proofs) = (self.noise_server_bits, &contribution.proofs);
+        if proofs.len() != bits {
+            return Err(NoiseError::WrongLength { len: proofs.len(), expected: bits });
+        }
+        self.inputs_closed = true;
+        let valid = proofs.par_iter().enumerate()
+            .all(|(k, proof)| self.verifier.verify(proof, &mut noise_bit_transcript(&self.session_id, server * bits + k)));
+        METRICS.proofs_verified("or_proof", proofs.len());
+        if !METRICS.check("or_proof", valid) {
+            return Err(NoiseError::InvalidProof);
+        }
+        self.noise_contributions[server] = Some(proofs.clone());
+        debug!(server, bits, "Took the noise commitments of a server");
+
+        if self.noise_contributions.iter().all(Option::is_some) {
+            self.private_proofs = self.noise_contributions.iter().flatten().flatten().cloned().collect();
+            self.private_commits = self.private_proofs.iter().map(|p| p.com).collect();
+            self.released = false;
+        }
+        Ok(())
+    }
+
+    // Takes one server's share of the final opening once it is checked against that server's XOR commitments
+    pub fn add_noise_share(&mut self, server: usize, share: NoiseShare) -> Result<(), NoiseError> {
+        if server >= self.noise_servers {
+            return Err(NoiseError::NotDistributed);
+        }
+        if self.awaiting_coins() {
+            return Err(NoiseError::CoinsMissing);
+        }
+        if self.xor_commits.len() != self.noise_servers * self.noise_server_bits {
+            return Err(NoiseError::ContributionsOpen);
         }
+        if self.noise_shares[server].is_some() {
+            return Err(NoiseError::AlreadyShared);
+        }
+        let bits = self.noise_server_bits;
+        let folded = self.folded_input_coms.get(server).copied().unwrap_or_default();
+        let expected: RistrettoPoint = folded + self.xor_commits[server * bits..(server + 1) * bits].iter().sum::<RistrettoPoint>();
+        if !METRICS.check("noise_share", self.server.com.open(share.value, share.randomness, expected)) {
+            return Err(NoiseError::InvalidShare);
+        }
+        self.noise_shares[server] = Some(share);
+        Ok(())
+    }
+
+    // Whether compute_sum can run on the servers' shares
+    pub fn noise_shares_complete(&self) -> Result<(), NoiseError> {
+        if self.noise_servers == 0 {
+            return Err(NoiseError::NotDistributed);
+        }
+        if !self.noise_shares.iter().all(Option::is_some) {
+            return Err(NoiseError::SharesMissing);
+        }
+        Ok(())
+    }
+
+    // Session the runner's proofs are bound to. Doubles as the session key of the server.
+    pub fn get_session_id(&self) -> String {
+        self.session_id.clone()
+    }
+
+    // Number of client inputs x_i
+    pub fn num_inputs(&self) -> usize {
+        self.input_commitments.len()
+    }
+
+    // Number of noise bits n_b taken in so far, for either mechanism. With noise servers, all the bits they will
+    // commit to, so the session's share of the noise limits is held from the start.
+    pub fn num_noise_bits(&self) -> usize {
+        if self.var_p {
+            self.var_p_n.max(0) as usize
+        } else if self.noise_servers > 0 {
+            self.noise_servers * self.noise_server_bits
+        } else {
+            self.public_bits.len()
+        }
+    }
+
+    // Dataset the privacy budget of this run is counted against
+    pub fn set_dataset(&mut self, dataset: &str) {
+        self.dataset = dataset.to_string();
+    }
+
+    // True once compute_sum released the sum of the current noise
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+
+    pub fn phase(&self) -> Phase {
+        if self.lhs != RistrettoPoint::default() {
+            Phase::Checked
+        } else if self.released {
+            Phase::Released
+        } else if !self.xor_bits.is_empty() || !self.xor_commits.is_empty() {
+            Phase::NoiseCommitted
+        } else if self.awaiting_coins() {
+            Phase::AwaitingCoins
+        } else if self.inputs_open() {
+            Phase::CollectingInputs
+        } else {
+            Phase::InputsCommitted
+        }
+    }
+
+    // epsilon of the released sum at the given delta. By the bound of Dwork et al. for binomial noise (Our Data,
+    // Ourselves, 2006), n_b fair coins make a count (epsilon, delta)-DP for n_b >= 64 ln(2 / delta) / epsilon^2.
+    // None before any noise is in and for the variable p mechanism, whose coins are not fair.
+    pub fn epsilon(&self, delta: f64) -> Option<f64> {
+        // With noise servers, against any coalition that misses one honest server
+        let n_b = if self.noise_servers > 0 { self.public_bits.len().min(self.noise_server_bits) } else { self.public_bits.len() };
+        if self.var_p || n_b == 0 {
+            return None;
+        }
+        Some(8.0 * ((2.0 / delta).ln() / n_b as f64).sqrt())
+    }
+
+    // Hook the following steps report their progress to, until it is replaced or removed
+    pub fn set_progress_hook(&mut self, progress: Option<ProgressHook>) {
+        self.progress = progress;
+    }
+
+    // Span of one protocol step. The session ID tells concurrent sessions apart in the logs.
+    fn step_span(&self, name: &'static str) -> Span {
+        info_span!("step", step = name, session_id = %self.session_id)
+    }
+
+    // Fiat-Shamir transcript for the OR-proof of the i-th private bit
+    fn bit_transcript(&self, i: usize) -> Transcript {
+        noise_bit_transcript(&self.session_id, i)
     }
 
     // <===== Step 2 =====> 
@@ -140,51 +621,228 @@ impl BinomialRunner {
     // No Rust side involvement
 
     // <===== Step 4 =====>
-    // Take in random private bits from JS, with cheating, and save them
+    // Takes the private bits, commits to each one with an OR-proof that it is a bit, then draws the verifier's seed
+    // and XORs in the public coins. For runs where one process plays every party: the examples, the tests and the
+    // legacy routes. The v1 API stops after commit_randomness and takes the seed from the auditor.
     pub fn input_randomness(&mut self, bits: &[u8]) {
-        let private_bits_new: Vec<Scalar> = bits.iter().map(
-            |x| match *x {
-                1 => {
-                    Scalar::one()
-                },
-                _ => {
-                    Scalar::zero()
-                }
-            }
-        ).collect();
-
-        self.private_bits = private_bits_new;
+        self.commit_randomness(bits);
+        let seed = self.draw_verifier_seed();
+        // Only fails without commitments, which commit_randomness just made unless bits is empty
+        let _ = self.take_coin_seed(seed);
+    }
 
-        let results: Vec<(
-            Scalar,          
-            Scalar, 
-            RistrettoPoint,
-            RistrettoPoint
-        )> = self.private_bits
+    // Takes the private bits and commits to each one with an OR-proof that it is a bit. The public coins wait for
+    // the verifier's seed, see take_coin_seed.
+    pub fn commit_randomness(&mut self, bits: &[u8]) {
+        let _span = self.step_span("commit_randomness").entered();
+        self.inputs_closed = true;
+        self.private_bits = Secret::new(bits.iter().map(|b| bit_to_scalar(*b)).collect());
+        let rng = &mut self.rng;
+        self.private_randomness = Secret::new(self.private_bits.iter().map(|_| random_scalar(rng)).collect());
+        let streams = self.rng.streams();
+
+        let step = StepProgress::new(self.progress.as_ref(), PRIVATE_BIT_PROOFS, self.private_bits.len());
+        self.private_proofs = self.private_bits
             .par_iter()
-            .map(|bit| {
-                let transcript = self.server.com.create_proof_0(*bit);
-                _ = self.verifier.verify(&transcript);
-                let b = flip();
-                if b {
-                    let public_flip = Scalar::one();
-                    let xor_flip = Scalar::one() - bit;
-                    let com_one = self.server.com.commit(Scalar::one(), Scalar::one());
-                    let bit_com = &com_one - &transcript.com;
-                    (public_flip, xor_flip, bit_com, transcript.com)
-                } else {
-                    let public_flip = Scalar::zero();
-                    let xor_flip = *bit;
-                    (public_flip, xor_flip, transcript.com, transcript.com)
-                }
+            .zip(self.private_randomness.par_iter())
+            .enumerate()
+            .map(|(i, (bit, r))| {
+                let mut rng = streams.get(i);
+                let proof = self.server.com.create_bit_proof(bit.ct_eq(&Scalar::one()), *r, &mut self.bit_transcript(i), &mut rng);
+                step.tick();
+                proof
             })
             .collect();
-    
-        let (public_flips, xor_flips, bit_coms, private_commits): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = results.into_iter().unzip_n();
-        self.public_bits = public_flips;
-        self.xor_bits = xor_flips;
-        self.xor_commits = bit_coms;
-        self.private_commits = private_commits;
+        METRICS.proofs_generated("or_proof", self.private_proofs.len());
+        debug!(proofs = self.private_proofs.len(), "Created OR-proofs for the private bits");
+        self.private_commits = self.private_proofs.iter().map(|p| p.com).collect();
+        self.released = false;
+        self.coin_seed = [0; 32];
+        self.public_bits = Vec::new();
+        self.xor_bits = Secret::default();
+        self.xor_commits = Vec::new();
+    }
+
+    // Whether the noise bits are committed and the public coins still wait for the verifier's seed
+    pub fn awaiting_coins(&self) -> bool {
+        !self.var_p && !self.vector_noise && !self.private_commits.is_empty() && self.public_bits.is_empty()
+    }
+
+    // Takes the verifier's seed once the noise bits are committed, derives the public coins from it and the
+    // commitments, and XORs them in. The seed has to come from someone other than the prover, drawn after the
+    // commitments were posted: whoever knows it in advance can pick bits that the coins flip to any value.
+    pub fn take_coin_seed(&mut self, seed: [u8; 32]) -> Result<(), NoiseError> {
+        let _span = self.step_span("take_coin_seed").entered();
+        if self.private_commits.is_empty() || self.var_p || self.vector_noise {
+            return Err(NoiseError::NotCommitted);
+        }
+        if !self.public_bits.is_empty() {
+            return Err(NoiseError::CoinsDrawn);
+        }
+        self.coin_seed = seed;
+        self.public_bits = self.derive_public_coins(&self.private_commits, self.private_commits.len());
+        if self.noise_servers > 0 {
+            // The servers XOR their own bits, only the commitments are derived here
+            let com_one = self.server.com.commit(Scalar::one(), Scalar::zero());
+            self.xor_commits = self.private_commits.iter().zip(self.public_bits.iter())
+                .map(|(com, c)| if *c == Scalar::one() { com_one - com } else { *com })
+                .collect();
+        } else {
+            self.apply_public_coins();
+        }
+        debug!(coins = self.public_bits.len(), "Drew the public coins from the verifier's seed");
+        Ok(())
+    }
+
+    // XORs every private bit with its public coin. The commitment to b ^ c is derived from the commitment to b:
+    // it is com(b, r) for c = 0 and com(1, 0) - com(b, r) = com(1 - b, -r) for c = 1.
+    fn apply_public_coins(&mut self) {
+        let com_one = self.server.com.commit(Scalar::one(), Scalar::zero());
+        let (xor_bits, xor_commits): (Vec<_>, Vec<_>) = self.private_bits.iter()
+            .zip(self.private_commits.iter())
+            .zip(self.public_bits.iter())
+            .map(|((b, com), c)| (xor_bit(b, c), if *c == Scalar::one() { com_one - com } else { *com }))
+            .unzip();
+        self.xor_bits = Secret::new(xor_bits);
+        self.xor_commits = xor_commits;
+    }
+
+    // Transcript the public coins are derived from
+    fn coin_transcript(&self, commitments: &[RistrettoPoint]) -> Transcript {
+        let mut transcript = Transcript::with_context(PUBLIC_COIN_LABEL, self.session_id.as_bytes(), 0);
+        transcript.append_message(b"seed", &self.coin_seed);
+        for com in commitments {
+            transcript.append_point(b"com", com);
+        }
+        transcript
+    }
+
+    // A verifier's seed drawn by the runner itself. Only sound when the runner also plays the verifier, as in
+    // input_randomness and input_randomness_vector: a prover that draws its own seed can grind on it.
+    fn draw_verifier_seed(&mut self) -> [u8; 32] {
+        let mut seed = [0; 32];
+        self.rng.fill_bytes(&mut seed);
+        seed
+    }
+
+    // Public coins derived from the seed and the commitments to the private bits, so anyone can recompute them
+    fn derive_public_coins(&self, commitments: &[RistrettoPoint], n: usize) -> Vec<Scalar> {
+        derive_coins(&mut self.coin_transcript(commitments), n)
+    }
+
+    // Verifier side check that every private commitment carries a valid OR-proof
+    pub fn verify_private_bits(&self) -> bool {
+        METRICS.proofs_verified("or_proof", self.private_proofs.len());
+        METRICS.check("or_proof", self.private_bits_valid())
+    }
+
+    fn private_bits_valid(&self) -> bool {
+        let step = StepProgress::new(self.progress.as_ref(), PRIVATE_BIT_VERIFICATION, self.private_proofs.len());
+        let valid = self.private_proofs.len() == self.private_commits.len()
+            && self.private_proofs.par_iter().zip(self.private_commits.par_iter()).enumerate().all(|(i, (proof, com))| {
+                let valid = proof.com == *com && self.verifier.verify(proof, &mut self.bit_transcript(i));
+                step.tick();
+                valid
+            });
+        debug!(proofs = self.private_proofs.len(), valid, "Verified OR-proofs for the private bits");
+        valid
+    }
+
+    // Verifier side check that the public coins are the ones the seed and the committed private bits determine
+    pub fn verify_public_coins(&self) -> bool {
+        METRICS.check("public_coins", self.public_coins_valid())
+    }
+
+    fn public_coins_valid(&self) -> bool {
+        let commitments = if self.vector_noise { vec![self.noise_vector_com] } else { self.private_commits.clone() };
+        let valid = self.public_bits == self.derive_public_coins(&commitments, self.public_bits.len());
+        debug!(coins = self.public_bits.len(), valid, "Recomputed the public coins");
+        valid
+    }
+
+    // Verifier side check that the XOR commitments follow from the private commitments and the public coins
+    fn verify_xor_commits(&self) -> bool {
+        METRICS.check("xor_commitments", self.xor_commits_valid())
+    }
+
+    fn xor_commits_valid(&self) -> bool {
+        let com_one = self.server.com.commit(Scalar::one(), Scalar::zero());
+        let valid = self.xor_commits.len() == self.private_commits.len()
+            && self.public_bits.len() == self.private_commits.len()
+            && self.private_commits.iter().zip(self.public_bits.iter()).zip(self.xor_commits.iter())
+                .all(|((com, c), xor)| *xor == if *c == Scalar::one() { com_one - com } else { *com });
+        debug!(commitments = self.xor_commits.len(), valid, "Checked the XOR commitments");
+        valid
+    }
+
+    // <===== Alternate Step 4 =====>
+    // Same private bits, but committed as one vector C = sum_i b_i G_i + r h instead of one point per bit.
+    // After the public coins, a single O(log n) proof shows every b_i is a bit and that V + k g commits to
+    // sum_i b_i ^ c_i, where V commits to sum_i (1 - 2 c_i) b_i and k is the number of one coins.
+    pub fn input_randomness_vector(&mut self, bits: &[u8]) -> bool {
+        let _span = self.step_span("input_randomness_vector").entered();
+        self.inputs_closed = true;
+        let private_bits: Secret<Vec<Scalar>> = Secret::new(bits.iter().map(|b| bit_to_scalar(*b)).collect());
+        let vc = VectorCommitment::new(private_bits.len());
+        let r = random_scalar(&mut self.rng);
+        let gamma = random_scalar(&mut self.rng);
+        self.noise_vector_com = vc.commit(&private_bits, r);
+
+        // Coins are drawn once C is fixed. The proof needs them, so the runner plays the verifier here and this
+        // mode is only offered in process, not through the v1 API.
+        self.coin_seed = self.draw_verifier_seed();
+        self.public_bits = self.derive_public_coins(&[self.noise_vector_com], private_bits.len());
+        let weights: Vec<Scalar> = self.public_bits.iter().map(|c| Scalar::one() - c - c).collect();
+        let (proof, _, v_com) = match vc.prove_bits_with_rng(&mut self.vector_transcript(), &private_bits, r, &weights, gamma, &mut self.rng) {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let ones: Scalar = self.public_bits.iter().sum();
+        self.noise_sum_com = v_com + self.server.com.commit(ones, Scalar::zero());
+        self.xor_bits = Secret::new(private_bits.iter().zip(self.public_bits.iter()).map(|(b, c)| xor_bit(b, c)).collect());
+        self.xor_commits = vec![self.noise_sum_com];
+        self.private_bits = private_bits;
+        self.noise_proof = Some(proof);
+        METRICS.proofs_generated("bit_vector", 1);
+        debug!(bits = self.private_bits.len(), "Created the bit-vector proof of the noise");
+        self.noise_randomness = Secret::new(gamma);
+        self.vector_noise = true;
+        self.released = false;
+        self.verify_noise_vector()
+    }
+
+    // Fiat-Shamir transcript for the bit-vector proof of the noise
+    fn vector_transcript(&self) -> Transcript {
+        Transcript::with_context(VECTOR_NOISE_LABEL, self.session_id.as_bytes(), 0)
+    }
+
+    // Verifier side check of the bit-vector proof, using only C, V, the public coins and the proof
+    pub fn verify_noise_vector(&self) -> bool {
+        if self.noise_proof.is_some() {
+            METRICS.proofs_verified("bit_vector", 1);
+        }
+        METRICS.check("bit_vector", self.noise_vector_valid())
+    }
+
+    fn noise_vector_valid(&self) -> bool {
+        match &self.noise_proof {
+            Some(proof) => {
+                let vc = VectorCommitment::new(self.public_bits.len());
+                let weights: Vec<Scalar> = self.public_bits.iter().map(|c| Scalar::one() - c - c).collect();
+                let ones: Scalar = self.public_bits.iter().sum();
+                let v_com = self.noise_sum_com - self.server.com.commit(ones, Scalar::zero());
+                let valid = vc.verify_bits(&mut self.vector_transcript(), &self.noise_vector_com, &v_com, &weights, proof);
+                debug!(bits = self.public_bits.len(), valid, "Verified the bit-vector proof of the noise");
+                valid
+            },
+            None => false,
+        }
+    }
+
+    // Number of points sent for the noise bits in the vector mode: C, V and the proof
+    pub fn get_noise_proof_size(&self) -> usize {
+        self.noise_proof.as_ref().map_or(0, |p| 2 + p.num_points())
     }
 
     // <===== Step 5 =====>
@@ -195,13 +853,18 @@ impl BinomialRunner {
         ).collect()
     }
 
+    // OR-proofs of the private bits, proof.com being the commitment to the bit
+    pub fn get_private_proofs(&self) -> &[ProofScalar] {
+        &self.private_proofs
+    }
+
     // <===== Step 6 =====>
     // We can change this, but the site should just return True. There is no option for non-integer randomness
     // And even if there were, Rust forces values into 0 or 1 so it wouldn't matter
 
 
     // <===== Step 7 =====>
-    // Morra flips. Already has taken place. Can return sampled randomness
+    // Public coins. Already drawn in step 4, returns them
     pub fn get_public_random(&self) -> Vec<u8> {
         self.public_bits.iter().map(
             |b| if *b == Scalar::one() {
@@ -212,34 +875,32 @@ impl BinomialRunner {
         ).collect()
     }
 
+    // The verifier's seed the public coins are derived from, see derive_public_coins
+    pub fn get_coin_seed(&self) -> [u8; 32] {
+        self.coin_seed
+    }
+
     // <===== Alternate Step 7 =====>
     // Given ranges of values that are privately 0 or 1, returns a list of indices, and records the commitments of each. 
     // Used for variable p binomial mechanism
     pub fn rand_p_init(&mut self, n: i32) {
+        let _span = self.step_span("rand_p_init").entered();
+        self.inputs_closed = true;
         self.var_p_n = n;
         self.var_p = true;
+        self.released = false;
     }
 
     pub fn random_variable_p_input(&mut self, k: u32, m: i32, bits: &[u8]) -> bool {
+        let _span = self.step_span("rand_p_input").entered();
         if bits.len() != m as usize {
             return false;
         }
 
-        let bits: Vec<Scalar> = bits.iter().map(|b| {
-            match *b {
-                0 => {
-                    Scalar::zero()
-                },
-                _ => {
-                    Scalar::one()
-                }
-            }
-        }).collect();
+        // Any nonzero byte is a one
+        let bits: Secret<Vec<Scalar>> = Secret::new(bits.iter().map(|b| scalar_from_choice(!b.ct_eq(&0))).collect());
 
-        let r: Vec<Scalar> = (0..bits.len()).map(|_| {
-            let mut csprng = OsRng;
-                Scalar::random(&mut csprng)
-        }).collect();
+        let r: Secret<Vec<Scalar>> = Secret::new((0..bits.len()).map(|_| random_scalar(&mut self.rng)).collect());
 
         let bit_coms: Vec<RistrettoPoint> = bits.iter().zip(r.iter()).map(|(b, r)| self.server.com.commit(*b, *r)).collect();
         let lhs: RistrettoPoint = bit_coms.iter().sum();
@@ -247,9 +908,9 @@ impl BinomialRunner {
         if lhs != rhs {
             return false;
         } else {
-            self.var_p_bits.push(bits);
+            self.var_p_bits.push(bits.to_vec());
             self.var_p_coms.push(bit_coms);
-            self.var_p_randomness.push(r);
+            self.var_p_randomness.push(r.to_vec());
             self.var_p_in_count += 1;
             return true;
         }
@@ -257,11 +918,12 @@ impl BinomialRunner {
     }
 
     pub fn random_variable_p_end(&mut self) -> bool {
+        let _span = self.step_span("rand_p_end").entered();
         if self.var_p_n != self.var_p_in_count {
             false
         } else {
             for range in self.var_p_bits.iter().zip(self.var_p_coms.iter()).zip(self.var_p_randomness.iter()) {
-                let ind = (rand::random::<f32>() * range.0.0.len() as f32).floor() as usize;
+                let ind = index(&mut self.rng, range.0.0.len());
                 self.xor_bits.push(range.0.0[ind]);
                 self.xor_commits.push(range.0.1[ind]);
                 self.var_p_randomness_final.push(range.1[ind])
@@ -274,13 +936,7 @@ impl BinomialRunner {
     // <===== Step 8 =====>
     // XOR Private and public bits. This is done, so this call will return XORed bits and their commits
     pub fn get_xor_bits(&self) -> Vec<u8> {
-        self.xor_bits.iter().map(
-            |b| if *b == Scalar::one() {
-                1
-            } else {
-                0
-            } 
-        ).collect()
+        self.xor_bits.iter().map(scalar_to_bit).collect()
     }
 
     pub fn get_xor_commits(&self) -> Vec<String> {
@@ -289,42 +945,78 @@ impl BinomialRunner {
         ).collect()
     }
 
-    pub fn overwrite_xor_bits(&mut self, bits: &[u8]) {
-        let overwrite_bits_new: Vec<Scalar> = bits.iter().map(
-            |x| match *x {
-                1 => {
-                    Scalar::one()
-                },
-                _ => {
-                    Scalar::zero()
-                }
+    // <===== Step 9 =====>
+    // Compute sum (output result). A session with noise servers is refused until every server has sent its share,
+    // a partial sum would release the inputs with only part of the noise. So is a seeded run, whose noise anyone
+    // with the seed can recompute; it is neither released nor counted against the privacy budget.
+    pub fn compute_sum(&mut self) -> Result<u64, NoiseError> {
+        let _span = self.step_span("compute_sum").entered();
+        if self.seeded {
+            return Err(NoiseError::Seeded);
+        }
+        if self.awaiting_coins() {
+            return Err(NoiseError::CoinsMissing);
+        }
+        if self.num_noise_bits() == 0 {
+            return Err(NoiseError::NotCommitted); // The exact sum
+        }
+        if self.noise_servers > 0 {
+            self.noise_shares_complete()?;
+        }
+        if !self.input_shares_complete() {
+            return Err(NoiseError::InputSharesMissing);
+        }
+        let sum = self.sum();
+        if self.result_output.as_bytes()[8..].iter().any(|b| *b != 0) {
+            return Err(NoiseError::SumOutOfRange); // sum() only reads the low 64 bits
+        }
+        if !self.released {
+            self.released = true;
+            if let Some(epsilon) = self.epsilon(RELEASE_DELTA) {
+                METRICS.privacy_budget_spent.with_label_values(&[self.dataset.as_str()]).inc_by(epsilon);
             }
-        ).collect(); 
-        self.xor_bits = overwrite_bits_new;
+        }
+        Ok(sum)
     }
 
-    // <===== Step 9 =====>
-    // Compute sum (output result). 
-    pub fn compute_sum(&mut self) -> u64 {
-        if self.var_p {
+    // compute_sum for a seeded run, which replays or checks a run but releases nothing. None for any other run.
+    pub fn replay_sum(&mut self) -> Option<u64> {
+        if !self.seeded || (self.noise_servers > 0 && self.noise_shares_complete().is_err()) || !self.input_shares_complete() {
+            return None;
+        }
+        Some(self.sum())
+    }
+
+    // Noisy sum and its opening, final_x under final_z
+    fn sum(&mut self) -> u64 {
+        if self.noise_servers > 0 {
+            let (s_sum, t_sum) = self.noise_shares.iter().flatten()
+                .fold((Scalar::zero(), Scalar::zero()), |(s, t), share| (s + share.value, t + share.randomness));
+            self.result_output = *self.x_sum + s_sum;
+            self.final_x = *self.x_sum + s_sum;
+            self.final_z = Secret::new(*self.r_sum + t_sum);
+        } else if self.var_p {
             let p_sum: Scalar = self.xor_bits.iter().sum();
-            self.result_output = self.x_sum + p_sum;
+            self.result_output = *self.x_sum + p_sum;
             let s_sum: Scalar = self.var_p_randomness_final.iter().sum();
-            self.final_x = self.x_sum + p_sum;
-            self.final_z = self.r_sum + s_sum;
-
-            BigUint::from_bytes_le(&self.result_output.to_bytes()).to_u64_digits()[0]
+            self.final_x = *self.x_sum + p_sum;
+            self.final_z = Secret::new(*self.r_sum + s_sum);
+        } else if self.vector_noise {
+            let s_sum: Scalar = self.xor_bits.iter().sum();
+            self.result_output = *self.x_sum + s_sum;
+            self.final_x = *self.x_sum + s_sum;
+            self.final_z = Secret::new(*self.r_sum + *self.noise_randomness);
         } else {
-            let v_sum: Scalar = self.public_bits.iter().sum();
             let s_sum: Scalar = self.xor_bits.iter().sum();
-            let x = self.x_sum + v_sum;
-            let z = self.r_sum + s_sum;
-            self.result_output = self.x_sum + s_sum;
-            self.final_x = x;
-            self.final_z = z;
-
-            BigUint::from_bytes_le(&self.result_output.to_bytes()).to_u64_digits()[0]
+            let t_sum: Scalar = self.private_randomness.iter().zip(self.public_bits.iter())
+                .map(|(r, c)| xor_randomness(r, c))
+                .sum();
+            self.result_output = *self.x_sum + s_sum;
+            self.final_x = *self.x_sum + s_sum;
+            self.final_z = Secret::new(*self.r_sum + t_sum);
         }
+        // A sum of 0 has no digits
+        BigUint::from_bytes_le(&self.result_output.to_bytes()).to_u64_digits().first().copied().unwrap_or(0)
     }
 
     // <===== Step 10 =====>
@@ -337,7 +1029,8 @@ impl BinomialRunner {
     // Commits final sum with total final randomness, and computes sum of previous commitments
     // Returns final lhs and rhs
     pub fn commit_pedersons(&mut self) {
-        let lhs = self.client.com.commit(self.final_x, self.final_z);
+        let _span = self.step_span("commit_pedersons").entered();
+        let lhs = self.client.com.commit(self.final_x, *self.final_z);
         let v_coms_sum: RistrettoPoint = self.xor_commits.iter().sum();
         let rhs = self.coms_sum + v_coms_sum;
         self.lhs = lhs;
@@ -351,6 +1044,74 @@ impl BinomialRunner {
     pub fn get_rhs(&self) -> String {
         BigUint::from_bytes_le(&self.rhs.compress().to_bytes()).to_str_radix(10)
     }
+
+    // <===== Step 12 =====>
+    // Auditor side check of the whole run: the proofs of client submitted inputs, the proofs on the noise, the
+    // public coins and lhs == rhs. The selection of the variable p mechanism is not publicly verifiable, so only
+    // lhs == rhs is checked for it.
+    pub fn verify(&self) -> bool {
+        let _span = self.step_span("verify").entered();
+        if self.client_inputs && !self.verify_input_proofs() {
+            return false;
+        }
+        let noise_valid = if self.var_p {
+            true
+        } else if self.vector_noise {
+            self.verify_noise_vector() && self.verify_public_coins()
+        } else {
+            self.verify_private_bits() && self.verify_public_coins() && self.verify_xor_commits()
+        };
+        noise_valid && METRICS.check("opening", self.lhs == self.rhs)
+    }
+
+    // The same check as verify, without counting it in the metrics. For reads that repeat a check verify already
+    // counted, such as GET /check.
+    pub fn is_valid(&self) -> bool {
+        if self.client_inputs && !self.input_proofs_valid() {
+            return false;
+        }
+        let noise_valid = if self.var_p {
+            true
+        } else if self.vector_noise {
+            self.noise_vector_valid() && self.public_coins_valid()
+        } else {
+            self.private_bits_valid() && self.public_coins_valid() && self.xor_commits_valid()
+        };
+        noise_valid && self.lhs == self.rhs
+    }
+}
+
+// Adversarial prover behaviors, so the demo can show each of them being caught. Only built with the cheat-mode feature.
+#[cfg(feature = "cheat-mode")]
+impl BinomialRunner {
+
+    // Replaces the XORed bits after the fact. Caught by lhs != rhs.
+    pub fn overwrite_xor_bits(&mut self, bits: &[u8]) {
+        self.xor_bits = Secret::new(bits.iter().map(|b| bit_to_scalar(*b)).collect());
+    }
+
+    // Commits the i-th private "bit" to an arbitrary value and reuses the OR-proof of the honest bit for it.
+    // Everything downstream is kept consistent, so only the OR-proof check catches it.
+    pub fn forge_private_bit(&mut self, i: usize, value: u64) {
+        let v = Scalar::from(value);
+        let com = self.server.com.commit(v, self.private_randomness[i]);
+        self.private_bits[i] = v;
+        self.private_commits[i] = com;
+        self.private_proofs[i].com = com;
+        self.apply_public_coins();
+    }
+
+    // Replaces the public coins with ones chosen by the prover and redoes the XOR with them.
+    // The sums still open, so only the recomputation of the coins catches it.
+    pub fn bias_public_coins(&mut self, coins: &[u8]) {
+        self.public_bits = coins.iter().map(|c| if *c == 1 { Scalar::one() } else { Scalar::zero() }).collect();
+        self.apply_public_coins();
+    }
+
+    // Reports a final randomness other than the one the commitments add up to. Caught by lhs != rhs.
+    pub fn overwrite_final_z(&mut self, z: Scalar) {
+        self.final_z = Secret::new(z);
+    }
 }
 
 
@@ -378,7 +1139,7 @@ pub fn test_unbiased_p() {
 
     let _xorcoms = br.get_xor_commits(); // Get commitments of XOR bits
 
-    let output = br.compute_sum(); // Compute final output sum of our mechanism
+    let output = br.compute_sum().unwrap(); // Compute final output sum of our mechanism
     println!("Output: {}", output);
     let _z = br.get_z(); 
 
@@ -392,6 +1153,7 @@ pub fn test_unbiased_p() {
 
 // This test is almost the same as the unbiased p without cheating. It has one change, which overwrites the bits after 
 // the XOR operation (which would be how an adversary would attempt to cheat the distribution). The idea is to catch this
+#[cfg(feature = "cheat-mode")]
 #[test]
 pub fn test_unbiased_p_cheat() {
     let mut rng = rand::thread_rng();
@@ -416,7 +1178,7 @@ pub fn test_unbiased_p_cheat() {
     br.overwrite_xor_bits(&vec![1; xorbits.len()]); // Overwrite our XORed bits with all 1's. This would cause the output to be higher than it should be
     let _xorcoms = br.get_xor_commits();
 
-    let output = br.compute_sum();
+    let output = br.compute_sum().unwrap();
     println!("Output: {}", output);
     let _z = br.get_z();
 
@@ -448,7 +1210,7 @@ pub fn test_biased_p() {
 
     let _xorcoms = br.get_xor_commits();
 
-    let out = br.compute_sum();
+    let out = br.compute_sum().unwrap();
     println!("XOR sum: {}", out);
     let _z = br.get_z();
     br.commit_pedersons();
@@ -458,6 +1220,7 @@ pub fn test_biased_p() {
 }
 
 // This function tests that we catch cheating with the biased binomial mechanism. It is essentially the same as without cheating, with one extra call.
+#[cfg(feature = "cheat-mode")]
 #[test]
 pub fn test_biased_p_cheat() {
     let mut rng = rand::thread_rng(); // Same initialization as before
@@ -481,11 +1244,205 @@ pub fn test_biased_p_cheat() {
 
     br.overwrite_xor_bits(&vec![0; xorbits.len()]); // This is our cheat. Here, we overwrite all our final bits with 0's, artificially decreasing the count.
 
-    let out = br.compute_sum();
+    let out = br.compute_sum().unwrap();
     println!("XOR sum: {}", out);
     let _z = br.get_z();
     br.commit_pedersons();
     let lhs = br.get_lhs();
     let rhs = br.get_rhs();
     assert_ne!(lhs, rhs); // Check that our lhs and rhs are different, ensuring we catch the cheating.
-}
\ No newline at end of file
+}
+// Unbiased mechanism with the noise bits committed as one vector. The verifier receives O(log n) points for the noise.
+#[test]
+pub fn test_unbiased_p_vector() {
+    let mut rng = rand::thread_rng();
+    let bits: Vec<u8> = (0..1000).map(|_| rng.gen_bool(0.5) as u8).collect();
+    let mut br: BinomialRunner = BinomialRunner::new(&bits);
+
+    let randbits: Vec<u8> = (0..1000).map(|_| rng.gen_bool(0.5) as u8).collect();
+    assert!(br.input_randomness_vector(&randbits)); // Commit the private bits as a vector and prove them after the coin flips
+    assert_eq!(br.get_noise_proof_size(), 2 + 4 + 2 * 10);
+
+    let xorbits = br.get_xor_bits();
+    let output = br.compute_sum().unwrap();
+    let expected: u64 = bits.iter().chain(xorbits.iter()).map(|&x| x as u64).sum();
+    assert_eq!(output, expected);
+
+    br.commit_pedersons();
+    assert_eq!(br.get_lhs(), br.get_rhs());
+    assert!(br.verify());
+    assert!(br.is_valid());
+
+    // Overwriting the XORed bits is still caught
+    #[cfg(feature = "cheat-mode")]
+    {
+        br.overwrite_xor_bits(&vec![1; xorbits.len()]);
+        br.compute_sum().unwrap();
+        br.commit_pedersons();
+        assert_ne!(br.get_lhs(), br.get_rhs());
+        assert!(!br.verify());
+    }
+}
+
+// Runs the unbiased mechanism on 64 bits up to step 4 for the cheat tests
+#[cfg(all(test, feature = "cheat-mode"))]
+fn small_run() -> BinomialRunner {
+    let mut rng = rand::thread_rng();
+    let bits: Vec<u8> = (0..64).map(|_| rng.gen_bool(0.5) as u8).collect();
+    let mut br = BinomialRunner::new(&bits);
+    let randbits: Vec<u8> = (0..64).map(|_| rng.gen_bool(0.5) as u8).collect();
+    br.input_randomness(&randbits);
+    br
+}
+
+// A private "bit" committed to 5 with a reused OR-proof. The commitments still add up, the OR-proof does not verify.
+#[cfg(feature = "cheat-mode")]
+#[test]
+pub fn test_forged_or_proof_caught() {
+    let mut br = small_run();
+    assert!(br.verify_private_bits());
+
+    br.forge_private_bit(3, 5);
+    br.compute_sum().unwrap();
+    br.commit_pedersons();
+    assert_eq!(br.get_lhs(), br.get_rhs());
+    assert!(!br.verify_private_bits());
+    assert!(!br.verify());
+    assert!(!br.is_valid());
+}
+
+// The prover picks coins that flip every private 0 into a 1. The commitments still add up, the coins cannot be recomputed.
+#[cfg(feature = "cheat-mode")]
+#[test]
+pub fn test_biased_public_coins_caught() {
+    let mut br = small_run();
+    assert!(br.verify_public_coins());
+
+    let coins: Vec<u8> = br.private_bits.iter().map(|b| if *b == Scalar::zero() { 1 } else { 0 }).collect();
+    br.bias_public_coins(&coins);
+    assert!(br.get_xor_bits().iter().all(|b| *b == 1));
+    br.compute_sum().unwrap();
+    br.commit_pedersons();
+    assert_eq!(br.get_lhs(), br.get_rhs());
+    assert!(!br.verify_public_coins());
+    assert!(!br.verify());
+    assert!(!br.is_valid());
+}
+
+// The prover reports a final_z other than the sum of the randomness
+#[cfg(feature = "cheat-mode")]
+#[test]
+pub fn test_lied_final_z_caught() {
+    let mut br = small_run();
+    br.compute_sum().unwrap();
+    br.commit_pedersons();
+    assert!(br.verify());
+
+    br.overwrite_final_z(*br.final_z + Scalar::one());
+    br.commit_pedersons();
+    assert_ne!(br.get_lhs(), br.get_rhs());
+    assert!(!br.verify());
+}
+
+// Inputs submitted by the clients: only the ones that check out count, and collection closes at max_inputs
+#[test]
+pub fn test_client_submitted_inputs() {
+    let mut br = BinomialRunner::collecting(3, None, 2);
+    let session_id = br.get_session_id();
+    assert_eq!(br.phase(), Phase::CollectingInputs);
+
+    let mut aggregators = [InputAggregator::new(0), InputAggregator::new(1)];
+    let mut submit = |client_id: &str, x: bool| {
+        let (submission, shares) = InputSubmission::new(&session_id, client_id, x, 2);
+        for (aggregator, share) in aggregators.iter_mut().zip(shares) {
+            aggregator.receive(client_id, share);
+        }
+        submission
+    };
+    let mut lying = submit("c", false);
+    lying.share_coms[0] += CurveParams::new().g; // Shares that open to 1 for a commitment to 0
+    let mut replayed = submit("d", true);
+    replayed.client_id = "e".to_string(); // The proof is bound to "d"
+    let submissions = vec![
+        submit("a", true),
+        submit("b", false),
+        lying,
+        replayed,
+        submit("a", true),
+        InputSubmission::new("another-session", "f", true, 2).0,
+        submit("g", true),
+        submit("h", true),
+    ];
+    let results = br.submit_inputs(&submissions);
+    assert_eq!(results, vec![
+        Ok(()),
+        Ok(()),
+        Err(SubmissionError::InvalidOpening),
+        Err(SubmissionError::InvalidProof),
+        Err(SubmissionError::DuplicateClient),
+        Err(SubmissionError::InvalidProof),
+        Ok(()),
+        Err(SubmissionError::InputsClosed),
+    ]);
+    assert!(!br.inputs_open());
+    assert_eq!(br.num_inputs(), 3);
+    assert_eq!(br.phase(), Phase::InputsCommitted);
+
+    // Each aggregator sums its shares over the accepted clients only, the opening is out until both are in
+    let accepted = br.accepted_inputs();
+    let sums: Vec<OpeningShare> = aggregators.iter().map(|a| a.aggregate(&accepted).unwrap()).collect();
+    br.input_randomness(&[0; 16]);
+    let noise: u64 = br.get_xor_bits().iter().map(|b| *b as u64).sum();
+    assert_eq!(br.compute_sum().err(), Some(NoiseError::InputSharesMissing));
+    assert_eq!(br.add_input_share(0, sums[1]).err(), Some(SubmissionError::InvalidShare));
+    assert!(br.add_input_share(0, sums[0]).is_ok());
+    assert_eq!(br.add_input_share(0, sums[0]).err(), Some(SubmissionError::AlreadyShared));
+    assert_eq!(br.add_input_share(2, sums[1]).err(), Some(SubmissionError::NotAggregated));
+    assert!(br.add_input_share(1, sums[1]).is_ok());
+    assert_eq!(br.compute_sum().unwrap(), 2 + noise);
+    br.commit_pedersons();
+    assert!(br.verify());
+
+    // A proof swapped in after the fact fails the audit
+    br.input_proofs.swap(0, 1);
+    assert!(!br.verify());
+}
+
+// Two runners seeded alike produce the same session, commitments, proofs, coins and result, in both noise modes.
+// The result of a seeded run only comes out of replay_sum.
+#[test]
+pub fn test_seeded_replay() {
+    let x = [1, 0, 1, 1, 0, 1, 1, 0];
+    let noise = [0, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0, 1, 1];
+    let run = |seed: u64, vector: bool| {
+        let mut br = BinomialRunner::new_with_rng(&x, None, ProtocolRng::seeded(seed));
+        if vector {
+            assert!(br.input_randomness_vector(&noise));
+        } else {
+            br.input_randomness(&noise);
+        }
+        // Never released, and not counted against the budget
+        assert_eq!(br.compute_sum(), Err(NoiseError::Seeded));
+        assert!(!br.released);
+        let sum = br.replay_sum().unwrap();
+        br.commit_pedersons();
+        assert!(br.verify());
+        let proofs = serde_json::to_string(&(&br.private_proofs, &br.noise_proof)).unwrap();
+        (br.get_session_id(), br.get_x_commits(), proofs, br.get_public_random(), sum, br.get_z())
+    };
+    for vector in [false, true] {
+        let first = run(3, vector);
+        assert_eq!(first, run(3, vector));
+        assert_ne!(first.0, run(4, vector).0);
+    }
+    assert_ne!(BinomialRunner::new(&x).get_session_id(), BinomialRunner::new(&x).get_session_id());
+
+    // A resumed seeded run still refuses the release, and replay_sum refuses any other run
+    let mut br = BinomialRunner::new_with_rng(&x, None, ProtocolRng::seeded(3));
+    br.input_randomness(&noise);
+    let mut resumed: BinomialRunner = serde_json::from_str(&serde_json::to_string(&br).unwrap()).unwrap();
+    assert_eq!(resumed.compute_sum(), Err(NoiseError::Seeded));
+    let mut unseeded = BinomialRunner::new(&x);
+    unseeded.input_randomness(&noise);
+    assert_eq!(unseeded.replay_sum(), None);
+}
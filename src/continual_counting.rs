@@ -0,0 +1,467 @@
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+use crate::participants;
+use crate::binomial_runner::{default_client, default_verifier, derive_coins};
+use crate::consants::MGRAIN;
+use crate::distributed_noise::NoiseError;
+use crate::generic_commitments::Commitment;
+use num_bigint::BigUint;
+use rand_core::RngCore;
+use rayon::prelude::*;
+use uuid::Builder;
+use crate::transcript::{Transcript, TREE_COIN_LABEL, TREE_NOISE_LABEL};
+use crate::curve_parameters::CurveParams;
+use crate::rng::{flip, index, ProtocolRng};
+use crate::secrets::{bit_to_scalar, xor_bit, xor_randomness, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info_span};
+use subtle::ConstantTimeEq;
+
+// Continual release of running counts with the binary tree mechanism (Chan, Shi and Song / Dwork et al.).
+// Time step t covers one batch of client bits (e.g. one day of telemetry). Every dyadic interval of time steps
+// is a node of the tree. A node is finalised once its interval is complete: it stores the committed partial
+// sum of its inputs plus verifiably generated noise. The prefix count at time t is the sum of at most log(t)
+// nodes, and an auditor checks a release by adding up the public node commitments.
+
+// How the noise for each node is sampled
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum TreeNoise {
+    // n_b private bits XORed with public coins, as in BinomialRunner
+    Binomial { n_b: usize },
+    // Geometric noise built from committed Bernoulli bits, as in participants::Server::distributional_geometric_com
+    Geometric { precision_bits: usize, base_prob: f64 },
+}
+
+impl TreeNoise {
+
+    // Noise bits committed for every node: n_b private bits, or MGRAIN Bernoulli coins per precision bit
+    pub fn num_noise_bits(&self) -> usize {
+        match self {
+            TreeNoise::Binomial { n_b } => *n_b,
+            TreeNoise::Geometric { precision_bits, .. } => precision_bits.saturating_mul(MGRAIN),
+        }
+    }
+}
+
+// A finalised node of the tree. The public part (commitments) is kept for auditors,
+// the private part (openings) is kept by the prover to answer later releases.
+#[derive(Serialize, Deserialize)]
+pub struct TreeNode {
+    pub level: usize,
+    pub start: usize, // first time step covered by this node
+    pub input_com: RistrettoPoint, // sum of the commitments of every input in the interval
+    pub noise_com: RistrettoPoint, // commitment to the noise that was added to the node
+    value: Scalar,
+    value_rand: Scalar,
+    noise: Scalar,
+    noise_rand: Scalar,
+}
+
+impl TreeNode {
+
+    // Commitment an auditor expects to open to the noisy partial sum of this node
+    pub fn com(&self) -> RistrettoPoint {
+        self.input_com + self.noise_com
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + (1 << self.level)
+    }
+}
+
+// Partial sum for a level of the tree whose interval is not yet complete
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PartialSum {
+    start: usize,
+    com: RistrettoPoint,
+    value: Scalar,
+    rand: Scalar,
+}
+
+// Noise of a node whose commitments are out and whose public coins wait for the auditor's seed
+#[derive(Serialize, Deserialize)]
+enum PendingNoise {
+    // The private bits with their randomness and commitments, XORed with the coins once they are drawn
+    Binomial { bits: Secret<Vec<Scalar>>, rand: Secret<Vec<Scalar>>, coms: Vec<RistrettoPoint> },
+    // The commitments to the Bernoulli coins of every precision bit. The seed picks one coin per precision bit,
+    // the server keeps the openings.
+    Geometric { coms: Vec<Vec<RistrettoPoint>> },
+}
+
+// The node of the current time step, finalised by ContinualCounter::take_coin_seed
+#[derive(Serialize, Deserialize)]
+struct PendingNode {
+    level: usize,
+    acc: PartialSum,
+    noise: PendingNoise,
+}
+
+// Output of a prefix release. count and z open the sum of the node commitments in `nodes`.
+pub struct PrefixRelease {
+    pub t: usize,
+    pub count: Scalar,
+    pub z: Scalar,
+    pub nodes: Vec<(usize, usize)>, // (level, start) of every node used for the release
+}
+
+impl PrefixRelease {
+
+    pub fn get_count(&self) -> u64 {
+        BigUint::from_bytes_le(&self.count.to_bytes()).to_u64_digits().first().copied().unwrap_or(0)
+    }
+
+    pub fn get_z(&self) -> String {
+        BigUint::from_bytes_le(&self.z.to_bytes()).to_str_radix(10)
+    }
+}
+
+// Saved with its session between releases, see SessionManager::update_counter. As for BinomialRunner, the client
+// and the verifier are rebuilt from CurveParams and a resumed counter draws from OsRng.
+#[derive(Serialize, Deserialize)]
+pub struct ContinualCounter {
+    session_id: String,
+    #[serde(skip, default = "default_client")]
+    client: participants::Client,
+    server: participants::Server,
+    #[serde(skip, default = "default_verifier")]
+    verifier: participants::Board,
+    noise: TreeNoise,
+    t: usize,
+    partial: Vec<Option<PartialSum>>, // one slot per level of the tree
+    nodes: Vec<TreeNode>, // every finalised node, kept between releases
+    #[serde(default)]
+    pending: Option<PendingNode>,
+    #[serde(skip)]
+    rng: ProtocolRng,
+}
+
+impl ContinualCounter {
+
+    pub fn new(noise: TreeNoise) -> ContinualCounter {
+        Self::new_with_rng(noise, ProtocolRng::default())
+    }
+
+    // Same as new, with the session id, the input randomness, the noise and the verifier's choices drawn from rng
+    pub fn new_with_rng(noise: TreeNoise, mut rng: ProtocolRng) -> ContinualCounter {
+        let params = CurveParams::new();
+        let (g, h) = (params.g, params.h);
+        let mut id_bytes = [0; 16];
+        rng.fill_bytes(&mut id_bytes);
+
+        ContinualCounter {
+            session_id: Builder::from_random_bytes(id_bytes).into_uuid().to_string(),
+            client: participants::Client::new(g, h),
+            server: participants::Server::new(g, h),
+            verifier: participants::Board::new(g, h),
+            noise,
+            t: 0,
+            partial: Vec::new(),
+            nodes: Vec::new(),
+            pending: None,
+            rng,
+        }
+    }
+
+    // Number of batches ingested so far
+    pub fn time(&self) -> usize {
+        self.t
+    }
+
+    pub fn get_nodes(&self) -> &[TreeNode] {
+        &self.nodes
+    }
+
+    // Noise bits committed for every node, see TreeNoise::num_noise_bits
+    pub fn num_noise_bits(&self) -> usize {
+        self.noise.num_noise_bits()
+    }
+
+    // True while the node of the current time step waits for the auditor's coin seed
+    pub fn awaiting_coins(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // Commitments to the noise of the pending node, which the auditor sees before it draws the coin seed
+    pub fn pending_commitments(&self) -> Vec<RistrettoPoint> {
+        match self.pending.as_ref().map(|p| &p.noise) {
+            Some(PendingNoise::Binomial { coms, .. }) => coms.clone(),
+            Some(PendingNoise::Geometric { coms }) => coms.concat(),
+            None => Vec::new(),
+        }
+    }
+
+    // Ingest the batch for the next time step. Commits to every input bit, merges the batch into the levels below
+    // the new node and commits to the node's noise. The node is finalised by take_coin_seed.
+    // Returns false if the last node still waits for its seed or if any noise proof fails verification, in which
+    // case nothing is recorded.
+    pub fn ingest(&mut self, x: &[u8]) -> bool {
+        let _span = info_span!("step", step = "ingest", session_id = %self.session_id, t = self.t).entered();
+        if self.pending.is_some() {
+            return false;
+        }
+        let x_new: Vec<Scalar> = x.iter().map(|b| Scalar::from((*b == 1) as u64)).collect();
+        let r: Vec<Scalar> = x_new.iter().map(|_| self.client.com.sample_randomness_with_rng(&mut self.rng)).collect();
+        let input_coms: Vec<RistrettoPoint> = x_new.par_iter()
+                                                    .zip(r.par_iter())
+                                                    .map(|(&x_i, &r_i)| self.client.com.commit(x_i, r_i))
+                                                    .collect();
+
+        let mut acc = PartialSum {
+            start: self.t,
+            com: input_coms.iter().sum(),
+            value: x_new.iter().sum(),
+            rand: r.iter().sum(),
+        };
+
+        // The new node sits on the level given by the number of trailing zeros of t + 1,
+        // and absorbs every partial sum below it.
+        let level = (self.t + 1).trailing_zeros() as usize;
+        for p in self.partial.iter().take(level).flatten() {
+            acc.start = p.start;
+            acc.com += p.com;
+            acc.value += p.value;
+            acc.rand += p.rand;
+        }
+
+        let noise = match self.commit_noise() {
+            Some(n) => n,
+            None => return false,
+        };
+        self.pending = Some(PendingNode { level, acc, noise });
+        true
+    }
+
+    // Takes the auditor's seed for the pending node, drawn after the node's noise commitments were published, and
+    // finalises the node. As in BinomialRunner::take_coin_seed, the Binomial public coins follow from the seed and
+    // the commitments, and so does the coin a Geometric node opens for each precision bit.
+    pub fn take_coin_seed(&mut self, seed: [u8; 32]) -> Result<(), NoiseError> {
+        let _span = info_span!("step", step = "take_coin_seed", session_id = %self.session_id, t = self.t).entered();
+        let PendingNode { level, acc, noise } = self.pending.take().ok_or(NoiseError::NotCommitted)?;
+        let (noise, noise_rand, noise_com) = match noise {
+            PendingNoise::Binomial { bits, rand, coms } => {
+                let coins = derive_coins(&mut self.coin_transcript(&seed, &coms), coms.len());
+                // A one coin flips the committed bit, Com(1 - b, -r) = g - Com(b, r)
+                let noise = bits.iter().zip(coins.iter()).map(|(b, c)| xor_bit(b, c)).sum();
+                let noise_rand = rand.iter().zip(coins.iter()).map(|(r, c)| xor_randomness(r, c)).sum();
+                let noise_com = coms.iter().zip(coins.iter())
+                                    .map(|(com, c)| if *c == Scalar::one() { self.verifier.g - com } else { *com })
+                                    .sum();
+                (noise, noise_rand, noise_com)
+            },
+            PendingNoise::Geometric { coms } => {
+                // The verifier picks which committed coin is used for every precision bit
+                let mut transcript = self.coin_transcript(&seed, &coms.concat());
+                let mut rng = ProtocolRng::from_seed(transcript.challenge_scalar(b"indices").to_bytes());
+                let challenge_indices: Vec<usize> = coms.iter().map(|c| index(&mut rng, c.len())).collect();
+                let bit_coms: Vec<RistrettoPoint> = coms.iter().zip(challenge_indices.iter()).map(|(c, &i)| c[i]).collect();
+                let noise_com = self.verifier.binary_to_exp(bit_coms);
+                let (noise, noise_rand) = self.server.geometric_opening(challenge_indices);
+                self.server.clear_openings();
+                (noise, noise_rand, noise_com)
+            },
+        };
+
+        self.nodes.push(TreeNode {
+            level,
+            start: acc.start,
+            input_com: acc.com,
+            noise_com,
+            value: acc.value,
+            value_rand: acc.rand,
+            noise,
+            noise_rand,
+        });
+        if self.partial.len() <= level {
+            self.partial.resize(level + 1, None);
+        }
+        self.partial[..level].fill(None);
+        self.partial[level] = Some(acc);
+        self.t += 1;
+        debug!(level, "Drew the public coins of the node from the auditor's seed");
+        Ok(())
+    }
+
+    // Transcript for the OR-proof of the i-th noise bit of the node finalised at the current time step
+    fn noise_transcript(&self, i: usize) -> Transcript {
+        let mut transcript = Transcript::with_context(TREE_NOISE_LABEL, self.session_id.as_bytes(), self.t as u64);
+        transcript.append_u64(b"bit", i as u64);
+        transcript
+    }
+
+    // Transcript the coins of the current node are drawn from, see BinomialRunner::coin_transcript
+    fn coin_transcript(&self, seed: &[u8; 32], commitments: &[RistrettoPoint]) -> Transcript {
+        let mut transcript = Transcript::with_context(TREE_COIN_LABEL, self.session_id.as_bytes(), self.t as u64);
+        transcript.append_message(b"seed", seed);
+        for com in commitments {
+            transcript.append_point(b"com", com);
+        }
+        transcript
+    }
+
+    // Commits to the noise of one node, after checking every proof the prover sends
+    fn commit_noise(&mut self) -> Option<PendingNoise> {
+        match self.noise {
+            TreeNoise::Binomial { n_b } => {
+                let streams = self.rng.streams();
+                let results: Vec<Option<(Scalar, Scalar, RistrettoPoint)>> = (0..n_b)
+                    .into_par_iter()
+                    .map(|i| {
+                        // Prover commits to a private bit and proves it is a bit
+                        let mut rng = streams.get(i);
+                        let bit = bit_to_scalar(flip(&mut rng) as u8);
+                        let r = self.server.get_random_value_with_rng(&mut rng);
+                        let transcript = self.server.com.create_bit_proof(bit.ct_eq(&Scalar::one()), r, &mut self.noise_transcript(i), &mut rng);
+                        if !self.verifier.verify(&transcript, &mut self.noise_transcript(i)) {
+                            return None;
+                        }
+                        Some((bit, r, transcript.com))
+                    })
+                    .collect();
+
+                debug!(proofs = n_b, valid = results.iter().all(Option::is_some), "Created and verified the OR-proofs of the node noise");
+                let (mut bits, mut rand, mut coms) = (Secret::new(Vec::new()), Secret::new(Vec::new()), Vec::new());
+                for res in results {
+                    let (b, r, c) = res?;
+                    bits.push(b);
+                    rand.push(r);
+                    coms.push(c);
+                }
+                Some(PendingNoise::Binomial { bits, rand, coms })
+            },
+            TreeNoise::Geometric { precision_bits, base_prob } => {
+                self.server.clear_openings();
+                self.server.session_id = [self.session_id.as_bytes(), &(self.t as u64).to_le_bytes()].concat();
+                let dist_geom_com = self.server.distributional_geometric_com_with_rng(precision_bits, base_prob, &mut self.rng);
+                for (coin_idx, dist) in dist_geom_com.iter().enumerate() {
+                    for (i, transcript) in dist.or_proofs.iter().enumerate() {
+                        let mut fs_transcript = participants::Server::bernoulli_transcript(&self.server.session_id, coin_idx, i);
+                        if !self.verifier.verify(transcript, &mut fs_transcript) {
+                            debug!(coin = coin_idx, proof = i, "OR-proof of a geometric coin failed to verify");
+                            return None;
+                        }
+                    }
+                }
+                debug!(proofs = dist_geom_com.iter().map(|d| d.or_proofs.len()).sum::<usize>(), "Verified the OR-proofs of the geometric coins");
+                Some(PendingNoise::Geometric { coms: dist_geom_com.iter().map(|d| d.or_proofs.iter().map(|p| p.com).collect()).collect() })
+            },
+        }
+    }
+
+    // Nodes whose intervals partition [0, t): one node per set bit of t, largest first
+    fn prefix_nodes(&self, t: usize) -> Vec<&TreeNode> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        for level in (0..usize::BITS as usize).rev() {
+            if t & (1 << level) != 0 {
+                if let Some(node) = self.nodes.iter().find(|n| n.level == level && n.start == start) {
+                    out.push(node);
+                }
+                start += 1 << level;
+            }
+        }
+        out
+    }
+
+    // Release the noisy running count over the first t time steps
+    pub fn release(&self, t: usize) -> Option<PrefixRelease> {
+        if t == 0 || t > self.t {
+            return None;
+        }
+        let nodes = self.prefix_nodes(t);
+        let count = nodes.iter().map(|n| n.value + n.noise).sum();
+        let z = nodes.iter().map(|n| n.value_rand + n.noise_rand).sum();
+        Some(PrefixRelease {
+            t,
+            count,
+            z,
+            nodes: nodes.iter().map(|n| (n.level, n.start)).collect(),
+        })
+    }
+
+    // Release the running count at the current time step
+    pub fn release_current(&self) -> Option<PrefixRelease> {
+        self.release(self.t)
+    }
+
+    // Auditor check: the release must use exactly the nodes of the binary decomposition of t,
+    // and (count, z) must open the sum of their public commitments.
+    pub fn verify_release(&self, release: &PrefixRelease) -> bool {
+        let _span = info_span!("step", step = "verify_release", session_id = %self.session_id, t = release.t).entered();
+        let nodes = self.prefix_nodes(release.t);
+        let expected: Vec<(usize, usize)> = nodes.iter().map(|n| (n.level, n.start)).collect();
+        if nodes.iter().map(|n| n.end()).max() != Some(release.t) || expected != release.nodes {
+            return false;
+        }
+        let rhs: RistrettoPoint = nodes.iter().map(|n| n.com()).sum();
+        self.verifier.com.open(release.count, release.z, rhs)
+    }
+}
+
+
+#[test]
+pub fn test_continual_binomial() {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let mut cc = ContinualCounter::new(TreeNoise::Binomial { n_b: 64 });
+    let mut true_count: u64 = 0;
+    for day in 1..=11 {
+        let bits: Vec<u8> = (0..200).map(|_| rng.gen_bool(0.5) as u8).collect();
+        true_count += bits.iter().map(|&b| b as u64).sum::<u64>();
+        assert!(cc.ingest(&bits));
+        // The node is only counted once the auditor's seed is in, and the next batch waits for it
+        assert_eq!(cc.time(), day - 1);
+        assert_eq!(cc.pending_commitments().len(), 64);
+        assert!(!cc.ingest(&bits));
+        cc.take_coin_seed([day as u8; 32]).unwrap();
+        assert_eq!(cc.take_coin_seed([day as u8; 32]), Err(NoiseError::NotCommitted));
+
+        let release = cc.release_current().unwrap();
+        assert_eq!(release.nodes.len(), day.count_ones() as usize); // at most log(t) nodes per release
+        assert!(cc.verify_release(&release));
+        assert!(release.get_count() >= true_count); // binomial noise is non-negative
+        assert!(release.get_count() <= true_count + 64 * release.nodes.len() as u64);
+    }
+
+    // Earlier prefixes can still be released and verified from the stored nodes
+    let release = cc.release(5).unwrap();
+    assert!(cc.verify_release(&release));
+}
+
+#[test]
+pub fn test_continual_geometric() {
+    let mut cc = ContinualCounter::new(TreeNoise::Geometric { precision_bits: 4, base_prob: 0.5276334472589853 });
+    for _ in 0..4 {
+        assert!(cc.ingest(&[1, 0, 1, 1]));
+        cc.take_coin_seed([7; 32]).unwrap();
+        let release = cc.release_current().unwrap();
+        assert!(cc.verify_release(&release));
+    }
+
+    // Counters seeded alike, with the same seeds from the auditor, draw the same noise and release the same openings
+    let seeded = || ContinualCounter::new_with_rng(TreeNoise::Geometric { precision_bits: 4, base_prob: 0.5276334472589853 }, ProtocolRng::seeded(5));
+    let (mut a, mut b) = (seeded(), seeded());
+    for _ in 0..3 {
+        assert!(a.ingest(&[1, 0, 1]) && b.ingest(&[1, 0, 1]));
+        assert_eq!(a.pending_commitments(), b.pending_commitments());
+        a.take_coin_seed([7; 32]).unwrap();
+        b.take_coin_seed([7; 32]).unwrap();
+        let (ra, rb) = (a.release_current().unwrap(), b.release_current().unwrap());
+        assert_eq!((ra.count, ra.z), (rb.count, rb.z));
+    }
+}
+
+#[test]
+pub fn test_continual_tampered_release() {
+    let mut cc = ContinualCounter::new(TreeNoise::Binomial { n_b: 16 });
+    for _ in 0..6 {
+        assert!(cc.ingest(&[1, 1, 0, 1]));
+        cc.take_coin_seed([7; 32]).unwrap();
+    }
+    let mut release = cc.release(6).unwrap();
+    release.count += Scalar::one(); // Prover lies about the count
+    assert!(!cc.verify_release(&release));
+
+    let mut release = cc.release(6).unwrap();
+    release.nodes.pop(); // Prover drops a node from the decomposition
+    assert!(!cc.verify_release(&release));
+}
@@ -0,0 +1,215 @@
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+use subtle::ConstantTimeEq;
+use crate::curve_parameters::CurveParams;
+use crate::generic_commitments::CurveCommitment;
+use crate::rng::random_scalar;
+use crate::secrets::{bit_to_scalar, xor_bit, xor_randomness, Secret};
+use crate::sigma_ff::ProofScalar;
+use crate::transcript::{Transcript, PRIVATE_BIT_LABEL};
+
+// Noise from several non-colluding servers instead of the prover. Each of the n servers commits to its own n_b
+// private bits with OR-proofs, the public coins follow from the seed the auditor sends once every server is
+// committed, and each server then hands in its share of the noise: the sum of its XORed bits and of the randomness
+// opening them. The final opening is the sum of the prover's and the servers' shares, each checked against the
+// commitments of whoever sent it. No coalition that misses one honest server knows that server's n_b bits, so the
+// release keeps the epsilon of n_b coins against it. The prover holds the inputs and learns the total noise from
+// the release, as in the single server mode.
+
+// Most noise servers per session, each holds a capability token
+pub const MAX_NOISE_SERVERS: usize = 16;
+
+// What a server publishes before the coins: one OR-proof per bit, proof.com being the commitment
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct NoiseContribution {
+    #[schema(value_type = Vec<Object>)]
+    pub proofs: Vec<ProofScalar>,
+}
+
+// A server's additive share of the final opening: the sum of its XORed bits and of their randomness
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct NoiseShare {
+    #[schema(value_type = Vec<u8>)]
+    pub value: Scalar,
+    #[schema(value_type = Vec<u8>)]
+    pub randomness: Scalar,
+}
+
+// Transcript of the OR-proof of bit i of all the noise, server j's bits being j n_b to (j + 1) n_b - 1.
+// Same as the prover's bits in the single server mode.
+pub fn noise_bit_transcript(session_id: &str, i: usize) -> Transcript {
+    Transcript::with_context(PRIVATE_BIT_LABEL, session_id.as_bytes(), i as u64)
+}
+
+// One noise server, holding its private bits between the two rounds
+pub struct NoiseServer {
+    session_id: String,
+    index: usize,
+    bits: Secret<Vec<Scalar>>,
+    randomness: Secret<Vec<Scalar>>,
+}
+
+impl NoiseServer {
+
+    // bits are this server's private coins, fresh and uniform for an honest server
+    pub fn new(session_id: &str, index: usize, bits: &[u8]) -> NoiseServer {
+        Self::new_with_rng(session_id, index, bits, &mut OsRng)
+    }
+
+    pub fn new_with_rng<R: RngCore + CryptoRng>(session_id: &str, index: usize, bits: &[u8], rng: &mut R) -> NoiseServer {
+        let bits: Secret<Vec<Scalar>> = Secret::new(bits.iter().map(|b| bit_to_scalar(*b)).collect());
+        let randomness = Secret::new(bits.iter().map(|_| random_scalar(rng)).collect());
+        Self { session_id: session_id.to_string(), index, bits, randomness }
+    }
+
+    pub fn contribution(&self) -> NoiseContribution {
+        self.contribution_with_rng(&mut OsRng)
+    }
+
+    pub fn contribution_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> NoiseContribution {
+        let params = CurveParams::new();
+        let com = CurveCommitment::new(params.g, params.h);
+        let offset = self.index * self.bits.len();
+        let proofs = self.bits.iter().zip(self.randomness.iter()).enumerate().map(|(k, (bit, r))| {
+            let mut transcript = noise_bit_transcript(&self.session_id, offset + k);
+            com.create_bit_proof(bit.ct_eq(&Scalar::one()), *r, &mut transcript, rng)
+        }).collect();
+        NoiseContribution { proofs }
+    }
+
+    // Share for the public coins of this server's bits. A one coin turns the commitment to b under r into one to
+    // 1 - b under -r, as in BinomialRunner::input_randomness.
+    pub fn share(&self, coins: &[u8]) -> NoiseShare {
+        let (value, randomness) = self.bits.iter().zip(self.randomness.iter()).zip(coins.iter())
+            .fold((Scalar::zero(), Scalar::zero()), |(value, randomness), ((b, r), c)| {
+                let c = bit_to_scalar(*c);
+                (value + xor_bit(b, &c), randomness + xor_randomness(r, &c))
+            });
+        NoiseShare { value, randomness }
+    }
+}
+
+// Why a step of the distributed noise, a seed for the public coins or the release of any noise was refused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseError {
+    NotDistributed, // The session takes its noise from the prover
+    Distributed, // The prover's noise steps are disabled, the noise servers supply it
+    AlreadyContributed,
+    WrongLength { len: usize, expected: usize },
+    InvalidProof,
+    ContributionsOpen, // Not every server has committed to its bits yet
+    AlreadyShared,
+    InvalidShare, // The share does not open the server's XOR commitments
+    SharesMissing,
+    Seeded, // The noise was drawn from a seeded generator, anyone with the seed can subtract it
+    NotCommitted, // A coin seed or a release came before any noise bits were committed, or a seed for a mechanism that takes none
+    CoinsDrawn, // The public coins follow from the first seed, a second one is refused
+    CoinsMissing, // The noise bits are committed but the auditor has not sent the coin seed
+    InputSharesMissing, // Not every aggregator has sent its share of the opening of the client inputs
+    SumOutOfRange, // The opened total does not fit in a u64, so the shares summed to no count
+}
+
+impl fmt::Display for NoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoiseError::NotDistributed => write!(f, "This session takes its noise from the prover"),
+            NoiseError::Distributed => write!(f, "This session takes its noise from the noise servers"),
+            NoiseError::AlreadyContributed => write!(f, "This server already committed to its noise bits"),
+            NoiseError::WrongLength { len, expected } => write!(f, "Got {} noise bits, each server commits to {}", len, expected),
+            NoiseError::InvalidProof => write!(f, "An OR-proof of the noise bits does not verify"),
+            NoiseError::ContributionsOpen => write!(f, "Not every noise server has committed to its bits yet"),
+            NoiseError::AlreadyShared => write!(f, "This server already sent its share"),
+            NoiseError::InvalidShare => write!(f, "The share does not open the XOR commitments of this server"),
+            NoiseError::SharesMissing => write!(f, "Not every noise server has sent its share yet"),
+            NoiseError::Seeded => write!(f, "The noise of a seeded run is predictable and is never released"),
+            NoiseError::NotCommitted => write!(f, "No noise bits are committed yet"),
+            NoiseError::CoinsDrawn => write!(f, "The public coins of this session are already drawn"),
+            NoiseError::CoinsMissing => write!(f, "The auditor has not sent the seed of the public coins yet"),
+            NoiseError::InputSharesMissing => write!(f, "Not every aggregator has sent its share of the client inputs yet"),
+            NoiseError::SumOutOfRange => write!(f, "The opened sum does not fit in 64 bits"),
+        }
+    }
+}
+
+impl NoiseError {
+
+    // Stable code for API clients, independent of the message wording
+    pub fn code(&self) -> &'static str {
+        match self {
+            NoiseError::NotDistributed => "not_distributed",
+            NoiseError::Distributed => "distributed_noise",
+            NoiseError::AlreadyContributed => "already_contributed",
+            NoiseError::WrongLength { .. } => "wrong_noise_length",
+            NoiseError::InvalidProof => "invalid_proof",
+            NoiseError::ContributionsOpen => "contributions_open",
+            NoiseError::AlreadyShared => "already_shared",
+            NoiseError::InvalidShare => "invalid_share",
+            NoiseError::SharesMissing => "shares_missing",
+            NoiseError::Seeded => "seeded_noise",
+            NoiseError::NotCommitted => "noise_not_committed",
+            NoiseError::CoinsDrawn => "coins_drawn",
+            NoiseError::CoinsMissing => "coins_missing",
+            NoiseError::InputSharesMissing => "input_shares_missing",
+            NoiseError::SumOutOfRange => "sum_out_of_range",
+        }
+    }
+}
+
+impl std::error::Error for NoiseError {}
+
+
+// Three in-process servers supply the noise, one of them cheats on its share and is caught
+#[test]
+pub fn test_distributed_noise() {
+    use crate::binomial_runner::BinomialRunner;
+    use rand::Rng;
+
+    let (count, bits) = (3, 32);
+    let x = [1, 0, 1, 1, 0, 1];
+    let mut runner = BinomialRunner::new(&x);
+    runner.set_noise_servers(count, bits);
+    let session_id = runner.get_session_id();
+    let mut rng = rand::thread_rng();
+    let servers: Vec<NoiseServer> = (0..count).map(|j| {
+        let bits: Vec<u8> = (0..bits).map(|_| rng.gen_bool(0.5) as u8).collect();
+        NoiseServer::new(&session_id, j, &bits)
+    }).collect();
+
+    // A proof made for another position does not verify, and no share is taken before every server is committed
+    let stray = NoiseServer::new(&session_id, 1, &[1; 32]).contribution();
+    assert_eq!(runner.add_noise_contribution(0, &stray), Err(NoiseError::InvalidProof));
+    assert_eq!(runner.add_noise_contribution(0, &NoiseContribution { proofs: Vec::new() }), Err(NoiseError::WrongLength { len: 0, expected: 32 }));
+    for (j, server) in servers.iter().enumerate() {
+        assert!(runner.get_public_random().is_empty());
+        assert_eq!(runner.add_noise_share(j, server.share(&[])), Err(NoiseError::ContributionsOpen));
+        runner.add_noise_contribution(j, &server.contribution()).unwrap();
+    }
+    assert_eq!(runner.add_noise_contribution(2, &servers[2].contribution()), Err(NoiseError::AlreadyContributed));
+
+    // The coins wait for the auditor's seed, and only the first seed counts
+    assert!(runner.get_public_random().is_empty());
+    assert_eq!(runner.add_noise_share(0, servers[0].share(&[])), Err(NoiseError::CoinsMissing));
+    assert_eq!(runner.compute_sum(), Err(NoiseError::CoinsMissing));
+    runner.take_coin_seed([7; 32]).unwrap();
+    assert_eq!(runner.take_coin_seed([8; 32]), Err(NoiseError::CoinsDrawn));
+
+    let coins = runner.get_public_random();
+    assert_eq!(coins.len(), count * bits);
+    let mut noise = 0;
+    for (j, server) in servers.iter().enumerate() {
+        let share = server.share(&coins[j * bits..(j + 1) * bits]);
+        let cheat = NoiseShare { value: share.value + Scalar::one(), ..share };
+        assert_eq!(runner.add_noise_share(j, cheat), Err(NoiseError::InvalidShare));
+        assert_eq!(runner.noise_shares_complete(), Err(NoiseError::SharesMissing));
+        assert_eq!(runner.compute_sum(), Err(NoiseError::SharesMissing));
+        runner.add_noise_share(j, share).unwrap();
+        noise += server.bits.iter().zip(&coins[j * bits..]).filter(|(b, c)| (**b == Scalar::one()) != (**c == 1)).count() as u64;
+    }
+    runner.noise_shares_complete().unwrap();
+    assert_eq!(runner.compute_sum(), Ok(4 + noise));
+    runner.commit_pedersons();
+    assert!(runner.verify());
+}
@@ -1,50 +1,309 @@
 use openssl::bn::{BigNum, BigNumContext};
 use openssl::error::ErrorStack;
-use crate::utils::{gen_random, mod_exp};
-use std::ops::Rem;
+use sha3::{Digest, Sha3_512};
+use crate::algebra::{Field, Group, SchnorrGroup};
+use crate::generic_commitments::{self, Pedersen};
+use crate::utils::{calculate_q, mod_exp};
+use std::fmt;
 
+// Pedersen commitments g^m h^r in the subgroup of order q mod p, the OpenSSL backend of generic_commitments::Pedersen
 pub struct Commitment{
-    p: BigNum,
-    q: BigNum,
+    group: SchnorrGroup,
     g: BigNum,
     h: BigNum,
 }
 
 impl Commitment {
 
-    pub fn new(_p: &BigNum, _q: &BigNum, _g: &BigNum, _h: &BigNum)->Commitment{
-
-        let p = &BigNum::new().unwrap() + _p;
-        let q = &BigNum::new().unwrap() + _q;
-        let g = &BigNum::new().unwrap() + _g;
-        let h = &BigNum::new().unwrap() + _h;
-        Self{p, q, g, h}
-
-    }
-    pub fn helper(& self, x1: &BigNum, r: &BigNum, ctx: &mut BigNumContext) -> Result<BigNum, ErrorStack> {
-        // returns g^x1h^r        
-        let tmp3 = mod_exp(&self.g, x1, &self.p, ctx);
-        let tmp4 = mod_exp(&self.h, r, &self.p, ctx);                
-        return Ok((&(tmp3) * &(tmp4)).rem(&self.p));        
-    }  
-    pub fn mult_commitments(&mut self, cm: &[&BigNum]) -> Result<BigNum, ErrorStack> {
-        // Multiply arry of commitments cm
-        let res = (cm.iter().fold(BigNum::from_u32(1)?, |acc, x| &acc * *x)).rem(&self.p);
-        Ok(res)
-    }   
-    pub fn open(&self, c: &BigNum, x: &BigNum, r: &BigNum, ctx: &mut BigNumContext) -> Result<bool, ErrorStack> {
-        // c: commitment
-        // x: the secret
-        // r: array of randomness
-        
-        let res = self.helper(&x, &r, ctx)?;    
-        Ok(&res == c)
-    } 
-
-    pub fn commit(&self, x: &BigNum,  ctx: &mut BigNumContext) -> Result<(BigNum, BigNum), ErrorStack> {
-
-        let r = gen_random(&self.q).unwrap();
-        let c = self.helper(&x, &r, ctx)?;
-        Ok((c, r))
-    }    
-}
\ No newline at end of file
+    pub fn new(p: &BigNum, q: &BigNum, g: &BigNum, h: &BigNum)->Commitment{
+
+        Self{group: SchnorrGroup::new(p, q), g: (**g).to_owned().unwrap(), h: (**h).to_owned().unwrap()}
+    }
+}
+
+impl generic_commitments::Commitment<BigNum, BigNum> for Commitment {
+
+    fn sample_randomness(&self)->BigNum{
+        self.group.scalars().random()
+    }
+
+    fn commit(&self, message: BigNum, randomness: BigNum)->BigNum{
+        self.group.op(&self.group.exp(&self.g, &message), &self.group.exp(&self.h, &randomness))
+    }
+
+    fn open(&self, message: BigNum, randomness: BigNum, com: BigNum)->bool{
+        self.commit(message, randomness) == com
+    }
+}
+
+impl Pedersen for Commitment {
+    type Group = SchnorrGroup;
+
+    fn group(&self)->&SchnorrGroup{
+        &self.group
+    }
+}
+
+// Parameters of Commitment: a prime p, a prime q dividing p - 1, and two generators g and h of the subgroup of
+// order q mod p. Commitments bind only as long as nobody knows log_g h, so h is hashed into the group from p and
+// a fixed label, and anyone can recompute it. g is hashed the same way under another label, or is the generator 2
+// of a standard group, so that neither can be picked as a known power of the other. generate makes a safe prime p = 2q + 1, where the subgroup is the
+// squares mod p; the standard groups are safe primes from RFC 3526 and RFC 7919 with their generator 2.
+#[derive(Debug)]
+pub struct GroupParams {
+    pub p: BigNum,
+    pub q: BigNum,
+    pub g: BigNum,
+    pub h: BigNum,
+}
+
+// Smallest p that validate accepts for parameters in production, as in RFC 7919
+pub const MIN_GROUP_BITS: i32 = 2048;
+
+// Rounds of Miller-Rabin when checking external parameters, which may be chosen adversarially
+const PRIME_CHECKS: i32 = 64;
+
+const HASH_TO_GROUP_LABEL: &[u8] = b"zkdp/finite_field_coms/hash_to_group";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StandardGroup {
+    Modp2048, // RFC 3526, group 14
+    Modp3072, // RFC 3526, group 15
+    Ffdhe2048, // RFC 7919
+    Ffdhe3072,
+}
+
+impl StandardGroup {
+
+    pub const ALL: [StandardGroup; 4] = [StandardGroup::Modp2048, StandardGroup::Modp3072, StandardGroup::Ffdhe2048, StandardGroup::Ffdhe3072];
+
+    pub fn prime(self) -> Result<BigNum, ErrorStack> {
+        match self {
+            StandardGroup::Modp2048 => BigNum::get_rfc3526_prime_2048(),
+            StandardGroup::Modp3072 => BigNum::get_rfc3526_prime_3072(),
+            StandardGroup::Ffdhe2048 => BigNum::from_hex_str(FFDHE2048_P),
+            StandardGroup::Ffdhe3072 => BigNum::from_hex_str(FFDHE3072_P),
+        }
+    }
+}
+
+// RFC 7919, appendix A. OpenSSL only ships the RFC 3526 primes as BigNums.
+const FFDHE2048_P: &str = concat!(
+    "FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695A9E13641146433FBCC939DCE249B3EF9",
+    "7D2FE363630C75D8F681B202AEC4617AD3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935",
+    "984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797ABC0AB182B324FB61D108A94BB2C8E3FB",
+    "B96ADAB760D7F4681D4F42A3DE394DF4AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F61",
+    "9172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005C58EF1837D1683B2C6F34A26C1B2EFFA",
+    "886B423861285C97FFFFFFFFFFFFFFFF",
+);
+
+const FFDHE3072_P: &str = concat!(
+    "FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695A9E13641146433FBCC939DCE249B3EF9",
+    "7D2FE363630C75D8F681B202AEC4617AD3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935",
+    "984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797ABC0AB182B324FB61D108A94BB2C8E3FB",
+    "B96ADAB760D7F4681D4F42A3DE394DF4AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F61",
+    "9172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005C58EF1837D1683B2C6F34A26C1B2EFFA",
+    "886B4238611FCFDCDE355B3B6519035BBC34F4DEF99C023861B46FC9D6E6C9077AD91D2691F7F7EE598CB0FAC186D91C",
+    "AEFE130985139270B4130C93BC437944F4FD4452E2D74DD364F2E21E71F54BFF5CAE82AB9C9DF69EE86D2BC522363A0D",
+    "ABC521979B0DEADA1DBF9A42D5C4484E0ABCD06BFA53DDEF3C1B20EE3FD59D7C25E41D2B66C62E37FFFFFFFFFFFFFFFF",
+);
+
+// Why validate refused a set of parameters
+#[derive(Debug)]
+pub enum ParamError {
+    TooSmall { bits: i32, min: i32 },
+    NotPrime(&'static str), // p or q
+    NoSubgroup, // q does not divide p - 1
+    NotAGenerator(&'static str), // g or h is 0, 1, out of range or outside the subgroup of order q
+    SameGenerators,
+    NotDerived(&'static str), // g or h is not derived from p, so log_g h may be known
+    Openssl(ErrorStack),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamError::TooSmall { bits, min } => write!(f, "p has {} bits, at least {} are needed", bits, min),
+            ParamError::NotPrime(name) => write!(f, "{} is not prime", name),
+            ParamError::NoSubgroup => write!(f, "q does not divide p - 1"),
+            ParamError::NotAGenerator(name) => write!(f, "{} does not generate the subgroup of order q", name),
+            ParamError::SameGenerators => write!(f, "g and h are the same"),
+            ParamError::NotDerived(name) => write!(f, "{} is not derived from p by hashing to the group", name),
+            ParamError::Openssl(e) => write!(f, "OpenSSL error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl From<ErrorStack> for ParamError {
+    fn from(e: ErrorStack) -> Self {
+        ParamError::Openssl(e)
+    }
+}
+
+// Element of the subgroup of order q derived from p and label. Hashes to an integer mod p with 128 extra bits and
+// raises it to the cofactor (p - 1) / q, counting up until the result is not 1. Nobody knows its discrete log
+// to any other base.
+pub fn hash_to_group(p: &BigNum, q: &BigNum, label: &[u8], ctx: &mut BigNumContext) -> Result<BigNum, ErrorStack> {
+    let one = BigNum::from_u32(1)?;
+    let cofactor = &(p - &one) / q;
+    let p_bytes = p.to_vec();
+    let len = p_bytes.len() + 16;
+    for counter in 0u32.. {
+        let mut bytes = Vec::with_capacity(len + 64);
+        for block in 0u32.. {
+            if bytes.len() >= len {
+                break;
+            }
+            let out = Sha3_512::new()
+                .chain_update(HASH_TO_GROUP_LABEL)
+                .chain_update((p_bytes.len() as u64).to_be_bytes())
+                .chain_update(&p_bytes)
+                .chain_update((label.len() as u64).to_be_bytes())
+                .chain_update(label)
+                .chain_update(counter.to_be_bytes())
+                .chain_update(block.to_be_bytes())
+                .finalize();
+            bytes.extend_from_slice(&out);
+        }
+        let wide = BigNum::from_slice(&bytes[..len])?;
+        let mut x = BigNum::new()?;
+        x.nnmod(&wide, p, ctx)?;
+        let y = mod_exp(&x, &cofactor, p, ctx);
+        if y != one && y.num_bits() > 0 {
+            return Ok(y);
+        }
+    }
+    unreachable!("Counter exhausted")
+}
+
+impl GroupParams {
+
+    // A fresh safe prime p of bits bits, with g and h both hashed into the squares mod p
+    pub fn generate(bits: i32, ctx: &mut BigNumContext) -> Result<GroupParams, ErrorStack> {
+        let _span = tracing::info_span!("group_params", bits).entered();
+        let mut p = BigNum::new()?;
+        p.generate_prime(bits, true, None, None)?;
+        let q = calculate_q(&p)?;
+        let g = hash_to_group(&p, &q, b"g", ctx)?;
+        let h = hash_to_group(&p, &q, b"h", ctx)?;
+        tracing::debug!(bits = p.num_bits(), "Generated the commitment group");
+        Ok(Self { p, q, g, h })
+    }
+
+    // One of the standard safe prime groups, with their generator 2 as g and h hashed into the group
+    pub fn standard(group: StandardGroup, ctx: &mut BigNumContext) -> Result<GroupParams, ErrorStack> {
+        let p = group.prime()?;
+        let q = calculate_q(&p)?;
+        let h = hash_to_group(&p, &q, b"h", ctx)?;
+        Ok(Self { p, q, g: BigNum::from_u32(2)?, h })
+    }
+
+    // Checks externally supplied parameters: p of at least min_bits bits, p and q prime, q dividing p - 1,
+    // g and h distinct elements of order q, h hashed from p so that its discrete log is unknown, and g hashed from p
+    // as well or the generator 2 of a standard group. A g chosen freely could be h^k, whose log_g h is 1/k.
+    pub fn validate(&self, min_bits: i32, ctx: &mut BigNumContext) -> Result<(), ParamError> {
+        if self.p.num_bits() < min_bits {
+            return Err(ParamError::TooSmall { bits: self.p.num_bits(), min: min_bits });
+        }
+        if !self.p.is_prime(PRIME_CHECKS, ctx)? {
+            return Err(ParamError::NotPrime("p"));
+        }
+        if !self.q.is_prime(PRIME_CHECKS, ctx)? {
+            return Err(ParamError::NotPrime("q"));
+        }
+        let one = BigNum::from_u32(1)?;
+        let mut rem = BigNum::new()?;
+        rem.nnmod(&(&self.p - &one), &self.q, ctx)?;
+        if rem.num_bits() > 0 {
+            return Err(ParamError::NoSubgroup);
+        }
+        for (name, x) in [("g", &self.g), ("h", &self.h)] {
+            if *x <= one || *x >= self.p || mod_exp(x, &self.q, &self.p, ctx) != one {
+                return Err(ParamError::NotAGenerator(name));
+            }
+        }
+        if self.g == self.h {
+            return Err(ParamError::SameGenerators);
+        }
+        if self.h != hash_to_group(&self.p, &self.q, b"h", ctx)? {
+            return Err(ParamError::NotDerived("h"));
+        }
+        let mut standard = false;
+        for group in StandardGroup::ALL {
+            standard |= self.g == BigNum::from_u32(2)? && self.p == group.prime()?;
+        }
+        if !standard && self.g != hash_to_group(&self.p, &self.q, b"g", ctx)? {
+            return Err(ParamError::NotDerived("g"));
+        }
+        Ok(())
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        Commitment::new(&self.p, &self.q, &self.g, &self.h)
+    }
+}
+
+
+#[test]
+pub fn test_generated_group() {
+    use crate::generic_commitments::Commitment as _;
+
+    let mut ctx = BigNumContext::new().unwrap();
+    let params = GroupParams::generate(256, &mut ctx).unwrap();
+    params.validate(256, &mut ctx).unwrap();
+    assert!(matches!(params.validate(MIN_GROUP_BITS, &mut ctx), Err(ParamError::TooSmall { bits: 256, min: 2048 })));
+
+    // Commit, open, and multiply two commitments into one to the sum under the summed randomness
+    let com = params.commitment();
+    let scalars = com.group().scalars();
+    let dup = |x: &BigNum| (**x).to_owned().unwrap();
+    let (r1, r2) = (com.sample_randomness(), com.sample_randomness());
+    let c1 = com.commit(scalars.element(20), dup(&r1));
+    let c2 = com.commit(scalars.element(22), dup(&r2));
+    assert!(com.open(scalars.element(20), dup(&r1), dup(&c1)));
+    assert!(!com.open(scalars.element(22), dup(&r1), dup(&c1)));
+    let c = com.combine(&[c1, c2]);
+    assert!(com.open(scalars.element(42), scalars.add(&r1, &r2), c));
+
+    // Tampered parameters
+    let copy = |params: &GroupParams| GroupParams { p: (*params.p).to_owned().unwrap(), q: (*params.q).to_owned().unwrap(), g: (*params.g).to_owned().unwrap(), h: (*params.h).to_owned().unwrap() };
+    let mut known_log = copy(&params);
+    known_log.h = mod_exp(&params.g, &BigNum::from_u32(7).unwrap(), &params.p, &mut ctx);
+    assert!(matches!(known_log.validate(256, &mut ctx), Err(ParamError::NotDerived("h"))));
+    let mut known_g = copy(&params);
+    known_g.g = mod_exp(&params.h, &BigNum::from_u32(7).unwrap(), &params.p, &mut ctx); // log_g h = 1/7
+    assert!(matches!(known_g.validate(256, &mut ctx), Err(ParamError::NotDerived("g"))));
+    let mut small_g = copy(&params);
+    small_g.g = BigNum::from_u32(4).unwrap(); // A square, but p is not a standard group
+    assert!(matches!(small_g.validate(256, &mut ctx), Err(ParamError::NotDerived("g"))));
+    let mut order_two = copy(&params);
+    order_two.g = &params.p - &BigNum::from_u32(1).unwrap();
+    assert!(matches!(order_two.validate(256, &mut ctx), Err(ParamError::NotAGenerator("g"))));
+    let mut same = copy(&params);
+    same.g = copy(&params).h;
+    assert!(matches!(same.validate(256, &mut ctx), Err(ParamError::SameGenerators)));
+    let mut composite = copy(&params);
+    composite.q = &params.q + &BigNum::from_u32(2).unwrap();
+    composite.q = &composite.q * &BigNum::from_u32(3).unwrap();
+    assert!(matches!(composite.validate(256, &mut ctx), Err(ParamError::NotPrime("q"))));
+}
+
+#[test]
+pub fn test_standard_groups() {
+    use crate::generic_commitments::Commitment as _;
+
+    let mut ctx = BigNumContext::new().unwrap();
+    for group in StandardGroup::ALL {
+        let params = GroupParams::standard(group, &mut ctx).unwrap();
+        params.validate(MIN_GROUP_BITS, &mut ctx).unwrap();
+
+        let pedersen = params.commitment();
+        let scalars = pedersen.group().scalars();
+        let (m1, m2) = (scalars.element(3), scalars.element(4));
+        let (r1, r2) = (pedersen.sample_randomness(), pedersen.sample_randomness());
+        let c = pedersen.combine(&[pedersen.commit(m1, (*r1).to_owned().unwrap()), pedersen.commit(m2, (*r2).to_owned().unwrap())]);
+        assert!(pedersen.open(scalars.element(7), scalars.add(&r1, &r2), c), "{:?}", group);
+    }
+}
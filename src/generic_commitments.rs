@@ -1,9 +1,13 @@
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 // use rand::{rngs::StdRng, RngCore, SeedableRng};
-use rand_core::OsRng;
-use sha3::{Digest, Sha3_256};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use crate::algebra::{Field, Group, RistrettoGroup, ScalarOf};
 use crate::sigma_ff::ProofScalar;
+use crate::transcript::Transcript;
+use crate::rng::random_scalar;
+use crate::sigma_protocols::{bit_relation, prove_bit_with_rng, verify};
+use subtle::Choice;
 // use crate::converters::u32_to_bytes;
 
 pub trait Commitment<X, Y>{
@@ -14,8 +18,47 @@ pub trait Commitment<X, Y>{
 }
 
 pub trait SecretSharing{
-    fn share(&self, message: &[u8; 32], num_shares: usize)->Vec<[u8;32]>;
-    fn reconstruct(&self, shares: &Vec<[u8; 32]>, num_shares: usize)->[u8; 32];
+    type Secret;
+    type Share;
+
+    fn share(&self, secret: &Self::Secret, num_shares: usize)->Vec<Self::Share>;
+    fn reconstruct(&self, shares: &[Self::Share])->Self::Secret;
+}
+
+// n-of-n additive sharing over any field, as the Prio and Poplar clients share their votes
+pub struct AdditiveSharing<F: Field>{
+    pub field: F,
+}
+
+impl<F: Field> SecretSharing for AdditiveSharing<F>{
+    type Secret = F::Elem;
+    type Share = F::Elem;
+
+    fn share(&self, secret: &F::Elem, num_shares: usize)->Vec<F::Elem>{
+
+        let mut shares: Vec<F::Elem> = (1..num_shares).map(|_| self.field.random()).collect();
+        let last = self.field.sub(secret, &self.field.sum(&shares));
+        shares.push(last);
+        shares
+    }
+
+    fn reconstruct(&self, shares: &[F::Elem])->F::Elem{
+        self.field.sum(shares)
+    }
+}
+
+// Pedersen commitments g^m h^r in a prime order group, see algebra::Group. CurveCommitment is the Ristretto
+// backend and finite_field_coms::Commitment the OpenSSL one, so code generic over the backend takes a P: Pedersen.
+pub trait Pedersen: Commitment<ScalarOf<Self::Group>, <Self::Group as Group>::Elem>{
+    type Group: Group;
+
+    fn group(&self)->&Self::Group;
+
+    // Commitment to the sum of the messages under the sum of the randomness
+    fn combine(&self, coms: &[<Self::Group as Group>::Elem])-><Self::Group as Group>::Elem{
+        let group = self.group();
+        coms.iter().fold(group.identity(), |acc, com| group.op(&acc, com))
+    }
 }
 
 pub struct CurveCommitment {
@@ -28,73 +71,41 @@ impl CurveCommitment{
         return Self{g, h};
     }   
 
-    pub fn create_proof_0(&self, rand: Scalar)->ProofScalar{
+    // OR-proof that the commitment to `bit` under `rand` opens to 0 or 1, as an instance of the
+    // 1-out-of-2 composition in sigma_protocols. The proof for the other bit is simulated, and the bit only
+    // selects between the two branches in constant time, see sigma_protocols::prove_bit_with_rng.
+    pub fn create_bit_proof<R: RngCore + CryptoRng>(&self, bit: Choice, rand: Scalar, transcript: &mut Transcript, rng: &mut R)->ProofScalar{
 
-        // create FIAT shamir proof for when the secret is 0
-        // let mut hasher = Sha3_256::new();
-        let com = self.commit(Scalar::zero(), rand);
-        let v1 = self.sample_randomness();
-        let e1 = self.sample_randomness();
-        let b = self.sample_randomness();
-
-        // d1 : Cheat
-        let ce1 = -(&com * &e1); // 1/c^{e1}
-        let ge1 = &self.g * &e1; // g^{e1}
-        let hv1 = &self.h * &v1;      
-        let d1 = hv1 + ce1 + ge1; //h^{v1} x 1/c^{e1} x g^{e1}
-
-        // d0 : Honest
-        let d0 = &b * &self.h; // h^{b}  
-
-        let mut hasher = Sha3_256::new();
-        let mut input_to_rom: Vec<u8> = Vec::new();
-        input_to_rom.extend(com.compress().as_bytes());        
-        input_to_rom.extend(d0.compress().as_bytes());
-        input_to_rom.extend(d1.compress().as_bytes());
-        hasher.update(input_to_rom); // this will take d0, d1, commitment as a byte array
-        let result: [u8;32] =  hasher.finalize().into();        
-        let e = Scalar::from_bytes_mod_order(result); // In the interactive version this would come in round 2
-        
-        let e0 = e - e1;         
-        let v0 = b + e0*rand;
+        transcript.append_generators(&self.g, &self.h);
+        let (com, proof) = prove_bit_with_rng(self, bit, rand, transcript, rng);
+        ProofScalar::from_fiat_shamir(com, &proof)
+    }
 
-        return ProofScalar{com, e0, e1, e, v0, v1, d0, d1};
+    pub fn create_proof_0(&self, rand: Scalar, transcript: &mut Transcript)->ProofScalar{
 
+        // create FIAT shamir proof for when the secret is 0
+        // d1 is simulated, d0 is honest
+        self.create_bit_proof(Choice::from(0), rand, transcript, &mut OsRng)
     }
-    
 
-    pub fn create_proof_1(&self, rand: Scalar)->ProofScalar{
+    pub fn create_proof_1(&self, rand: Scalar, transcript: &mut Transcript)->ProofScalar{
 
-        // create FIAT shamir proof for when the secret is 0
-        // let mut hasher = Sha3_256::new();
-        let com = self.commit(Scalar::one(), rand);
-
-        let v0 = self.sample_randomness();
-        let e0 = self.sample_randomness();
-        let b = self.sample_randomness();
-
-        // d0 : Cheat
-        let ce0 = -(&com * &e0); // 1/c^{e0}
-        let hv0 = &self.h * &v0; // h^{0}     
-        let d0 = hv0 + ce0; //h^{v0} x 1/c^{e0} 
-
-        // d1 : Honest
-        let d1 = &b * &self.h; // h^{b}  
-
-        let mut hasher = Sha3_256::new();
-        let mut input_to_rom: Vec<u8> = Vec::new();
-        input_to_rom.extend(com.compress().as_bytes());        
-        input_to_rom.extend(d0.compress().as_bytes());
-        input_to_rom.extend(d1.compress().as_bytes());
-        hasher.update(input_to_rom); // this will take d0, d1, commitment as a byte array
-        let result: [u8;32] =  hasher.finalize().into();        
-        let e = Scalar::from_bytes_mod_order(result); // In the interactive version this would come in round 2
-        
-        let e1 = e - e0;         
-        let v1 = b + e1*rand;
+        // create FIAT shamir proof for when the secret is 1
+        // d0 is simulated, d1 is honest
+        self.create_bit_proof(Choice::from(1), rand, transcript, &mut OsRng)
+    }
 
-        return ProofScalar{com, e0, e1, e, v0, v1, d0, d1};
+    // Same as Commitment::sample_randomness, from rng
+    pub fn sample_randomness_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R)->Scalar{
+        random_scalar(rng)
+    }
 
+    // Checks an OR-proof made by create_proof_0 or create_proof_1 against the same transcript context
+    pub fn verify_bit_proof(&self, proof: &ProofScalar, transcript: &mut Transcript)->bool{
+
+        let relation = bit_relation(self, proof.com);
+        transcript.append_generators(&self.g, &self.h);
+        verify(&relation, &proof.to_fiat_shamir(), transcript)
     }
 
 }
@@ -123,3 +134,55 @@ impl Commitment<Scalar, RistrettoPoint> for CurveCommitment{
     
 }
 
+impl Pedersen for CurveCommitment{
+    type Group = RistrettoGroup;
+
+    fn group(&self)->&RistrettoGroup{
+        &RistrettoGroup
+    }
+}
+
+
+// Both backends commit homomorphically through the generic interface
+#[test]
+pub fn test_pedersen_backends(){
+
+    use openssl::bn::BigNum;
+    use crate::curve_parameters::CurveParams;
+    use crate::finite_field_coms;
+
+    fn check_homomorphic<P: Pedersen>(pedersen: &P){
+        let field = pedersen.group().scalars();
+        let (m1, m2) = (field.element(3), field.element(4));
+        let (r1, r2) = (pedersen.sample_randomness(), pedersen.sample_randomness());
+        let (m, r) = (field.add(&m1, &m2), field.add(&r1, &r2));
+        let com = pedersen.combine(&[pedersen.commit(m1, r1), pedersen.commit(m2, r2)]);
+        assert!(pedersen.open(m, r, com));
+    }
+
+    let params = CurveParams::new();
+    check_homomorphic(&CurveCommitment::new(params.g, params.h));
+    // 2039 = 2 * 1019 + 1, and 4 and 9 generate the squares mod 2039
+    let (p, q) = (BigNum::from_u32(2039).unwrap(), BigNum::from_u32(1019).unwrap());
+    check_homomorphic(&finite_field_coms::Commitment::new(&p, &q, &BigNum::from_u32(4).unwrap(), &BigNum::from_u32(9).unwrap()));
+}
+
+#[test]
+pub fn test_additive_sharing(){
+
+    use crate::algebra::{BigNumField, ScalarField};
+    use crate::public_parameters::PublicParams;
+
+    fn check_sharing<F: Field>(field: F){
+        let sharing = AdditiveSharing{ field };
+        let secret = sharing.field.random();
+        let shares = sharing.share(&secret, 3);
+        assert_eq!(shares.len(), 3);
+        assert_eq!(sharing.reconstruct(&shares), secret);
+        assert_ne!(sharing.reconstruct(&shares[..2]), secret);
+    }
+
+    check_sharing(ScalarField);
+    let public_param = PublicParams::new(128, 3).unwrap();
+    check_sharing(BigNumField::new(&public_param.q));
+}
@@ -0,0 +1,237 @@
+use curve25519_dalek::constants::BASEPOINT_ORDER;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use openssl::bn::{BigNum, BigNumContext};
+use rand::Rng;
+use rand_core::{OsRng, RngCore};
+use tracing::{info_span, warn};
+use crate::beaver_triple::BeaverTriple;
+use crate::binomial_runner::BinomialRunner;
+use crate::curve_parameters::CurveParams;
+use crate::distributed_noise::{NoiseServer, NoiseShare};
+use crate::generic_commitments::{Commitment, CurveCommitment};
+use crate::idpf::{EvalState, IdpfKey};
+use crate::poplar;
+use crate::rng::random_scalar;
+use crate::verifiable_client::{PoplarVote, Share};
+
+// Private heavy hitters with two servers, as in Poplar. Every client encodes its bit-string alpha as a pair of
+// IDPF keys with output (1, kappa) on each prefix of alpha. The servers walk the prefix tree level by level: they
+// evaluate the keys on the children of the prefixes still alive, check with Poplar's sketch that every client
+// contributes at most once and with weight 1, and add up the accepted outputs. No count is ever opened on its own:
+// each server commits to its share of a count, commits to binomial noise bits of its own and opens its share plus
+// its noise, so the aggregator only sees the noisy count and anyone can check the noise was added faithfully.
+// Prefixes whose noisy count falls below the threshold are pruned with their subtrees.
+// The shares are taken mod the order of the Ristretto group, see group_order, so they are shares of the committed
+// counts as they are.
+
+// Correlated randomness for the sketch of one level, see verifiable_client::Client::get_ab_for_poplar
+pub struct LevelSketch {
+    pub triple: BeaverTriple, // Client-generated
+    pub ucase_a: Share,
+    pub ucase_b: Share,
+}
+
+// Everything a client sends for its string, one key per server and one sketch per level
+pub struct HeavyHitterReport {
+    pub keys: (IdpfKey, IdpfKey),
+    pub levels: Vec<LevelSketch>,
+}
+
+// Noisy count of a prefix with the run of the binomial mechanism that produced it
+pub struct PrefixCount {
+    pub prefix: Vec<bool>,
+    pub noisy_count: u64,
+    pub runner: BinomialRunner,
+}
+
+impl PrefixCount {
+
+    // Unbiased estimate of the count, the noise has mean n_b / 2
+    pub fn estimate(&self) -> f64 {
+        self.noisy_count as f64 - self.runner.num_noise_bits() as f64 / 2.0
+    }
+}
+
+pub struct HeavyHitters {
+    pub hitters: Vec<PrefixCount>, // Full-length strings whose estimate reaches the threshold
+    pub rejected: Vec<usize>, // Reports that failed a sketch or were malformed
+    pub rejected_prefixes: Vec<Vec<bool>>, // Prefixes whose release failed its checks or came out of range, pruned
+}
+
+// Modulus q of the IDPF outputs and the sketches. Clients build their reports over it too.
+pub fn group_order() -> BigNum {
+    let mut bytes = BASEPOINT_ORDER.to_bytes();
+    bytes.reverse();
+    BigNum::from_slice(&bytes).expect("32 bytes fit a BigNum")
+}
+
+// A server's share of a count as a scalar, None unless it is reduced mod group_order
+fn share_to_scalar(share: &BigNum) -> Option<Scalar> {
+    let mut bytes: [u8; 32] = share.to_vec_padded(32).ok()?.try_into().ok()?;
+    bytes.reverse();
+    Scalar::from_canonical_bytes(bytes)
+}
+
+// Releases the count the two servers hold in shares through the binomial mechanism, each server adding noise_bits
+// noise bits of its own. The aggregator draws the seed of the public coins once both servers are committed.
+fn release(shares: &[Scalar; 2], noise_bits: usize) -> Option<(u64, BinomialRunner)> {
+    let params = CurveParams::new();
+    let com = CurveCommitment::new(params.g, params.h);
+    let randomness = [random_scalar(&mut OsRng), random_scalar(&mut OsRng)];
+    let share_coms: Vec<RistrettoPoint> = shares.iter().zip(randomness.iter()).map(|(s, r)| com.commit(*s, *r)).collect();
+    let mut runner = BinomialRunner::shared_aggregate(&share_coms, noise_bits);
+    let session_id = runner.get_session_id();
+
+    let mut rng = rand::thread_rng();
+    let servers: Vec<NoiseServer> = (0..2).map(|j| {
+        let bits: Vec<u8> = (0..noise_bits).map(|_| rng.gen_bool(0.5) as u8).collect();
+        NoiseServer::new(&session_id, j, &bits)
+    }).collect();
+    for (j, server) in servers.iter().enumerate() {
+        runner.add_noise_contribution(j, &server.contribution()).ok()?;
+    }
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    runner.take_coin_seed(seed).ok()?;
+    let coins = runner.get_public_random();
+    for (j, server) in servers.iter().enumerate() {
+        let noise = server.share(&coins[j * noise_bits..(j + 1) * noise_bits]);
+        let share = NoiseShare { value: noise.value + shares[j], randomness: noise.randomness + randomness[j] };
+        runner.add_noise_share(j, share).ok()?;
+    }
+    let noisy_count = runner.compute_sum().ok()?;
+    runner.commit_pedersons();
+    Some((noisy_count, runner))
+}
+
+// Runs the walk over the bits-long strings of the reports, built over group_order. threshold applies to
+// PrefixCount::estimate at every level, noise_bits is the noise of each of the two servers.
+pub fn heavy_hitters(reports: Vec<HeavyHitterReport>, bits: usize, threshold: f64, noise_bits: usize, ctx: &mut BigNumContext) -> HeavyHitters {
+    let q = &group_order();
+    let (keys, mut sketches): (Vec<_>, Vec<_>) = reports.into_iter().map(|report| {
+        let well_formed = report.keys.0.party == 0 && report.keys.1.party == 1 && report.levels.len() == bits
+            && report.keys.0.levels() == bits && report.keys.1.levels() == bits;
+        (report.keys, well_formed.then(|| report.levels.into_iter()))
+    }).unzip();
+    let mut valid: Vec<bool> = sketches.iter().map(Option::is_some).collect();
+
+    // Per report, the state of both keys at every live prefix
+    let mut states: Vec<Vec<[EvalState; 2]>> = keys.iter().map(|(k0, k1)| vec![[k0.root(), k1.root()]]).collect();
+    let mut prefixes: Vec<Vec<bool>> = vec![Vec::new()];
+    let mut counts = Vec::new();
+    let mut rejected_prefixes = Vec::new();
+    for level in 0..bits {
+        let _span = info_span!("step", step = "heavy_hitters_level", level, prefixes = prefixes.len()).entered();
+        let children: Vec<(usize, bool)> = (0..prefixes.len()).flat_map(|p| [(p, false), (p, true)]).collect();
+
+        // Both servers evaluate their key of every live report on every child
+        let mut voters = Vec::new();
+        let mut votes = Vec::new();
+        for (r, (k0, k1)) in keys.iter().enumerate() {
+            let sketch = sketches[r].as_mut().and_then(|levels| levels.next());
+            let Some(sketch) = sketch.filter(|_| valid[r]) else { continue };
+            let mut next = Vec::with_capacity(children.len());
+            let (mut vote, mut kvote) = (Vec::new(), Vec::new());
+            for (p, bit) in &children {
+                let (s0, [y0, ky0]) = k0.eval_next(&states[r][*p][0], level, *bit, q, ctx);
+                let (s1, [y1, ky1]) = k1.eval_next(&states[r][*p][1], level, *bit, q, ctx);
+                next.push([s0, s1]);
+                vote.push(Share { shares: vec![y0, y1] });
+                kvote.push(Share { shares: vec![ky0, ky1] });
+            }
+            states[r] = next;
+            voters.push(r);
+            votes.push(PoplarVote { vote, kvote, triple: sketch.triple, ucase_a: sketch.ucase_a, ucase_b: sketch.ucase_b });
+        }
+
+        let mut servers: Vec<poplar::Server> = (0..2).map(|_| poplar::Server::new(2, children.len(), q)).collect();
+        let accepted = poplar::collect_votes(&mut servers, &votes, ctx);
+        for (r, ok) in voters.iter().zip(accepted.iter()) {
+            valid[*r] &= ok;
+        }
+        let num_inputs = accepted.iter().filter(|ok| **ok).count();
+
+        // Each accepted client adds at most 1 to a level, so a noisy count above num_inputs plus all the noise
+        // means the shares do not add up to a count. Such a prefix is rejected rather than released.
+        let mut alive = Vec::new();
+        counts.clear();
+        for (i, (p, bit)) in children.iter().enumerate() {
+            let mut prefix = prefixes[*p].clone();
+            prefix.push(*bit);
+            let shares = [share_to_scalar(&servers[0].totals[i]), share_to_scalar(&servers[1].totals[i])];
+            let release = match shares {
+                [Some(s0), Some(s1)] => release(&[s0, s1], noise_bits),
+                _ => None,
+            };
+            let Some((noisy_count, runner)) = release.filter(|(noisy_count, _)| *noisy_count <= (num_inputs + 2 * noise_bits) as u64) else {
+                warn!(level, ?prefix, "Rejected a prefix whose count shares do not open to a count");
+                rejected_prefixes.push(prefix);
+                continue;
+            };
+            let prefix_count = PrefixCount { prefix, noisy_count, runner };
+            if prefix_count.estimate() >= threshold {
+                alive.push(i);
+                counts.push(prefix_count);
+            }
+        }
+
+        // Prune the children below the threshold, along with their states
+        prefixes = counts.iter().map(|c| c.prefix.clone()).collect();
+        for r in &voters {
+            states[*r] = alive.iter().map(|i| states[*r][*i]).collect();
+        }
+        if prefixes.is_empty() {
+            break;
+        }
+    }
+
+    let rejected = valid.iter().enumerate().filter(|(_, ok)| !**ok).map(|(r, _)| r).collect();
+    HeavyHitters { hitters: counts, rejected, rejected_prefixes }
+}
+
+
+#[test]
+pub fn test_heavy_hitters() {
+    use crate::idpf;
+    use crate::verifiable_client::Client;
+
+    let (q, ctx) = (&group_order(), &mut BigNumContext::new().unwrap());
+    let strings: Vec<([bool; 3], usize)> = vec![([true, false, true], 8), ([true, true, false], 6), ([false, true, true], 1), ([false, false, false], 1)];
+    let mut reports = Vec::new();
+    for (alpha, times) in &strings {
+        for _ in 0..*times {
+            reports.push(Client::new(2, 2, q).heavy_hitter_report(alpha, ctx));
+        }
+    }
+
+    // A client counting itself twice on the second level
+    let client = Client::new(2, 2, q);
+    let mut report = client.heavy_hitter_report(&[true, false, true], ctx);
+    let one = BigNum::from_u32(1).unwrap();
+    let betas: Vec<idpf::Output> = (0..3).map(|l| {
+        let weight = BigNum::from_u32(if l == 1 { 2 } else { 1 }).unwrap();
+        [&one * &weight, &client.kappa * &weight]
+    }).collect();
+    report.keys = idpf::gen(&[true, false, true], &betas, q, ctx);
+    reports.push(report);
+
+    // 2 noise bits from each server move a count by at most 2 from its estimate, so the threshold of 4 separates
+    // 8 and 6 from 1
+    let result = heavy_hitters(reports, 3, 4.0, 2, ctx);
+    assert_eq!(result.rejected, vec![16]);
+    assert!(result.rejected_prefixes.is_empty());
+    assert!(share_to_scalar(q).is_none()); // A total that is not reduced is no share of a count
+    let found: Vec<Vec<bool>> = result.hitters.iter().map(|c| c.prefix.clone()).collect();
+    assert_eq!(found, vec![vec![true, false, true], vec![true, true, false]]);
+    for (hitter, count) in result.hitters.iter().zip([8, 6]) {
+        assert!(hitter.runner.verify());
+        assert!((hitter.estimate() - count as f64).abs() <= 2.0);
+    }
+
+    // Shares of 3 open to a count, with 2^64 added to one of them they would pass a check of the low 64 bits alone
+    let s0 = random_scalar(&mut OsRng);
+    let offset = Scalar::from(u64::MAX) + Scalar::one();
+    assert!(release(&[s0, Scalar::from(3u64) - s0], 2).is_some());
+    assert!(release(&[s0 + offset, Scalar::from(3u64) - s0], 2).is_none());
+}
@@ -0,0 +1,198 @@
+use openssl::bn::{BigNum, BigNumContext};
+use rand_core::{OsRng, RngCore};
+use sha3::{Digest, Sha3_512};
+
+// Incremental distributed point function for two servers (Boneh et al., Lightweight Techniques for Private Heavy
+// Hitters, 2021). gen(alpha, beta_1..beta_n) gives one key per server such that for every bit-string p of length l
+// the two evaluations at p add up to beta_l mod q if p is a prefix of alpha, and to 0 otherwise. Either key alone
+// looks random. It is the tree DPF of Boyle, Gilboa and Ishai (2016) with an output correction word at every level.
+// Outputs are pairs, (1, kappa) per level for Poplar's sketch, see poplar::validate_vote.
+
+const SEED_LEN: usize = 16;
+type Seed = [u8; SEED_LEN];
+
+// Output of one node, a pair of elements mod q
+pub type Output = [BigNum; 2];
+
+struct CorrectionWord {
+    seed: Seed,
+    t_left: bool,
+    t_right: bool,
+    output: Output,
+}
+
+pub struct IdpfKey {
+    pub party: u8, // 0 or 1
+    seed: Seed,
+    correction_words: Vec<CorrectionWord>,
+}
+
+// A node of the tree as one party sees it. Servers keep it for every surviving prefix so its children are one step away.
+#[derive(Clone, Copy)]
+pub struct EvalState {
+    seed: Seed,
+    t: bool,
+}
+
+// Length-doubling PRG: the seeds and control bits of both children
+fn expand(seed: &Seed) -> (Seed, bool, Seed, bool) {
+    let out = Sha3_512::new().chain_update(b"zkdp/idpf/expand").chain_update(seed).finalize();
+    let mut left = [0u8; SEED_LEN];
+    let mut right = [0u8; SEED_LEN];
+    left.copy_from_slice(&out[..SEED_LEN]);
+    right.copy_from_slice(&out[SEED_LEN..2 * SEED_LEN]);
+    (left, out[32] & 1 == 1, right, out[33] & 1 == 1)
+}
+
+// Maps a seed to an output, independently of the seeds expand derives from it. 256 bits per element keep the
+// reduction mod q close to uniform for q of up to 128 bits.
+fn convert(seed: &Seed, q: &BigNum, ctx: &mut BigNumContext) -> Output {
+    let out = Sha3_512::new().chain_update(b"zkdp/idpf/convert").chain_update(seed).finalize();
+    let element = |bytes: &[u8], ctx: &mut BigNumContext| {
+        let mut x = BigNum::new().unwrap();
+        x.nnmod(&BigNum::from_slice(bytes).unwrap(), q, ctx).unwrap();
+        x
+    };
+    [element(&out[..32], ctx), element(&out[32..], ctx)]
+}
+
+fn xor(a: &Seed, b: &Seed) -> Seed {
+    let mut out = *a;
+    out.iter_mut().zip(b.iter()).for_each(|(x, y)| *x ^= y);
+    out
+}
+
+fn random_seed() -> Seed {
+    let mut seed = [0u8; SEED_LEN];
+    OsRng.fill_bytes(&mut seed);
+    seed
+}
+
+fn mod_add(a: &BigNum, b: &BigNum, q: &BigNum, ctx: &mut BigNumContext) -> BigNum {
+    let mut out = BigNum::new().unwrap();
+    out.mod_add(a, b, q, ctx).unwrap();
+    out
+}
+
+fn mod_sub(a: &BigNum, b: &BigNum, q: &BigNum, ctx: &mut BigNumContext) -> BigNum {
+    let mut out = BigNum::new().unwrap();
+    out.mod_sub(a, b, q, ctx).unwrap();
+    out
+}
+
+fn negate(x: &Output, q: &BigNum, ctx: &mut BigNumContext) -> Output {
+    let zero = BigNum::new().unwrap();
+    [mod_sub(&zero, &x[0], q, ctx), mod_sub(&zero, &x[1], q, ctx)]
+}
+
+// Keys for the point alpha with output betas[l] at its prefix of length l + 1. betas has one entry per bit of alpha.
+pub fn gen(alpha: &[bool], betas: &[Output], q: &BigNum, ctx: &mut BigNumContext) -> (IdpfKey, IdpfKey) {
+    assert_eq!(alpha.len(), betas.len());
+    let roots = [random_seed(), random_seed()];
+    let (mut s, mut t) = (roots, [false, true]);
+    let mut correction_words = Vec::with_capacity(alpha.len());
+    for (bit, beta) in alpha.iter().zip(betas.iter()) {
+        let children = [expand(&s[0]), expand(&s[1])];
+        // The children on the path of alpha are kept, the others lose the difference between the two parties
+        let side = |c: &(Seed, bool, Seed, bool), right: bool| if right { (c.2, c.3) } else { (c.0, c.1) };
+        let (lose0, lose1) = (side(&children[0], !bit), side(&children[1], !bit));
+        let seed = xor(&lose0.0, &lose1.0);
+        let t_left = children[0].1 ^ children[1].1 ^ bit ^ true;
+        let t_right = children[0].3 ^ children[1].3 ^ bit;
+        let t_keep = if *bit { t_right } else { t_left };
+        for b in 0..2 {
+            let keep = side(&children[b], *bit);
+            s[b] = if t[b] { xor(&keep.0, &seed) } else { keep.0 };
+            t[b] = keep.1 ^ (t[b] && t_keep);
+        }
+
+        // Exactly one party has t set on the path, the output correction makes the two outputs add up to beta there
+        let (w0, w1) = (convert(&s[0], q, ctx), convert(&s[1], q, ctx));
+        let mut output = [0, 1].map(|j| {
+            let diff = mod_sub(&beta[j], &w0[j], q, ctx);
+            mod_add(&diff, &w1[j], q, ctx)
+        });
+        if t[1] {
+            output = negate(&output, q, ctx);
+        }
+        correction_words.push(CorrectionWord { seed, t_left, t_right, output });
+    }
+    // Both keys carry the same correction words
+    let copy = correction_words.iter().map(|cw| CorrectionWord {
+        seed: cw.seed,
+        t_left: cw.t_left,
+        t_right: cw.t_right,
+        output: [cw.output[0].to_owned().unwrap(), cw.output[1].to_owned().unwrap()],
+    }).collect();
+    (IdpfKey { party: 0, seed: roots[0], correction_words }, IdpfKey { party: 1, seed: roots[1], correction_words: copy })
+}
+
+impl IdpfKey {
+
+    pub fn levels(&self) -> usize {
+        self.correction_words.len()
+    }
+
+    pub fn root(&self) -> EvalState {
+        EvalState { seed: self.seed, t: self.party == 1 }
+    }
+
+    // One step down from a node at depth level, to its child along bit. Returns the child and this party's share
+    // of the output there.
+    pub fn eval_next(&self, state: &EvalState, level: usize, bit: bool, q: &BigNum, ctx: &mut BigNumContext) -> (EvalState, Output) {
+        let cw = &self.correction_words[level];
+        let (mut s_left, mut t_left, mut s_right, mut t_right) = expand(&state.seed);
+        if state.t {
+            s_left = xor(&s_left, &cw.seed);
+            s_right = xor(&s_right, &cw.seed);
+            t_left ^= cw.t_left;
+            t_right ^= cw.t_right;
+        }
+        let child = if bit { EvalState { seed: s_right, t: t_right } } else { EvalState { seed: s_left, t: t_left } };
+        let mut output = convert(&child.seed, q, ctx);
+        if child.t {
+            output = [mod_add(&output[0], &cw.output[0], q, ctx), mod_add(&output[1], &cw.output[1], q, ctx)];
+        }
+        if self.party == 1 {
+            output = negate(&output, q, ctx);
+        }
+        (child, output)
+    }
+
+    // This party's share of the output at prefix
+    pub fn eval(&self, prefix: &[bool], q: &BigNum, ctx: &mut BigNumContext) -> Output {
+        assert!(!prefix.is_empty() && prefix.len() <= self.levels());
+        let mut state = self.root();
+        let mut output = [BigNum::new().unwrap(), BigNum::new().unwrap()];
+        for (level, bit) in prefix.iter().enumerate() {
+            (state, output) = self.eval_next(&state, level, *bit, q, ctx);
+        }
+        output
+    }
+}
+
+
+#[test]
+pub fn test_idpf_prefixes() {
+    use crate::public_parameters::PublicParams;
+
+    let mut public_param = PublicParams::new(128, 2).unwrap();
+    let (q, ctx) = (&public_param.q, &mut public_param.ctx);
+    let alpha = [true, false, true, true];
+    let betas: Vec<Output> = (1..=4).map(|l| [BigNum::from_u32(l).unwrap(), BigNum::from_u32(10 * l).unwrap()]).collect();
+    let (key0, key1) = gen(&alpha, &betas, q, ctx);
+
+    // Every prefix of every length, on and off the path of alpha
+    for len in 1..=alpha.len() {
+        for x in 0..(1u32 << len) {
+            let prefix: Vec<bool> = (0..len).map(|i| (x >> (len - 1 - i)) & 1 == 1).collect();
+            let (y0, y1) = (key0.eval(&prefix, q, ctx), key1.eval(&prefix, q, ctx));
+            let sum = [mod_add(&y0[0], &y1[0], q, ctx), mod_add(&y0[1], &y1[1], q, ctx)];
+            if prefix[..] == alpha[..len] {
+                assert_eq!(sum, betas[len - 1], "{:?}", prefix);
+            } else {
+                assert_eq!(sum, [BigNum::new().unwrap(), BigNum::new().unwrap()], "{:?}", prefix);
+            }
+        }
+    }
+}
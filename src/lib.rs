@@ -4,15 +4,35 @@ extern crate rand;
 pub mod consants;
 pub mod verifiable_client;
 pub mod prio;
+pub mod beaver_triple; // Multiplication triples for the MPC of the Prio and Poplar servers
 pub mod poplar;
+pub mod idpf; // Incremental distributed point functions for two servers
+pub mod heavy_hitters; // Poplar heavy hitters over IDPF keys, with verifiable noise on every prefix count
 
 pub mod utils;
+pub mod algebra; // Field and group traits over the OpenSSL and curve25519 backends
 pub mod converters;
 pub mod public_parameters;
+pub mod curve_parameters; // Transparent setup for the Pedersen generators
 pub mod binomial_runner;
+pub mod submissions; // Inputs committed and proven by the clients themselves
+pub mod distributed_noise; // Noise from several non-colluding servers
+pub mod secrets; // Zeroizing wrapper and constant-time bit conversions for the prover's secrets
+pub mod rng; // OsRng by default, or a seeded ChaCha20 to replay a run
+pub mod sessions; // Session expiry, limits and persistence for the server
+pub mod server_config; // Server configuration from CLI flags, environment and a TOML file
+pub mod api; // Versioned REST API with JSON envelopes and an OpenAPI document
+pub mod logging; // tracing subscriber setup for the server and the examples
+pub mod metrics; // Prometheus metrics of the server
+pub mod progress; // Progress events of the long protocol steps
+pub mod continual_counting; // Binary tree mechanism for verifiable running counts
 
 pub mod generic_commitments; //Commitment Schemes
-pub mod finite_field_coms; // Finite field commitments using openSSL
+pub mod shamir; // Threshold secret sharing with Feldman and Pedersen verification
+pub mod vector_commitments; // Vector Pedersen commitments with a logarithmic bit-vector proof
+pub mod finite_field_coms; // Finite field commitments using openSSL, with generated and standard groups
+pub mod transcript; // Domain-separated Fiat-Shamir transcripts
+pub mod sigma_protocols; // Composable sigma protocols for Pedersen relations
 pub mod sigma_ff; // The struct describing the messages sent during a non-interactive Schnorr Proof
 
 pub mod participants; // Struct describing the client, server and verifier
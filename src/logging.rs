@@ -0,0 +1,52 @@
+use std::fmt;
+use std::str::FromStr;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+// Log output. The crate logs through tracing: every protocol step of BinomialRunner is a span carrying the
+// session ID, and proof generation and verification are debug events inside it. These install the subscriber.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json, // One JSON object per line, for log collectors
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}, expected \"text\" or \"json\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })
+    }
+}
+
+// filter takes the RUST_LOG syntax, e.g. "info" or "info,dp_client=debug". Every span logs how long it took when it
+// closes, so each request and each protocol step gets one line with its duration.
+pub fn init(filter: &str, format: LogFormat) -> Result<(), String> {
+    let filter = EnvFilter::try_new(filter).map_err(|e| format!("invalid log level {:?}: {}", filter, e))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_span_events(FmtSpan::CLOSE);
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).try_init(),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+// For the examples: text at info level unless RUST_LOG says otherwise. Timing a section is a matter of running it
+// in a span.
+pub fn init_timing() {
+    let _ = init(&std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()), LogFormat::Text);
+}
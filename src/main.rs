@@ -1,16 +1,23 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder, error::Error as ActixWebError};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Mutex, Arc};
+use std::time::Duration;
+use dp_client::api::{self, blocking, run_sized, ApiError};
 use dp_client::binomial_runner::BinomialRunner;
-use uuid::Uuid;
+use dp_client::server_config::ServerConfig;
+use dp_client::sessions::{FileStore, MemoryStore, SessionError, SessionManager, SessionStore};
+use actix_cors::Cors;
+use actix_web::middleware::from_fn;
+use dp_client::{logging, metrics};
+use tracing_actix_web::TracingLogger;
 
-type RunnerMap = Arc<Mutex<HashMap<String, BinomialRunner>>>;
+type RunnerMap = SessionManager; // Shared through web::Data, which is already an Arc
 
 // Define input and output structures for API calls
 #[derive(Deserialize, Debug)]
 pub struct NewRunnerRequest {
     pub x: Vec<u8>,
+    #[serde(default)]
+    pub dataset: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -24,6 +31,7 @@ pub struct InputRandomnessRequest {
     pub session_id: String, // Expect session_id in the request
 }
 
+#[cfg(feature = "cheat-mode")]
 #[derive(Deserialize, Debug)]
 pub struct OverwriteXorBitRequest {
     pub bits: Vec<u8>,
@@ -90,213 +98,158 @@ pub struct RandVarPEndResponse {
     pub success: bool
 }
 
-// API Handlers
+// API Handlers. These unversioned routes are kept for the current webdemo frontend, new clients use /v1 in api.rs
 async fn new_runner(
     req: web::Json<NewRunnerRequest>,
     runners: web::Data<RunnerMap>,
-) -> impl Responder {
-    println!("New runner called");
-    let new_runner_instance = BinomialRunner::new(&req.x);
-    let session_id = Uuid::new_v4().to_string(); // Generate new ID
-    let mut runners_map = runners.lock().unwrap();
-    runners_map.insert(session_id.clone(), new_runner_instance);
-    HttpResponse::Ok().body(session_id) // Send session ID back as plain text, or .json(json!({session_id}))
+    config: web::Data<api::ApiConfig>,
+) -> Result<impl Responder, ActixWebError> {
+    if let Some(dataset) = &req.dataset {
+        config.check_dataset(dataset)?;
+    }
+    let session_id = blocking(move || {
+        runners.check_input(req.x.len())?; // Refuse before doing the commitments
+        let mut runner = run_sized(req.x.len(), || BinomialRunner::new(&req.x));
+        if let Some(dataset) = &req.dataset {
+            runner.set_dataset(dataset);
+        }
+        runners.insert(runner).map(|(session_id, _)| session_id) // Proofs of the runner are bound to this ID
+    }).await?;
+    Ok(HttpResponse::Ok().body(session_id)) // Send session ID back as plain text, or .json(json!({session_id}))
 }
 
 async fn get_x_commits(
     req: web::Json<SessionIdRequest>, // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let commits = runner.get_x_commits().into_iter().map(|js_val| js_val).collect();
-        Ok(HttpResponse::Ok().json(GetCommitsResponse { commits }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let commits = blocking(move || runners.read(&req.session_id, |runner| runner.get_x_commits())).await?;
+    Ok(HttpResponse::Ok().json(GetCommitsResponse { commits }))
 }
 
 async fn input_randomness(
     req: web::Json<InputRandomnessRequest>, // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        runner.input_randomness(&req.bits);
-        Ok(HttpResponse::Ok().json("Randomness input"))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    blocking(move || {
+        runners.reserve_noise(&req.session_id, req.bits.len())?;
+        runners.update(&req.session_id, |runner| run_sized(req.bits.len(), || runner.input_randomness(&req.bits)))
+    }).await?;
+    Ok(HttpResponse::Ok().json("Randomness input"))
 }
 
 async fn get_private_random_commits(
     req: web::Json<SessionIdRequest>,
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let private_commits = runner.get_private_random_commits().into_iter().map(|js_val| js_val).collect();
-        Ok(HttpResponse::Ok().json(GetPrivateCommitsResponse { private_commits }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    } 
+    let private_commits = blocking(move || runners.read(&req.session_id, |runner| runner.get_private_random_commits())).await?;
+    Ok(HttpResponse::Ok().json(GetPrivateCommitsResponse { private_commits }))
 }
 
 async fn get_public_random(
     req: web::Json<SessionIdRequest>, // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let random_bits = runner.get_public_random();
-        Ok(HttpResponse::Ok().json(GetRandomResponse { random_bits }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let random_bits = blocking(move || runners.read(&req.session_id, |runner| runner.get_public_random())).await?;
+    Ok(HttpResponse::Ok().json(GetRandomResponse { random_bits }))
 }
 
 async fn rand_p_init(
     req: web::Json<RandPInitRequest>,
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        runner.rand_p_init(req.n);
-        Ok(HttpResponse::Ok().json("Switched to variable p"))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    } 
+    blocking(move || {
+        runners.reserve_noise(&req.session_id, req.n.max(0) as usize)?;
+        runners.update(&req.session_id, |runner| runner.rand_p_init(req.n))
+    }).await?;
+    Ok(HttpResponse::Ok().json("Switched to variable p"))
 }
 
 async fn random_variable_p_input(
     req: web::Json<RandVarPInputRequest>,
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        let success = runner.random_variable_p_input(req.k, req.m, &req.bits);
-        Ok(HttpResponse::Ok().json(RandVarPInputResponse {success}))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
+    let max = runners.limits().max_noise_bits;
+    if req.bits.len() > max {
+        return Err(ApiError::from(SessionError::NoiseTooLarge { len: req.bits.len(), max }).into());
     }
+    let success = blocking(move || runners.update(&req.session_id, |runner| runner.random_variable_p_input(req.k, req.m, &req.bits))).await?;
+    Ok(HttpResponse::Ok().json(RandVarPInputResponse {success}))
 }
 
 async fn random_variable_p_end(
     req: web::Json<SessionIdRequest>,
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        let success = runner.random_variable_p_end();
-        Ok(HttpResponse::Ok().json(RandVarPEndResponse {success}))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let success = blocking(move || runners.update(&req.session_id, |runner| runner.random_variable_p_end())).await?;
+    Ok(HttpResponse::Ok().json(RandVarPEndResponse {success}))
 }
 
 async fn get_xor_bits(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let xor_bits = runner.get_xor_bits();
-        Ok(HttpResponse::Ok().json(GetXorBitsResponse { xor_bits }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let xor_bits = blocking(move || runners.read(&req.session_id, |runner| runner.get_xor_bits())).await?;
+    Ok(HttpResponse::Ok().json(GetXorBitsResponse { xor_bits }))
 }
 
+#[cfg(feature = "cheat-mode")]
 async fn overwrite_xor_bits(
     req: web::Json<OverwriteXorBitRequest>,
     runners: web::Data<RunnerMap>
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        runner.overwrite_xor_bits(&req.bits);
-        Ok(HttpResponse::Ok().json("Bits overwritten"))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    blocking(move || {
+        runners.reserve_noise(&req.session_id, req.bits.len())?;
+        runners.update(&req.session_id, |runner| runner.overwrite_xor_bits(&req.bits))
+    }).await?;
+    Ok(HttpResponse::Ok().json("Bits overwritten"))
 }
 
 async fn get_xor_commits(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let xor_commits = runner.get_xor_commits().into_iter().map(|js_val| js_val).collect();
-        Ok(HttpResponse::Ok().json(GetXorCommitsResponse { xor_commits }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let xor_commits = blocking(move || runners.read(&req.session_id, |runner| runner.get_xor_commits())).await?;
+    Ok(HttpResponse::Ok().json(GetXorCommitsResponse { xor_commits }))
 }
 
 async fn compute_sum(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        let final_sum = runner.compute_sum();
-        Ok(HttpResponse::Ok().json(ComputeSumResponse { final_sum }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let final_sum = blocking(move || runners.update(&req.session_id, |runner| runner.compute_sum())).await?.map_err(ApiError::from)?;
+    Ok(HttpResponse::Ok().json(ComputeSumResponse { final_sum }))
 }
 
 async fn get_z(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let z_str = runner.get_z();
-        Ok(HttpResponse::Ok().json(GetZResponse { z: z_str }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let z_str = blocking(move || runners.read(&req.session_id, |runner| runner.get_z())).await?;
+    Ok(HttpResponse::Ok().json(GetZResponse { z: z_str }))
 }
 
 async fn commit_pedersons(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let mut runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get_mut(&req.session_id) {
-        runner.commit_pedersons();
-        Ok(HttpResponse::Ok().json("Pederson commitments computed"))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    blocking(move || runners.update(&req.session_id, |runner| runner.commit_pedersons())).await?;
+    Ok(HttpResponse::Ok().json("Pederson commitments computed"))
 }
 
 async fn get_lhs(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let lhs_str = runner.get_lhs();
-        Ok(HttpResponse::Ok().json(GetLhsRhsResponse { lhs: lhs_str, rhs: "".to_string() })) // Placeholder for rhs
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let lhs_str = blocking(move || runners.read(&req.session_id, |runner| runner.get_lhs())).await?;
+    Ok(HttpResponse::Ok().json(GetLhsRhsResponse { lhs: lhs_str, rhs: "".to_string() })) // Placeholder for rhs
 }
 
 async fn get_rhs(
     req: web::Json<SessionIdRequest>,  // Expect session_id in body
     runners: web::Data<RunnerMap>,
 ) -> Result<impl Responder, ActixWebError> {
-    let runners_map = runners.lock().unwrap();
-    if let Some(runner) = runners_map.get(&req.session_id) {
-        let rhs_str = runner.get_rhs();
-        let lhs_str = runner.get_lhs(); // Assuming you might want both
-        Ok(HttpResponse::Ok().json(GetLhsRhsResponse { lhs: lhs_str, rhs: rhs_str }))
-    } else {
-        Err(actix_web::error::ErrorNotFound("Runner not found for this session"))
-    }
+    let (lhs_str, rhs_str) = blocking(move || runners.read(&req.session_id, |runner| (runner.get_lhs(), runner.get_rhs()))).await?; // Assuming you might want both
+    Ok(HttpResponse::Ok().json(GetLhsRhsResponse { lhs: lhs_str, rhs: rhs_str }))
 }
 
 #[derive(Deserialize, Debug)]
@@ -305,33 +258,118 @@ struct SessionIdRequest {
 }
 
 
+// The unversioned routes take no capability tokens, so any holder of a session ID can play every role.
+// They are only mounted with --legacy-routes, for the webdemo frontend.
+fn legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/new", web::post().to(new_runner))
+        .route("/commits", web::post().to(get_x_commits))
+        .route("/randomness", web::post().to(input_randomness))
+        .route("/priv_random_commits", web::post().to(get_private_random_commits))
+        .route("/public_random", web::post().to(get_public_random))
+        .route("/rand_p_init", web::post().to(rand_p_init))
+        .route("/rand_p_input", web::post().to(random_variable_p_input))
+        .route("/rand_p_end", web::post().to(random_variable_p_end))
+        .route("/xor_bits", web::post().to(get_xor_bits))
+        .route("/xor_commits", web::post().to(get_xor_commits))
+        .route("/compute_sum", web::post().to(compute_sum))  
+        .route("/z", web::post().to(get_z))       
+        .route("/commit_pedersons", web::post().to(commit_pedersons))
+        .route("/lhs", web::post().to(get_lhs))     
+        .route("/rhs", web::post().to(get_rhs));
+    #[cfg(feature = "cheat-mode")]
+    cfg.route("/overwrite_xor_bits", web::post().to(overwrite_xor_bits));
+}
+
+// Cross-origin requests are refused unless their origin is listed. "*" allows any origin.
+fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "DELETE"])
+        .allowed_headers(vec![actix_web::http::header::CONTENT_TYPE, actix_web::http::header::AUTHORIZATION])
+        .max_age(3600);
+    if origins.iter().any(|o| o == "*") {
+        cors.allow_any_origin()
+    } else {
+        origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let runners_map: RunnerMap = Arc::new(Mutex::new(HashMap::new()));
-    HttpServer::new(move || {
+    let config = ServerConfig::load().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    logging::init(&config.log_level, config.log_format).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    tracing::info!("Effective configuration:\n{}", config);
+
+    let store: Box<dyn SessionStore> = match &config.session_dir {
+        Some(dir) => Box::new(FileStore::new(dir)?),
+        None => Box::new(MemoryStore),
+    };
+    let eviction_period = config.limits.ttl.min(Duration::from_secs(60)).max(Duration::from_secs(1));
+    let manager = SessionManager::new(config.limits.clone(), store).map_err(std::io::Error::other)?;
+    tracing::info!(sessions = manager.len(), "Resumed sessions from the store");
+    let runners_map = web::Data::new(manager);
+
+    // Expired sessions are also dropped on every new session, this catches the ones nobody touches again
+    let sessions = runners_map.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(eviction_period);
+        loop {
+            interval.tick().await;
+            let evictor = sessions.clone();
+            if let Err(e) = blocking(move || evictor.evict_expired()).await {
+                tracing::warn!(error = %e, "Session eviction failed");
+            }
+        }
+    });
+
+    if config.operator_token.is_none() {
+        tracing::warn!("No operator token is configured, POST /v1/sessions refuses every request");
+    }
+    let api_config = web::Data::new(api::ApiConfig { operator_token: config.operator_token.clone(), datasets: config.datasets.clone() });
+    let app_config = config.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(runners_map.clone()))
-            .route("/new", web::post().to(new_runner))
-            .route("/commits", web::post().to(get_x_commits))
-            .route("/randomness", web::post().to(input_randomness))
-            .route("/priv_random_commits", web::post().to(get_private_random_commits))
-            .route("/public_random", web::post().to(get_public_random))
-            .route("/rand_p_init", web::post().to(rand_p_init))
-            .route("/rand_p_input", web::post().to(random_variable_p_input))
-            .route("/rand_p_end", web::post().to(random_variable_p_end))
-            .route("/xor_bits", web::post().to(get_xor_bits))
-            .route("/overwrite_xor_bits", web::post().to(overwrite_xor_bits)) 
-            .route("/xor_commits", web::post().to(get_xor_commits))
-            .route("/compute_sum", web::post().to(compute_sum))  
-            .route("/z", web::post().to(get_z))       
-            .route("/commit_pedersons", web::post().to(commit_pedersons))
-            .route("/lhs", web::post().to(get_lhs))     
-            .route("/rhs", web::post().to(get_rhs))     
+            .wrap(cors(&app_config.cors_origins))
+            .wrap(TracingLogger::default())
+            .wrap(from_fn(metrics::track_latency))
+            .app_data(runners_map.clone())
+            .app_data(api_config.clone())
+            .app_data(web::JsonConfig::default().limit(app_config.max_request_bytes).error_handler(api::json_error_handler))
+            .route("/metrics", web::get().to(metrics::metrics_endpoint))
+            .configure(api::configure)
+            .configure(|cfg| if app_config.legacy_routes { legacy_routes(cfg) })
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await?;
-
-    println!("Server initialized!");
+    .workers(config.workers);
+
+    let address = (config.bind.as_str(), config.port);
+    let server = match &config.tls {
+        Some(tls) => server.bind_rustls_0_23(address, tls.load()?)?,
+        None => server.bind(address)?,
+    };
+    tracing::info!(bind = %config.bind, port = config.port, tls = config.tls.is_some(), "Server listening");
+    server.run().await?;
     Ok(())
 }
+
+
+// A browser deleting a session asks first whether the method and the bearer token may cross origins
+#[actix_web::test]
+pub async fn test_cors_preflight() {
+    use actix_web::http::{header, Method, StatusCode};
+    use actix_web::test;
+
+    let app = test::init_service(App::new().wrap(cors(&["https://demo.example".to_string()])).route("/", web::delete().to(HttpResponse::Ok))).await;
+    let req = test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/")
+        .insert_header((header::ORIGIN, "https://demo.example"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization, content-type"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let allowed = |name| resp.headers().get(name).unwrap().to_str().unwrap().to_lowercase();
+    assert!(allowed(header::ACCESS_CONTROL_ALLOW_METHODS).contains("delete"));
+    assert!(allowed(header::ACCESS_CONTROL_ALLOW_HEADERS).contains("authorization"));
+    assert_eq!(allowed(header::ACCESS_CONTROL_ALLOW_ORIGIN), "https://demo.example");
+}
@@ -0,0 +1,152 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use prometheus::{CounterVec, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::sync::LazyLock;
+use std::time::Instant;
+use crate::sessions::SessionManager;
+
+// Prometheus metrics of the server. The runner counts proofs, failed checks and the privacy budget it releases,
+// the server times every request, and the session gauges are read off the SessionManager when /metrics is scraped.
+// Rates such as proofs per second are left to PromQL, e.g. rate(zkdp_proofs_generated_total[1m]).
+
+pub struct Metrics {
+    registry: Registry,
+    pub active_sessions: IntGauge,
+    pub sessions_by_phase: IntGaugeVec, // phase
+    pub request_duration: HistogramVec, // method, endpoint, status
+    pub proofs_generated: IntCounterVec, // kind
+    pub proofs_verified: IntCounterVec, // kind
+    pub verification_failures: IntCounterVec, // check
+    pub privacy_budget_spent: CounterVec, // dataset
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Metrics {
+        let metrics = Self {
+            registry: Registry::new_custom(Some("zkdp".to_string()), None).unwrap(),
+            active_sessions: IntGauge::new("active_sessions", "Live sessions").unwrap(),
+            sessions_by_phase: IntGaugeVec::new(Opts::new("sessions_by_phase", "Live sessions by protocol phase"), &["phase"]).unwrap(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Request latency by route")
+                    .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0]),
+                &["method", "endpoint", "status"],
+            ).unwrap(),
+            proofs_generated: IntCounterVec::new(Opts::new("proofs_generated_total", "Proofs created by the prover"), &["kind"]).unwrap(),
+            proofs_verified: IntCounterVec::new(Opts::new("proofs_verified_total", "Proofs checked by the verifier, valid or not"), &["kind"]).unwrap(),
+            verification_failures: IntCounterVec::new(Opts::new("verification_failures_total", "Failed verifier checks"), &["check"]).unwrap(),
+            privacy_budget_spent: CounterVec::new(Opts::new("privacy_budget_spent_total", "Sum of the epsilons of the released results"), &["dataset"]).unwrap(),
+        };
+        metrics.registry.register(Box::new(metrics.active_sessions.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.sessions_by_phase.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.request_duration.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.proofs_generated.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.proofs_verified.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.verification_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.privacy_budget_spent.clone())).unwrap();
+        metrics
+    }
+
+    // Counts n proofs of one kind
+    pub fn proofs_generated(&self, kind: &str, n: usize) {
+        self.proofs_generated.with_label_values(&[kind]).inc_by(n as u64);
+    }
+
+    pub fn proofs_verified(&self, kind: &str, n: usize) {
+        self.proofs_verified.with_label_values(&[kind]).inc_by(n as u64);
+    }
+
+    // Passes valid through, counting it as a failure of check if it is false
+    pub fn check(&self, check: &str, valid: bool) -> bool {
+        if !valid {
+            self.verification_failures.with_label_values(&[check]).inc();
+        }
+        valid
+    }
+
+    // Text exposition of every metric, with the session gauges taken from sessions
+    pub fn render(&self, sessions: &SessionManager) -> String {
+        self.active_sessions.set(sessions.len() as i64);
+        for (phase, count) in sessions.phase_counts() {
+            self.sessions_by_phase.with_label_values(&[phase.name()]).set(count as i64);
+        }
+        let mut buffer = Vec::new();
+        // Encoding into a Vec only fails on invalid metric names, which are fixed above
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+// Dataset names become metric labels, so they are kept short and plain. Which ones a server takes is fixed by its
+// configuration, so requests cannot add label values.
+pub fn valid_dataset_name(dataset: &str) -> bool {
+    !dataset.is_empty() && dataset.len() <= 64 && dataset.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+// Times every request. The endpoint is the route pattern, e.g. /v1/sessions/{session_id}/randomness,
+// so session IDs do not end up as label values.
+pub async fn track_latency(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let endpoint = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    let resp = next.call(req).await?;
+    METRICS.request_duration
+        .with_label_values(&[method.as_str(), endpoint.as_str(), resp.status().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    Ok(resp)
+}
+
+pub async fn metrics_endpoint(sessions: web::Data<SessionManager>) -> HttpResponse {
+    let text = web::block(move || METRICS.render(&sessions)).await.unwrap_or_default();
+    HttpResponse::Ok().content_type(TextEncoder::new().format_type()).body(text)
+}
+
+
+#[actix_web::test]
+pub async fn test_metrics_endpoint() {
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use crate::sessions::{MemoryStore, SessionLimits};
+    use serde_json::{json, Value};
+
+    let sessions = web::Data::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(track_latency))
+            .app_data(sessions)
+            .app_data(web::Data::new(crate::api::ApiConfig { operator_token: Some("operator".to_string().into()), datasets: vec!["metrics-test".to_string()] }))
+            .route("/metrics", web::get().to(metrics_endpoint))
+            .configure(crate::api::configure)
+    ).await;
+
+    let req = test::TestRequest::post().uri("/v1/sessions").insert_header(("Authorization", "Bearer operator")).set_json(json!({"x": [1, 0, 1], "dataset": "metrics-test"})).to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    let id = body["data"]["session_id"].as_str().unwrap().to_string();
+    let prover = ("Authorization", format!("Bearer {}", body["data"]["tokens"]["prover"].as_str().unwrap()));
+    let auditor = ("Authorization", format!("Bearer {}", body["data"]["tokens"]["auditor"].as_str().unwrap()));
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/randomness", id)).insert_header(prover.clone()).set_json(json!({"bits": vec![1; 64]})).to_request();
+    test::call_service(&app, req).await;
+    let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/coin_seed", id)).insert_header(auditor).set_json(json!({"seed": vec![7; 32]})).to_request();
+    test::call_service(&app, req).await;
+    for step in ["sum", "commit_pedersons"] {
+        let req = test::TestRequest::post().uri(&format!("/v1/sessions/{}/{}", id, step)).insert_header(prover.clone()).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let text = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    let value = |prefix: &str| -> f64 {
+        text.lines().find(|l| l.starts_with(prefix)).and_then(|l| l.rsplit(' ').next()).unwrap().parse().unwrap()
+    };
+    assert_eq!(value("zkdp_active_sessions "), 1.0);
+    assert_eq!(value("zkdp_sessions_by_phase{phase=\"checked\"}"), 1.0);
+    assert_eq!(value("zkdp_sessions_by_phase{phase=\"noise_committed\"}"), 0.0);
+    // Other tests share the registry, so the counters are only bounded from below
+    assert!(value("zkdp_proofs_generated_total{kind=\"or_proof\"}") >= 64.0);
+    assert!(value("zkdp_proofs_verified_total{kind=\"or_proof\"}") >= 64.0);
+    assert!(value("zkdp_privacy_budget_spent_total{dataset=\"metrics-test\"}") > 0.0);
+    assert!(text.contains("zkdp_http_request_duration_seconds_count{endpoint=\"/v1/sessions/{session_id}/randomness\",method=\"POST\",status=\"200\"} 1"));
+}
@@ -4,9 +4,12 @@ use curve25519_dalek::scalar::Scalar;
 // use num_integer::Roots;
 use crate::sigma_ff::ProofScalar;
 // use crate::generic_commitments::CurveCommitment;
-use sha3::{Digest, Sha3_256};
-use rand_core::OsRng;
-use coinflip::flip;
+use crate::transcript::{Transcript, BERNOULLI_COIN_LABEL};
+use crate::secrets::{scalar_from_choice, Secret};
+use crate::rng::{flip, random_scalar};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use subtle::ConstantTimeEq;
 
 use crate::consants::MGRAIN;
 
@@ -44,45 +47,57 @@ impl Board {
         return agg_com;
     }
 
-    pub fn verify(&self, transcript: &ProofScalar) -> bool {
+    // Verifies an OR-proof against the Fiat-Shamir transcript for its protocol step, session and index.
+    // A proof replayed under any other context recomputes a different challenge and is rejected.
+    pub fn verify(&self, transcript: &ProofScalar, fs_transcript: &mut Transcript) -> bool {
 
-        // CHECK the hash of the initial transcript is equal to e and then 
-        let mut hasher = Sha3_256::new();
-        let mut input_to_rom: Vec<u8> = Vec::new();
-        input_to_rom.extend(transcript.com.compress().as_bytes());
-        input_to_rom.extend(transcript.d0.compress().as_bytes());
-        input_to_rom.extend(transcript.d1.compress().as_bytes());
-        hasher.update(input_to_rom); // this will take d0, d1, commitment as a byte array
-        let result: [u8;32] =  hasher.finalize().into();        
-        let e = Scalar::from_bytes_mod_order(result);
-        assert_eq!(e, transcript.e);
-
-        // transcript.e = hash(d0,d1, com. )
-        assert_eq!(transcript.e, transcript.e1 + transcript.e0); // CHECK e = e0 + e1
-
-        let ce0 = &transcript.com * &transcript.e0; //c^{e0}
-        let hv0 = self.h * &transcript.v0; //h^{v0}
-        assert_eq!(&transcript.d0 + &ce0, hv0); //d0 c^{e0} = h^{v0}
-
-        let ce1 = &transcript.com * &transcript.e1; // c^{e1}
-
-        let ge1 = self.g * &transcript.e1; // g^{e1}
-        let hv1 = self.h * &transcript.v1;// h^{v1}
-
-        assert_eq!(&transcript.d1 + &ce1, &ge1 + &hv1); //d1 c^{e1} = g^{e1}h^{v1}
-
-        return true;
+        // e = hash(statement, d0, d1), e = e0 + e1, d0 c^{e0} = h^{v0} and d1 c^{e1} = g^{e1}h^{v1}
+        self.com.verify_bit_proof(transcript, fs_transcript)
     }
     
 }
 
 pub struct Server{
-    pub num_shares: usize,
     pub g: RistrettoPoint,
     pub h: RistrettoPoint,
     pub com: CurveCommitment,
-    openings: Vec<Vec<(Scalar, Scalar)>>,
-    pub m_grain: [f64; MGRAIN]
+    openings: Secret<Vec<Vec<(Scalar, Scalar)>>>, // Wiped when cleared or dropped
+    pub m_grain: [f64; MGRAIN],
+    pub session_id: Vec<u8>, // bound into every Fiat-Shamir transcript the server produces
+}
+
+// A server is saved with the openings of its Bernoulli coins, so a resumed session can still open them.
+// com and m_grain follow from the generators and are rebuilt.
+#[derive(Serialize)]
+struct StoredServerRef<'a> {
+    g: &'a RistrettoPoint,
+    h: &'a RistrettoPoint,
+    openings: &'a Secret<Vec<Vec<(Scalar, Scalar)>>>,
+    session_id: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct StoredServer {
+    g: RistrettoPoint,
+    h: RistrettoPoint,
+    openings: Secret<Vec<Vec<(Scalar, Scalar)>>>,
+    session_id: Vec<u8>,
+}
+
+impl Serialize for Server {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredServerRef { g: &self.g, h: &self.h, openings: &self.openings, session_id: &self.session_id }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Server {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredServer::deserialize(deserializer)?;
+        let mut server = Server::new(stored.g, stored.h);
+        server.openings = stored.openings;
+        server.session_id = stored.session_id;
+        Ok(server)
+    }
 }
 
 pub struct DistBernoulliProof{
@@ -93,14 +108,14 @@ pub struct DistBernoulliProof{
 
 impl Server{
 
-    pub fn new(num_shares: usize, g: RistrettoPoint, h: RistrettoPoint)->Server{
+    pub fn new(g: RistrettoPoint, h: RistrettoPoint)->Server{
 
         let com = CurveCommitment{g, h};
         let mut m_grain = [0.0; MGRAIN];
         for i in 0..MGRAIN{
             m_grain[i] = (i as f64)/(MGRAIN as f64);
         }        
-        Self { num_shares: num_shares, g: g, h: h, com: com, openings:Vec::new(), m_grain } 
+        Self { g: g, h: h, com: com, openings: Secret::default(), m_grain, session_id: Vec::new() } 
     }
 
     pub fn get_opening(&self, opening_idx: usize, challenge_idx:usize)->(Scalar, Scalar){
@@ -150,15 +165,25 @@ impl Server{
         
     }
 
+    // Transcript for the OR-proof of the i-th committed bit of Bernoulli coin number coin_idx
+    pub fn bernoulli_transcript(session_id: &[u8], coin_idx: usize, i: usize)->Transcript{
+        let mut fs_transcript = Transcript::with_context(BERNOULLI_COIN_LABEL, session_id, coin_idx as u64);
+        fs_transcript.append_u64(b"bit", i as u64);
+        fs_transcript
+    }
+
     pub fn clear_openings(&mut self){
-        self.openings = Vec::new();
+        self.openings = Secret::default();
     }
 
     pub fn get_random_value(&self)->Scalar{
 
-        let mut csprng = OsRng;
-        let r: Scalar = Scalar::random(&mut csprng);
-        return r;
+        self.get_random_value_with_rng(&mut OsRng)
+    }
+
+    pub fn get_random_value_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R)->Scalar{
+
+        random_scalar(rng)
     }
 
     pub fn get_bit_prob(&self, base_prob:f64, bit_index:usize)->f64{
@@ -174,6 +199,11 @@ impl Server{
 
     pub fn distributional_geometric_com(&mut self, precision_bits: usize, base_prob: f64)->Vec<DistBernoulliProof>{
 
+        self.distributional_geometric_com_with_rng(precision_bits, base_prob, &mut OsRng)
+    }
+
+    pub fn distributional_geometric_com_with_rng<R: RngCore + CryptoRng>(&mut self, precision_bits: usize, base_prob: f64, rng: &mut R)->Vec<DistBernoulliProof>{
+
 
         let mut coin_coms = Vec::new();
         for bit_index in 0..precision_bits{
@@ -182,21 +212,27 @@ impl Server{
             let k = MGRAIN;
             let l = self.get_approximate_probability(prob); // l/k is the approximate M-Grain estimation
             // println!("True Prob for Bit j:{} Approx: {}", prob, self.m_grain[l]);
-            coin_coms.push(self.distributional_commitment_bernoulli(l, k));
+            coin_coms.push(self.distributional_commitment_bernoulli_with_rng(l, k, rng));
         }
         return coin_coms;        
     }
 
     pub fn distributional_commitment_bernoulli(&mut self, l: usize, k:usize)->DistBernoulliProof{
 
+        self.distributional_commitment_bernoulli_with_rng(l, k, &mut OsRng)
+    }
+
+    pub fn distributional_commitment_bernoulli_with_rng<R: RngCore + CryptoRng>(&mut self, l: usize, k:usize, rng: &mut R)->DistBernoulliProof{
+
         let mut private_openings: Vec<(Scalar, Scalar)>= Vec::new();
         let mut proof_transcripts : Vec<ProofScalar> = Vec::new();    
         let mut count_ones = 0;    
         let mut count_zeroes = 0;
         let mut aggregate_rand: Scalar = Scalar::zero();
-        for _ in 0..k{    
+        let coin_idx = self.openings.len();
+        for i in 0..k{    
             // Prover commits to a bit
-            let r = self.get_random_value();
+            let r = self.get_random_value_with_rng(rng);
 
             let b;    
             // All ones done
@@ -209,18 +245,17 @@ impl Server{
             }
             // Flip uniformm
             else{
-                b = flip();
+                b = flip(rng);
             }
             
             let transcript;            
+            let mut fs_transcript = Server::bernoulli_transcript(&self.session_id, coin_idx, i);
+            private_openings.push((scalar_from_choice((b as u8).into()), r));
+            transcript = self.com.create_bit_proof((b as u8).ct_eq(&1), r, &mut fs_transcript, rng);
             if b{
-                transcript = self.com.create_proof_1(r);
-                private_openings.push((Scalar::one(), r));
                 count_ones +=1;
             }
             else{
-                transcript = self.com.create_proof_0(r);
-                private_openings.push((Scalar::zero(), r));
                 count_zeroes +=1;
             }
 
@@ -235,7 +270,6 @@ impl Server{
 }
 
 pub struct Client{
-    pub num_shares: usize,
     pub g: RistrettoPoint,
     pub h: RistrettoPoint,
     pub com: CurveCommitment
@@ -243,18 +277,39 @@ pub struct Client{
 
 impl Client{
 
-    pub fn new(num_shares: usize, g: RistrettoPoint, h: RistrettoPoint)->Client{
+    pub fn new(g: RistrettoPoint, h: RistrettoPoint)->Client{
 
         let com = CurveCommitment{g, h};    
-        Self {num_shares: num_shares, g: g, h: h, com: com} 
+        Self {g: g, h: h, com: com} 
     }
 
     pub fn send_input_to_sever(&self)->(Scalar, Scalar){
 
+        self.send_input_to_sever_with_rng(&mut OsRng)
+    }
+
+    pub fn send_input_to_sever_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R)->(Scalar, Scalar){
+
         // This should be 1 or 0 but it doesn't matter for this script
         let x = Scalar::one();
-        let mut csprng = OsRng;
-        let r: Scalar = Scalar::random(&mut csprng);
+        let r: Scalar = random_scalar(rng);
         return (x, r);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_server_openings_survive_serde() {
+    use crate::curve_parameters::CurveParams;
+
+    let params = CurveParams::new();
+    let mut server = Server::new(params.g, params.h);
+    server.session_id = b"session".to_vec();
+    let proof = server.distributional_commitment_bernoulli(1, 2);
+    let resumed: Server = serde_json::from_slice(&serde_json::to_vec(&server).unwrap()).unwrap();
+
+    assert_eq!(resumed.session_id, server.session_id);
+    assert_eq!(resumed.com.g, server.com.g);
+    let challenges = vec![1];
+    assert_eq!(resumed.geometric_opening(challenges.clone()), server.geometric_opening(challenges));
+    assert_eq!(proof.or_proofs.len(), 2);
+}
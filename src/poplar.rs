@@ -2,11 +2,13 @@ use openssl::bn::{BigNum, BigNumContext};
 use std::ops::Rem;
 use std::fmt;
 use crate::utils::{gen_random};
+use crate::verifiable_client::{self, PoplarVote};
 
 pub struct Server{
     pub q: BigNum, // order of diffie Hellman group G
     pub num_candidates: usize,
     pub num_servers: usize,    
+    pub totals: Vec<BigNum>, // This server's shares of the per-candidate counts of the accepted votes
 }
 
 // NOTE: 
@@ -29,7 +31,8 @@ impl Server{
     pub fn new(num_servers:usize, num_candidates: usize, _q: &BigNum) -> Server {
        
         let q = &BigNum::new().unwrap() + _q;
-        Self{q, num_candidates, num_servers}
+        let totals = (0..num_candidates).map(|_| BigNum::new().unwrap()).collect();
+        Self{q, num_candidates, num_servers, totals}
     }
 
     pub fn multiply_first(&self, x_i: &BigNum, y_i: &BigNum, a_i: &BigNum, b_i: &BigNum, ctx: &mut BigNumContext)->(BigNum, BigNum){
@@ -64,7 +67,7 @@ impl Server{
         return r_vec;
     }
 
-    pub fn round_one_verification(&self, v: &Vec<BigNum>, kv: &Vec<BigNum>, randomness: &Vec<BigNum>, a_i: &BigNum, b_i: &BigNum, c_i: &BigNum, ctx: &mut BigNumContext)->(BigNum, BigNum, BigNum){
+    pub fn round_one_verification(&self, v: &[BigNum], kv: &[BigNum], randomness: &[BigNum], a_i: &BigNum, b_i: &BigNum, c_i: &BigNum, ctx: &mut BigNumContext)->(BigNum, BigNum, BigNum){
 
         // let z = (z_i.iter().fold(BigNum::from_u32(0).unwrap(), |acc, x| &acc + x)).rem(&self.q); 
         // let z_star = (z_i_star.iter().fold(BigNum::from_u32(0).unwrap(), |acc, x| &acc + x)).rem(&self.q);
@@ -101,26 +104,101 @@ impl Server{
         
     }
 
+    // Adds the shares of an accepted vote to the running totals
+    pub fn accumulate(&mut self, v: &[BigNum]){
+
+        for (total, v_i) in self.totals.iter_mut().zip(v.iter()){
+            *total = (&*total + v_i).rem(&self.q);
+        }
+    }
+
+}
+
+// The coordinates of a shared vector that server_idx holds
+fn server_shares(vector: &[verifiable_client::Share], server_idx: usize)->Vec<BigNum>{
+    vector.iter().map(|share| &share.shares[server_idx] + &BigNum::new().unwrap()).collect()
+}
+
+// Sketching check of Poplar, Appendix C, across all servers. With z = <v, r>, z* = <v, r^2> and z** = <kappa v, r>,
+// the servers open Z = z + a, Z* = z* + b and Z** = z** + c, then A Z + B. For the client's A and B,
+//   A Z + B + Z^2 - Z* - Z** = kappa z + z^2 - z* - z**,
+// which is 0 for a one-hot v and, being quadratic in r, nonzero except with probability 2/q for any vector with two
+// nonzero entries or a single entry other than 1. The client's triple only masks z, z* and z**, so a bad one does not help it.
+pub fn validate_vote(servers: &[Server], submission: &PoplarVote, randomness: &[BigNum], ctx: &mut BigNumContext)->bool{
+
+    let q = &servers[0].q;
+    let num_servers = servers.len();
+    let well_formed = |vector: &[verifiable_client::Share]| vector.len() == servers[0].num_candidates && vector.iter().all(|s| s.shares.len() == num_servers);
+    if !well_formed(&submission.vote) || !well_formed(&submission.kvote) || [&submission.ucase_a, &submission.ucase_b].iter().any(|s| s.shares.len() != num_servers)
+        || [&submission.triple.a_shares, &submission.triple.b_shares, &submission.triple.c_shares].iter().any(|s| s.len() != num_servers){
+        return false;
+    }
+
+    // ROUND 1 of MPC
+    let mut ucase_z = BigNum::new().unwrap();
+    let mut ucase_z_star = BigNum::new().unwrap();
+    let mut ucase_z_star_star = BigNum::new().unwrap();
+    for (server_idx, server) in servers.iter().enumerate(){
+        let (z, z_star, z_star_star) = server.round_one_verification(
+            &server_shares(&submission.vote, server_idx), &server_shares(&submission.kvote, server_idx), randomness,
+            &submission.triple.a_shares[server_idx], &submission.triple.b_shares[server_idx], &submission.triple.c_shares[server_idx], ctx);
+        ucase_z = (&ucase_z + &z).rem(q);
+        ucase_z_star = (&ucase_z_star + &z_star).rem(q);
+        ucase_z_star_star = (&ucase_z_star_star + &z_star_star).rem(q);
+    }
+
+    // ROUND 2 of MPC
+    let mut ans = BigNum::new().unwrap();
+    for (server_idx, server) in servers.iter().enumerate(){
+        let shr_output = server.round_two_verification(&submission.ucase_a.shares[server_idx], &submission.ucase_b.shares[server_idx], &ucase_z);
+        ans = (&ans + &shr_output).rem(q);
+    }
+
+    let pos = (&ans + &(&ucase_z*&ucase_z)).rem(q);
+    let neg = &ucase_z_star + &ucase_z_star_star;
+    let mut output = BigNum::new().unwrap();
+    _ = output.mod_sub(&pos, &neg, q, ctx);
+    output == BigNum::new().unwrap()
 }
 
-/*#[test]
+// Full flow for a batch of votes. Every vote is checked against fresh randomness, drawn after the client sent it,
+// and the servers add the shares of the valid ones to their totals. Returns which votes were accepted.
+pub fn collect_votes(servers: &mut [Server], votes: &[PoplarVote], ctx: &mut BigNumContext)->Vec<bool>{
+
+    votes.iter().map(|submission| {
+        let randomness = servers[0].generate_fresh_randomness();
+        let valid = validate_vote(servers, submission, &randomness, ctx);
+        if valid{
+            for (server_idx, server) in servers.iter_mut().enumerate(){
+                server.accumulate(&server_shares(&submission.vote, server_idx));
+            }
+        }
+        valid
+    }).collect()
+}
+
+// Per-candidate counts, reconstructed from the totals every server publishes at the end
+pub fn tally(servers: &[Server])->Vec<BigNum>{
+
+    (0..servers[0].num_candidates).map(|coord| {
+        servers.iter().fold(BigNum::new().unwrap(), |acc, server| (&acc + &server.totals[coord]).rem(&server.q))
+    }).collect()
+}
+
+#[test]
 fn test_sketching(){
 
-    // use crate::utils::{gen_random, additive_share};
     use crate::public_parameters::PublicParams;
-    use crate::poplar::Server;
     use crate::verifiable_client::Client;
 
-    let security_parameter = 4;
-    let num_candidates = 2; // Doesn't play a role in this test but need it to initialise server
+    let security_parameter = 128;
+    let num_candidates = 2;
     let num_shares = 3; // num_servers
     let mut public_param = PublicParams::new(security_parameter, num_shares).unwrap();
-    println!("{}", public_param);
 
     let client = Client::new(num_shares, num_candidates as u32,  &public_param.q);    
     let choice = client.generate_fake_vote();       
-    let vote = client.vote(choice, &mut public_param.ctx);
-    let kvote = client.poplar_vote(choice, &mut public_param.ctx);
+    let submission = client.poplar_submission(choice, &mut public_param.ctx);
 
     let mut servers = Vec::new();
     for _ in 0..num_shares{
@@ -128,47 +206,46 @@ fn test_sketching(){
         servers.push(server);
     }
 
-    // Pre-processing: Client has a,b and c
-    let randomness = servers[0].generate_fresh_randomness();
-    
     // Servers never see a, b, c in plain
-    let a = beaver_triples.a_shares.iter().fold(BigNum::new().unwrap(), |acc, x| &acc + x);
-    let b = beaver_triples.b_shares.iter().fold(BigNum::new().unwrap(), |acc, x| &acc + x);
-    let c = beaver_triples.c_shares.iter().fold(BigNum::new().unwrap(), |acc, x| &acc + x);
-
-    // A and B as defined in Appendix C of Poplar
-    let (ucase_a_share, ucase_b_share) = client.get_ab_for_poplar(&a, &b, &c, &mut public_param.ctx);
-
-    // ROUND 1 of MPC
-    let mut ucase_z = BigNum::new().unwrap();
-    let mut ucase_z_star = BigNum::new().unwrap();
-    let mut ucase_z_star_star = BigNum::new().unwrap();
-    for server_idx in 0..num_shares{
-        let mut shares = Vec::new(); // Get the shares for server i (copy it over)
-        let mut k_shares =  Vec::new(); 
-        for coord in 0..num_candidates{
-            shares.push(&vote[coord].shares[server_idx] + &BigNum::new().unwrap());
-            k_shares.push(&kvote[coord].shares[server_idx] + &BigNum::new().unwrap());
-        }
-        let (z, z_star, z_star_star) = servers[server_idx].round_one_verification(&shares, &k_shares, &randomness, &beaver_triples.a_shares[server_idx], &beaver_triples.b_shares[server_idx], &beaver_triples.c_shares[server_idx], &mut public_param.ctx);
-        ucase_z = (&ucase_z + &z).rem(&public_param.q);
-        ucase_z_star = (&ucase_z_star + &z_star).rem(&public_param.q);
-        ucase_z_star_star = (&ucase_z_star_star + &z_star_star).rem(&public_param.q);
-    }
-
-    // ROUND 2 of MPC
-    let mut ans = BigNum::new().unwrap();
-    for server_idx in 0..num_shares{
-        let shr_output = servers[server_idx].round_two_verification(&ucase_a_share.shares[server_idx], &ucase_b_share.shares[server_idx], &ucase_z);
-        ans = (&ans + &shr_output).rem(&public_param.q);
-    }
+    let randomness = servers[0].generate_fresh_randomness();
+    assert!(validate_vote(&servers, &submission, &randomness, &mut public_param.ctx));
+}
 
-    let pos = (&ans + &(&ucase_z*&ucase_z)).rem(&public_param.q);
-    let neg = &ucase_z_star + &ucase_z_star_star;
-    let mut output = BigNum::new().unwrap();
-    _ = output.mod_sub(&pos, &neg, &public_param.q, &mut public_param.ctx);
-    assert_eq!(output, BigNum::new().unwrap());
+// Malicious clients: a two-hot vote and votes scaled by 2 are rejected, honest ones are counted
+#[test]
+fn test_poplar_rejects_malicious_votes(){
 
+    use crate::public_parameters::PublicParams;
+    use crate::verifiable_client::Client;
 
+    let (num_candidates, num_shares) = (4, 5);
+    let mut public_param = PublicParams::new(128, num_shares).unwrap();
+    let ctx = &mut public_param.ctx;
+    let q = &public_param.q;
+    let client = Client::new(num_shares, num_candidates as u32, q);
+    let mut servers: Vec<Server> = (0..num_shares).map(|_| Server::new(num_shares, num_candidates, q)).collect();
+
+    // Honest triple, A and B around an arbitrary vote and its kappa-scaled version
+    let malicious = |v: &[u32], kv_scale: u32, ctx: &mut BigNumContext| -> PoplarVote {
+        let mut submission = client.poplar_submission(0, ctx);
+        submission.vote = v.iter().map(|v_i| client.share(&BigNum::from_u32(*v_i).unwrap())).collect();
+        submission.kvote = v.iter().map(|v_i| {
+            let scaled = (&client.kappa * &BigNum::from_u32(*v_i * kv_scale).unwrap()).rem(q);
+            client.share(&scaled)
+        }).collect();
+        submission
+    };
+    let votes = vec![
+        client.poplar_submission(2, ctx),
+        malicious(&[1, 1, 0, 0], 1, ctx), // Two-hot
+        malicious(&[0, 0, 2, 0], 1, ctx), // Scaled, kappa v consistent with v
+        malicious(&[0, 0, 2, 0], 0, ctx), // Scaled, with kappa v left at 0
+        client.poplar_submission(0, ctx),
+        client.poplar_submission(2, ctx),
+    ];
+    let accepted = collect_votes(&mut servers, &votes, ctx);
+    assert_eq!(accepted, vec![true, false, false, false, true, true]);
+
+    let counts: Vec<BigNum> = [1, 0, 2, 0].iter().map(|c| BigNum::from_u32(*c).unwrap()).collect();
+    assert_eq!(tally(&servers), counts);
 }
-    */
\ No newline at end of file
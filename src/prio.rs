@@ -1,4 +1,4 @@
-use openssl::bn::{BigNum, BigNumContext};
+use openssl::bn::BigNum;
 // use core::num;
 // use openssl::error::ErrorStack;
 // use rand::distributions::{Bernoulli, Distribution};
@@ -6,6 +6,9 @@ use std::ops::Rem;
 use std::fmt;
 // use rand::Rng;
 use crate::utils::{gen_random};
+use crate::algebra::{BigNumField, Field};
+use crate::beaver_triple::BeaverTriple;
+use crate::verifiable_client;
 
 
 // use crate::utils::calculate_q;
@@ -13,6 +16,7 @@ pub struct Server{
     pub q: BigNum, // order of diffie Hellman group G
     pub num_candidates: usize,
     pub num_servers: usize,
+    pub totals: Vec<BigNum>, // This server's shares of the per-candidate counts of the accepted votes
 }
 
 // NOTE: 
@@ -36,27 +40,8 @@ impl Server{
        
     
         let q = &BigNum::new().unwrap() + _q;                
-        Self{q, num_candidates, num_servers}
-    }
-
-    pub fn multiply_first(&self, x_i: &BigNum, y_i: &BigNum, a_i: &BigNum, b_i: &BigNum, ctx: &mut BigNumContext)->(BigNum, BigNum){
-
-        let mut d_i = BigNum::new().unwrap();
-        let mut e_i = BigNum::new().unwrap();
-
-        _ = d_i.mod_sub(x_i, a_i, &self.q, ctx);
-        _ = e_i.mod_sub(y_i, b_i, &self.q, ctx);
-
-        return (d_i, e_i);
-    }
-    pub fn multiply_second(&self, a_i: &BigNum, b_i: &BigNum, c_i: &BigNum, e: &BigNum, d: &BigNum)->BigNum{
-
-        let db = d*b_i;
-        let ea = e*a_i;
-                
-        let xy_i = &(&(&db + &ea) + c_i);
-        return xy_i.rem(&self.q);
-
+        let totals = (0..num_candidates).map(|_| BigNum::new().unwrap()).collect();
+        Self{q, num_candidates, num_servers, totals}
     }
 
     pub fn generate_fresh_randomness(&self)->Vec<BigNum>{
@@ -70,94 +55,151 @@ impl Server{
         return r_vec;
     }
 
-    pub fn create_sketch_share_one(&self, x: &Vec<BigNum>, randomness: &Vec<BigNum>, a_i: &BigNum, b_i: &BigNum, ctx: &mut BigNumContext)->(BigNum, BigNum){
+    // Share of sum_i x_i, which is 1 for a one-hot vote
+    pub fn sum_share(&self, x: &[BigNum])->BigNum{
 
-        // let z = (z_i.iter().fold(BigNum::from_u32(0).unwrap(), |acc, x| &acc + x)).rem(&self.q); 
-        // let z_star = (z_i_star.iter().fold(BigNum::from_u32(0).unwrap(), |acc, x| &acc + x)).rem(&self.q);
-        let mut z = BigNum::new().unwrap();        
-        let mut tmp = BigNum::new().unwrap();
-        for i in 0..self.num_candidates{
-            _ = tmp.mod_mul(&x[i], &randomness[i], &self.q, ctx);
-            z = (&z + &tmp).rem(&self.q);
+        x.iter().fold(BigNum::new().unwrap(), |acc, x_i| (&acc + x_i).rem(&self.q))
+    }
+
+    // Adds the shares of an accepted vote to the running totals
+    pub fn accumulate(&mut self, x: &[BigNum]){
+
+        for (total, x_i) in self.totals.iter_mut().zip(x.iter()){
+            *total = (&*total + x_i).rem(&self.q);
         }
+    }
+
+}
+
+// The coordinates of a vote that server_idx holds
+fn server_shares(vote: &[verifiable_client::Share], server_idx: usize)->Vec<BigNum>{
+    vote.iter().map(|share| &share.shares[server_idx] + &BigNum::new().unwrap()).collect()
+}
+
+// Validity check of one vote across all servers, after the SNIP of Prio. For a one-hot x and random r,
+// (sum_i r_i x_i)^2 = sum_i r_i^2 x_i and the entries sum to 1. Any other x with entries summing to 1 fails the
+// first equation except with probability 2/q. The servers only publish d, e and their shares of the two checks,
+// which are masked by the triple or are the values the check expects. Runs check_vote over the BigNum backend.
+pub fn validate_vote(servers: &[Server], vote: &[verifiable_client::Share], triple: &BeaverTriple, randomness: &[BigNum])->bool{
 
-        // I need to get a share of [z*z]_i from z_i
-        return self.multiply_first(&z, &z, a_i, b_i, ctx);        
+    let num_servers = servers.len();
+    if vote.len() != servers[0].num_candidates || vote.iter().any(|share| share.shares.len() != num_servers){
+        return false;
     }
+    let shares: Vec<Vec<BigNum>> = (0..num_servers).map(|server_idx| server_shares(vote, server_idx)).collect();
+    check_vote(&BigNumField::new(&servers[0].q), &shares, [&triple.a_shares, &triple.b_shares, &triple.c_shares], randomness)
+}
 
-    pub fn create_sketch_share_two(&self, x: &Vec<BigNum>, randomness: &Vec<BigNum>, a_i: &BigNum, b_i: &BigNum, c_i: &BigNum, e: &BigNum, d: &BigNum, ctx: &mut BigNumContext)->BigNum{
+// The check of validate_vote over any field. shares[i] is server i's share of the vote and triple holds the
+// servers' shares of a, b and c.
+pub fn check_vote<F: Field>(field: &F, shares: &[Vec<F::Elem>], triple: [&[F::Elem]; 3], randomness: &[F::Elem])->bool{
 
-        let z_squared_share = self.multiply_second(a_i, b_i, c_i, e, d);
+    let [a, b, c] = triple;
+    if [a, b, c].iter().any(|t| t.len() != shares.len()) || shares.iter().any(|x| x.len() != randomness.len()){
+        return false;
+    }
 
-        let mut z_star = BigNum::new().unwrap();
-        let mut tmp = BigNum::new().unwrap();        
-        for i in 0..self.num_candidates{
-            _ = tmp.mod_mul(&x[i], &(&randomness[i] * &randomness[i]), &self.q, ctx);
-            z_star = (&z_star + &tmp).rem(&self.q);
-        }
-        
-        _ = tmp.mod_sub(&z_squared_share, &z_star, &self.q, ctx);
-        return tmp;        
+    // ROUND 1 of MPC: every server publishes its shares of d = z - a and e = z - b, for z = <x, r>
+    let z: Vec<F::Elem> = shares.iter().map(|x| field.dot(x, randomness)).collect();
+    let d = z.iter().zip(a.iter()).fold(field.zero(), |acc, (z_i, a_i)| field.add(&acc, &field.sub(z_i, a_i)));
+    let e = z.iter().zip(b.iter()).fold(field.zero(), |acc, (z_i, b_i)| field.add(&acc, &field.sub(z_i, b_i)));
+
+    // ROUND 2 of MPC: shares of z^2 - z*, with z^2 = de + d b + e a + c, and of the sum of the entries
+    let r_squared: Vec<F::Elem> = randomness.iter().map(|r| field.mul(r, r)).collect();
+    let mut sketch = field.mul(&d, &e);
+    let mut sum = field.zero();
+    for (server_idx, x) in shares.iter().enumerate(){
+        let z_squared = field.add(&field.add(&field.mul(&d, &b[server_idx]), &field.mul(&e, &a[server_idx])), &c[server_idx]);
+        sketch = field.add(&sketch, &field.sub(&z_squared, &field.dot(x, &r_squared)));
+        sum = field.add(&sum, &field.sum(x));
     }
+    sketch == field.zero() && sum == field.one()
+}
+
+// Full Prio flow for a batch of votes. Every vote gets a fresh dealer triple and fresh randomness, and the servers
+// add the shares of the valid ones to their totals. Returns which votes were accepted.
+pub fn collect_votes(servers: &mut [Server], votes: &[Vec<verifiable_client::Share>])->Vec<bool>{
+
+    let q = &servers[0].q + &BigNum::new().unwrap();
+    votes.iter().map(|vote| {
+        let triple = BeaverTriple::new(servers.len(), &q);
+        let randomness = servers[0].generate_fresh_randomness();
+        let valid = validate_vote(servers, vote, &triple, &randomness);
+        if valid{
+            for (server_idx, server) in servers.iter_mut().enumerate(){
+                server.accumulate(&server_shares(vote, server_idx));
+            }
+        }
+        valid
+    }).collect()
+}
+
+// Per-candidate counts, reconstructed from the totals every server publishes at the end
+pub fn tally(servers: &[Server])->Vec<BigNum>{
 
+    (0..servers[0].num_candidates).map(|coord| {
+        servers.iter().fold(BigNum::new().unwrap(), |acc, server| (&acc + &server.totals[coord]).rem(&server.q))
+    }).collect()
 }
 
-/*
+
+// Valid votes are counted, two-hot, scaled, empty and malformed votes are rejected
 #[test]
-fn test_sketching(){
+fn test_prio_flow(){
 
-    // use crate::utils::{gen_random, additive_share};
     use crate::public_parameters::PublicParams;
-    use crate::prio::Server;
     use crate::verifiable_client::Client;
-    use crate::beaver_triple::BeaverTriple;
-
-    let security_parameter = 4;
-    let num_candidates = 2; // Doesn't play a role in this test but need it to initialise server
-    let num_shares = 3; // num_servers
-    let mut public_param = PublicParams::new(security_parameter, num_shares).unwrap();
-    println!("{}", public_param);
-
-    let client = Client::new(num_shares, num_candidates as u32 , &public_param.q);    
-    let choice = client.generate_fake_vote();       
-    let vote = client.vote(choice, &mut public_param.ctx);
-
-    let mut servers = Vec::new();
-    for _ in 0..num_shares{
-        let server = Server::new(num_shares, num_candidates, &public_param.q);
-        servers.push(server);
-    }
 
-    // Pre-processing
-    let beaver_triples = BeaverTriple::new(num_shares, &public_param.q, &mut public_param.ctx);
-    let randomness = servers[0].generate_fresh_randomness();
-    // end
-
-    // ROUND 1 of MPC
-    let mut d = BigNum::new().unwrap();
-    let mut e = BigNum::new().unwrap();    
-    for server_idx in 0..num_shares{
-        let mut shares = Vec::new(); // Get the shares for server i (copy it over)
-        for coord in 0..num_candidates{
-            shares.push(&vote[coord].shares[server_idx] + &BigNum::new().unwrap());
-        }
-        let (d_i, e_i) = servers[server_idx].create_sketch_share_one(&shares, &randomness, &beaver_triples.a_shares[server_idx], &beaver_triples.b_shares[server_idx], &mut public_param.ctx);
-        d = (&d + &d_i).rem(&public_param.q);
-        e = (&e + &e_i).rem(&public_param.q);
-    }
+    let (num_candidates, num_shares) = (4, 3);
+    let public_param = PublicParams::new(128, num_shares).unwrap();
+    let client = Client::new(num_shares, num_candidates as u32, &public_param.q);
+    let mut servers: Vec<Server> = (0..num_shares).map(|_| Server::new(num_shares, num_candidates, &public_param.q)).collect();
+
+    // Shares an arbitrary vector the way a malicious client would
+    let encode = |x: &[u32]| -> Vec<verifiable_client::Share> {
+        x.iter().map(|x_i| client.share(&BigNum::from_u32(*x_i).unwrap())).collect()
+    };
+    let votes = vec![
+        client.vote(1),
+        encode(&[1, 1, 0, 0]),
+        client.vote(3),
+        encode(&[0, 2, 0, 0]),
+        encode(&[0, 0, 0, 0]),
+        encode(&[2, 0, 0, 0]),
+        encode(&[0, 1, 0]),
+        client.vote(1),
+    ];
+    let accepted = collect_votes(&mut servers, &votes);
+    assert_eq!(accepted, vec![true, false, true, false, false, false, false, true]);
+
+    let counts: Vec<BigNum> = [0, 2, 0, 1].iter().map(|c| BigNum::from_u32(*c).unwrap()).collect();
+    assert_eq!(tally(&servers), counts);
+}
 
-    // ROUND 2 of MPC
-    let mut ans = BigNum::new().unwrap();
-    for server_idx in 0..num_shares{
-        let mut shares = Vec::new(); // Get the shares for server i (copy it over)
-        for coord in 0..num_candidates{
-            shares.push(&vote[coord].shares[server_idx] + &BigNum::new().unwrap());
-        }
-        let tmp = servers[server_idx].create_sketch_share_two(&shares, &randomness, &beaver_triples.a_shares[server_idx], &beaver_triples.b_shares[server_idx], &beaver_triples.c_shares[server_idx], &e, &d, &mut public_param.ctx);
-        ans = (&ans + &tmp).rem(&public_param.q);
-    }    
-    ans = (&ans +  &(&d*&e)).rem(&public_param.q);
-    
-    assert_eq!(ans, BigNum::new().unwrap());
+// The same check with the pure Rust scalar field of curve25519
+#[test]
+fn test_check_vote_scalar_field(){
+
+    use crate::algebra::ScalarField;
+    use crate::generic_commitments::{AdditiveSharing, SecretSharing};
+
+    let num_servers = 3;
+    let sharing = AdditiveSharing{ field: ScalarField };
+    let field = &sharing.field;
+    let triple_values = {
+        let (a, b) = (field.random(), field.random());
+        let c = field.mul(&a, &b);
+        [a, b, c]
+    };
+    let triple: Vec<Vec<_>> = triple_values.iter().map(|t| sharing.share(t, num_servers)).collect();
+    let check = |x: &[u64]| {
+        let coords: Vec<Vec<_>> = x.iter().map(|x_i| sharing.share(&field.element(*x_i), num_servers)).collect();
+        let shares: Vec<Vec<_>> = (0..num_servers).map(|i| coords.iter().map(|c| c[i]).collect()).collect();
+        let randomness: Vec<_> = x.iter().map(|_| field.random()).collect();
+        check_vote(field, &shares, [&triple[0], &triple[1], &triple[2]], &randomness)
+    };
+    assert!(check(&[0, 0, 1, 0]));
+    assert!(!check(&[0, 1, 1, 0]));
+    assert!(!check(&[0, 0, 0, 0]));
+    assert!(!check(&[2, 0, 0, 0]));
 }
-     */
\ No newline at end of file
@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+// Progress of the long protocol steps. BinomialRunner reports through a ProgressHook while it commits to the
+// inputs, proves the private bits and verifies them, and the server forwards the events as server-sent events.
+
+pub const INPUT_COMMITMENTS: &str = "input_commitments";
+pub const PRIVATE_BIT_PROOFS: &str = "private_bit_proofs";
+pub const PRIVATE_BIT_VERIFICATION: &str = "private_bit_verification";
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ProgressEvent {
+    pub step: String,
+    pub done: usize,
+    pub total: usize,
+    pub elapsed_ms: u64, // Since the step started
+}
+
+pub type ProgressHook = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+// Reports per step, not per item, so large steps do not flood the hook
+const REPORTS_PER_STEP: usize = 100;
+
+// Counts the items of one step, possibly from several rayon threads. Reports once when created, then about every
+// 1% of the items and once more when the last one is done.
+pub struct StepProgress<'a> {
+    hook: Option<&'a ProgressHook>,
+    step: &'static str,
+    total: usize,
+    every: usize,
+    done: AtomicUsize,
+    start: Instant,
+}
+
+impl<'a> StepProgress<'a> {
+    pub fn new(hook: Option<&'a ProgressHook>, step: &'static str, total: usize) -> StepProgress<'a> {
+        let progress = Self {
+            hook,
+            step,
+            total,
+            every: (total / REPORTS_PER_STEP).max(1),
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+        };
+        progress.report(0);
+        progress
+    }
+
+    pub fn tick(&self) {
+        if self.hook.is_some() {
+            let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+            if done.is_multiple_of(self.every) || done == self.total {
+                self.report(done);
+            }
+        }
+    }
+
+    fn report(&self, done: usize) {
+        if let Some(hook) = self.hook {
+            hook(ProgressEvent {
+                step: self.step.to_string(),
+                done,
+                total: self.total,
+                elapsed_ms: self.start.elapsed().as_millis() as u64,
+            });
+        }
+    }
+}
+
+
+#[test]
+pub fn test_step_progress() {
+    use std::sync::Mutex;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook: ProgressHook = {
+        let events = events.clone();
+        Arc::new(move |e: ProgressEvent| events.lock().unwrap().push(e.done))
+    };
+    let progress = StepProgress::new(Some(&hook), INPUT_COMMITMENTS, 1050);
+    (0..1050).for_each(|_| progress.tick());
+
+    // 0, every 10 items up to 1050, and 1050 itself
+    let done = events.lock().unwrap().clone();
+    assert_eq!(done.len(), 1 + 105);
+    assert_eq!(done.first(), Some(&0));
+    assert_eq!(done.last(), Some(&1050));
+    assert!(done.windows(2).all(|w| w[0] < w[1]));
+}
@@ -21,7 +21,7 @@ impl PublicParams{
 
     pub fn new(security: i32, num_shares: usize) -> Result<PublicParams, ErrorStack> {
         
-        println!("Generating Public Parameters");
+        let _span = tracing::info_span!("public_parameters", security, num_shares).entered();
         
         // create context to manage the bignum
         let ctx = BigNumContext::new()?;
@@ -29,7 +29,7 @@ impl PublicParams{
         // generate prime number with 2*security bits
         let mut q = BigNum::new()?;        
         q.generate_prime(security, true, None, None)?;        
-        println!("Done\n");
+        tracing::debug!(bits = q.num_bits(), "Generated the prime modulus");
         Ok(Self { num_shares, q, ctx })
         
     }
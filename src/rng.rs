@@ -0,0 +1,152 @@
+use curve25519_dalek::scalar::Scalar;
+use rand_chacha::ChaCha20Rng;
+use rand_core::{CryptoRng, OsRng, RngCore, SeedableRng};
+
+// Where the curve25519 protocols get their randomness. The commitments, OR-proofs, sigma protocols, bit-vector
+// proofs, BinomialRunner, ContinualCounter and the noise servers have *_with_rng variants taking any
+// RngCore + CryptoRng, and the plain variants pass OsRng. A ProtocolRng seeded with ChaCha20 replays a run exactly
+// (commitments, proofs, coins and session id) for tests, simulations and the known-answer vectors. A seeded run's
+// noise is predictable from the seed, so BinomialRunner::compute_sum refuses to release it.
+//
+// Not covered, and drawing from OsRng directly: ScalarField::random, and through it the generic Pedersen
+// commitments and Shamir sharing. The OpenSSL side (BigNumField::random, utils, prio, poplar, finite_field_coms)
+// draws from OpenSSL's own generator, and the heavy hitters simulation from rand::thread_rng.
+
+// Object safe RngCore + CryptoRng, for the sigma protocol trait objects
+pub trait CryptoRngCore: RngCore + CryptoRng {}
+
+impl<R: RngCore + CryptoRng + ?Sized> CryptoRngCore for R {}
+
+// RNG owned by a long-lived participant: OsRng in production, ChaCha20 from a seed for replays.
+// No Debug, which would print the ChaCha state and with it every value still to be drawn.
+#[derive(Clone, Default)]
+pub enum ProtocolRng {
+    #[default]
+    Os,
+    Seeded(Box<ChaCha20Rng>),
+}
+
+impl ProtocolRng {
+
+    pub fn seeded(seed: u64) -> ProtocolRng {
+        ProtocolRng::Seeded(Box::new(ChaCha20Rng::seed_from_u64(seed)))
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> ProtocolRng {
+        ProtocolRng::Seeded(Box::new(ChaCha20Rng::from_seed(seed)))
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        matches!(self, ProtocolRng::Seeded(_))
+    }
+
+    // Independent generators for the items of a parallel step, so a seeded run does not depend on how rayon
+    // schedules them. Advances self by one key.
+    pub fn streams(&mut self) -> RngStreams {
+        match self {
+            ProtocolRng::Os => RngStreams(None),
+            ProtocolRng::Seeded(rng) => {
+                let mut key = [0; 32];
+                rng.fill_bytes(&mut key);
+                RngStreams(Some(key))
+            },
+        }
+    }
+}
+
+impl RngCore for ProtocolRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            ProtocolRng::Os => OsRng.next_u32(),
+            ProtocolRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            ProtocolRng::Os => OsRng.next_u64(),
+            ProtocolRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            ProtocolRng::Os => OsRng.fill_bytes(dest),
+            ProtocolRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        match self {
+            ProtocolRng::Os => OsRng.try_fill_bytes(dest),
+            ProtocolRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for ProtocolRng {}
+
+// Stream i of a ChaCha20 key, or OsRng for every i
+#[derive(Clone, Copy)]
+pub struct RngStreams(Option<[u8; 32]>);
+
+impl RngStreams {
+
+    pub fn get(&self, i: usize) -> ProtocolRng {
+        match self.0 {
+            None => ProtocolRng::Os,
+            Some(key) => {
+                let mut rng = ChaCha20Rng::from_seed(key);
+                rng.set_stream(i as u64);
+                ProtocolRng::Seeded(Box::new(rng))
+            },
+        }
+    }
+}
+
+// Uniform scalar, the same as Scalar::random but callable on a trait object
+pub fn random_scalar<R: RngCore + CryptoRng + ?Sized>(rng: &mut R) -> Scalar {
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+// Fair coin
+pub fn flip<R: RngCore + ?Sized>(rng: &mut R) -> bool {
+    rng.next_u32() & 1 == 1
+}
+
+// Uniform index below len, rejecting the top of the range so there is no modulo bias
+pub fn index<R: RngCore + ?Sized>(rng: &mut R, len: usize) -> usize {
+    assert!(len > 0);
+    let len = len as u64;
+    let zone = u64::MAX - u64::MAX % len;
+    loop {
+        let x = rng.next_u64();
+        if x < zone {
+            return (x % len) as usize;
+        }
+    }
+}
+
+
+#[test]
+pub fn test_protocol_rng() {
+    let (mut a, mut b) = (ProtocolRng::seeded(7), ProtocolRng::seeded(7));
+    assert_eq!(random_scalar(&mut a), random_scalar(&mut b));
+    assert_ne!(random_scalar(&mut ProtocolRng::seeded(7)), random_scalar(&mut ProtocolRng::seeded(8)));
+    assert_ne!(random_scalar(&mut ProtocolRng::Os), random_scalar(&mut ProtocolRng::Os));
+
+    // Streams depend on the index only, not on the order they are taken in
+    let (sa, sb) = (a.streams(), b.streams());
+    assert_eq!(random_scalar(&mut sa.get(3)), random_scalar(&mut sb.get(3)));
+    assert_ne!(random_scalar(&mut sa.get(3)), random_scalar(&mut sa.get(4)));
+    assert!(!ProtocolRng::Os.streams().get(0).is_seeded());
+
+    let mut counts = [0; 3];
+    for _ in 0..300 {
+        counts[index(&mut a, 3)] += 1;
+    }
+    assert!(counts.iter().all(|c| *c > 50));
+    assert!((0..64).any(|_| flip(&mut a)) && (0..64).any(|_| !flip(&mut a)));
+}
@@ -0,0 +1,116 @@
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+// The prover's secrets: the private noise bits and their randomness, the sums of the inputs and of their
+// randomness, and the openings of the Bernoulli coins. They live in Secret, which wipes them on drop and never
+// prints them, and the conversions between bits and scalars below select with subtle instead of branching.
+//
+// What the review of binomial_runner.rs and participants.rs left as it is:
+// - participants::Server::distributional_commitment_bernoulli samples its coins with branches on the counts.
+// - Runners are serialized to the session store in the clear, so the store has to be protected like the server.
+// - Copies made by rayon's collect and by Vec growth are not wiped. Only the buffer a Secret owns at drop is.
+// - Secrets mod q in OpenSSL BigNums (prio, poplar, finite_field_coms) are out of scope.
+
+// A value that is zeroized on drop and shows as "Secret(..)" in Debug. Serialized as the bare value, so stored
+// sessions keep their format.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+
+    pub fn new(value: T) -> Secret<T> {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Zeroize for Secret<T> {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> ZeroizeOnDrop for Secret<T> {}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+// 1 if choice is set, 0 otherwise
+pub fn scalar_from_choice(choice: Choice) -> Scalar {
+    Scalar::conditional_select(&Scalar::zero(), &Scalar::one(), choice)
+}
+
+// A bit as sent by the prover: 1 is one, anything else zero
+pub fn bit_to_scalar(bit: u8) -> Scalar {
+    scalar_from_choice(bit.ct_eq(&1))
+}
+
+// 1 for the scalar one, 0 for anything else
+pub fn scalar_to_bit(s: &Scalar) -> u8 {
+    s.ct_eq(&Scalar::one()).unwrap_u8()
+}
+
+// b XOR c for a bit b and a coin c, as scalars
+pub fn xor_bit(b: &Scalar, c: &Scalar) -> Scalar {
+    Scalar::conditional_select(b, &(Scalar::one() - b), c.ct_eq(&Scalar::one()))
+}
+
+// The randomness of b XOR c when b was committed under r: -r for a one coin, r otherwise
+pub fn xor_randomness(r: &Scalar, c: &Scalar) -> Scalar {
+    Scalar::conditional_select(r, &-r, c.ct_eq(&Scalar::one()))
+}
+
+
+#[test]
+pub fn test_secret_handling() {
+    let mut secret = Secret::new(vec![Scalar::one(), Scalar::from(7u64)]);
+    assert_eq!(format!("{:?}", secret), "Secret(..)");
+    assert_eq!(serde_json::to_string(&secret).unwrap(), serde_json::to_string(&*secret).unwrap());
+    let json = serde_json::to_string(&secret).unwrap();
+    let back: Secret<Vec<Scalar>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(*back, *secret);
+    secret.zeroize();
+    assert!(secret.is_empty());
+
+    let (zero, one) = (Scalar::zero(), Scalar::one());
+    assert_eq!([0, 1, 2].map(bit_to_scalar), [zero, one, zero]);
+    assert_eq!([zero, one, Scalar::from(2u64)].map(|s| scalar_to_bit(&s)), [0, 1, 0]);
+    for (b, c, x) in [(zero, zero, zero), (zero, one, one), (one, zero, one), (one, one, zero)] {
+        assert_eq!(xor_bit(&b, &c), x);
+    }
+    let r = Scalar::from(5u64);
+    assert_eq!((xor_randomness(&r, &zero), xor_randomness(&r, &one)), (r, -r));
+}
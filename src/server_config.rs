@@ -0,0 +1,264 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::binomial_runner::DEFAULT_DATASET;
+use crate::logging::LogFormat;
+use crate::metrics::valid_dataset_name;
+use crate::secrets::Secret;
+use crate::sessions::SessionLimits;
+
+// Server configuration. Every setting can come from a CLI flag, an environment variable or the TOML file given
+// by --config, in that order of precedence, and falls back to the defaults below.
+
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 9537; // Port the webdemo frontend expects
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+// One layer of settings. clap fills it from the command line and environment, serde from the TOML file.
+#[derive(Parser, Deserialize, Debug, Default)]
+#[command(about = "Verifiable differential privacy server")]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    #[arg(long, env = "ZKDP_CONFIG")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "ZKDP_BIND")]
+    pub bind: Option<String>,
+    #[arg(long, env = "ZKDP_PORT")]
+    pub port: Option<u16>,
+    #[arg(long, env = "ZKDP_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>, // PEM certificate chain
+    #[arg(long, env = "ZKDP_TLS_KEY")]
+    pub tls_key: Option<PathBuf>, // PEM private key
+    #[arg(long = "cors-origin", env = "ZKDP_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>, // "*" allows any origin
+    #[arg(long, env = "ZKDP_WORKERS")]
+    pub workers: Option<usize>,
+    #[arg(long, env = "ZKDP_MAX_REQUEST_BYTES")]
+    pub max_request_bytes: Option<usize>,
+    #[arg(long, env = "ZKDP_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    #[arg(long, env = "ZKDP_LOG_FORMAT")]
+    pub log_format: Option<String>, // "text" or "json"
+    #[arg(long, env = "ZKDP_LEGACY_ROUTES", num_args = 0..=1, default_missing_value = "true")]
+    pub legacy_routes: Option<bool>, // Unauthenticated unversioned routes for the webdemo
+    #[arg(long, env = "ZKDP_OPERATOR_TOKEN", hide_env_values = true)]
+    pub operator_token: Option<Secret<String>>, // Bearer token for POST /v1/sessions
+    #[arg(long = "dataset", env = "ZKDP_DATASETS", value_delimiter = ',')]
+    pub datasets: Option<Vec<String>>, // Datasets sessions may name, each one a label of the privacy budget metric
+    #[arg(long, env = "ZKDP_SESSION_DIR")]
+    pub session_dir: Option<PathBuf>,
+    #[arg(long, env = "ZKDP_SESSION_TTL_SECS")]
+    pub session_ttl_secs: Option<u64>,
+    #[arg(long, env = "ZKDP_MAX_SESSIONS")]
+    pub max_sessions: Option<usize>,
+    #[arg(long, env = "ZKDP_MAX_INPUT_LEN")]
+    pub max_input_len: Option<usize>,
+    #[arg(long, env = "ZKDP_MAX_NOISE_BITS")]
+    pub max_noise_bits: Option<usize>,
+    #[arg(long, env = "ZKDP_MAX_TOTAL_INPUTS")]
+    pub max_total_inputs: Option<usize>,
+    #[arg(long, env = "ZKDP_MAX_TOTAL_NOISE_BITS")]
+    pub max_total_noise_bits: Option<usize>,
+}
+
+impl ConfigLayer {
+
+    pub fn from_toml(text: &str) -> Result<ConfigLayer, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    // Settings of self win, the gaps are filled from lower
+    pub fn or(self, lower: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            config: self.config.or(lower.config),
+            bind: self.bind.or(lower.bind),
+            port: self.port.or(lower.port),
+            tls_cert: self.tls_cert.or(lower.tls_cert),
+            tls_key: self.tls_key.or(lower.tls_key),
+            cors_origins: self.cors_origins.or(lower.cors_origins),
+            workers: self.workers.or(lower.workers),
+            max_request_bytes: self.max_request_bytes.or(lower.max_request_bytes),
+            log_level: self.log_level.or(lower.log_level),
+            log_format: self.log_format.or(lower.log_format),
+            legacy_routes: self.legacy_routes.or(lower.legacy_routes),
+            operator_token: self.operator_token.or(lower.operator_token),
+            datasets: self.datasets.or(lower.datasets),
+            session_dir: self.session_dir.or(lower.session_dir),
+            session_ttl_secs: self.session_ttl_secs.or(lower.session_ttl_secs),
+            max_sessions: self.max_sessions.or(lower.max_sessions),
+            max_input_len: self.max_input_len.or(lower.max_input_len),
+            max_noise_bits: self.max_noise_bits.or(lower.max_noise_bits),
+            max_total_inputs: self.max_total_inputs.or(lower.max_total_inputs),
+            max_total_noise_bits: self.max_total_noise_bits.or(lower.max_total_noise_bits),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+
+    // Reads the PEM files into a rustls configuration
+    pub fn load(&self) -> io::Result<rustls::ServerConfig> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let certs = rustls_pemfile::certs(&mut io::BufReader::new(fs::File::open(&self.cert)?)).collect::<Result<Vec<_>, _>>()?;
+        let key = rustls_pemfile::private_key(&mut io::BufReader::new(fs::File::open(&self.key)?))?
+            .ok_or_else(|| invalid(format!("no private key in {}", self.key.display())))?;
+        rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid(e.to_string()))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(e.to_string()))
+    }
+}
+
+// Effective configuration after all layers are applied
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+    pub cors_origins: Vec<String>,
+    pub workers: usize,
+    pub max_request_bytes: usize,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub legacy_routes: bool,
+    pub operator_token: Option<Secret<String>>, // Without one, no session can be created through /v1
+    pub datasets: Vec<String>, // Besides "default"
+    pub session_dir: Option<PathBuf>,
+    pub limits: SessionLimits,
+}
+
+impl ServerConfig {
+
+    // Parses the command line and environment, then reads the config file if one was named
+    pub fn load() -> Result<ServerConfig, String> {
+        let cli = ConfigLayer::parse();
+        let file = match &cli.config {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                ConfigLayer::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+            },
+            None => ConfigLayer::default(),
+        };
+        Self::resolve(cli.or(file))
+    }
+
+    pub fn resolve(layer: ConfigLayer) -> Result<ServerConfig, String> {
+        let defaults = SessionLimits::default();
+        let limits = SessionLimits {
+            ttl: layer.session_ttl_secs.map_or(defaults.ttl, Duration::from_secs),
+            max_sessions: layer.max_sessions.unwrap_or(defaults.max_sessions),
+            max_input_len: layer.max_input_len.unwrap_or(defaults.max_input_len),
+            max_noise_bits: layer.max_noise_bits.unwrap_or(defaults.max_noise_bits),
+            max_total_inputs: layer.max_total_inputs.unwrap_or(defaults.max_total_inputs),
+            max_total_noise_bits: layer.max_total_noise_bits.unwrap_or(defaults.max_total_noise_bits),
+        };
+        let tls = match (layer.tls_cert, layer.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsConfig { cert, key }),
+            (None, None) => None,
+            _ => return Err("tls_cert and tls_key must be given together".to_string()),
+        };
+        // Every u8 of a JSON array takes at most 4 bytes ("255,")
+        let max_request_bytes = layer.max_request_bytes.unwrap_or(4 * limits.max_input_len.max(limits.max_noise_bits) + 1024);
+        let datasets = layer.datasets.unwrap_or_default();
+        if let Some(dataset) = datasets.iter().find(|d| !valid_dataset_name(d)) {
+            return Err(format!("dataset {:?} must be 1 to 64 characters out of A-Z, a-z, 0-9, '-', '_' and '.'", dataset));
+        }
+        let workers = match layer.workers {
+            Some(0) => return Err("workers must be at least 1".to_string()),
+            Some(n) => n,
+            None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        Ok(Self {
+            bind: layer.bind.unwrap_or_else(|| DEFAULT_BIND.to_string()),
+            port: layer.port.unwrap_or(DEFAULT_PORT),
+            tls,
+            cors_origins: layer.cors_origins.unwrap_or_default(),
+            workers,
+            max_request_bytes,
+            log_level: layer.log_level.unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string()),
+            log_format: layer.log_format.as_deref().map_or(Ok(LogFormat::Text), str::parse)?,
+            legacy_routes: layer.legacy_routes.unwrap_or(false),
+            operator_token: layer.operator_token.filter(|token| !token.is_empty()),
+            datasets,
+            session_dir: layer.session_dir,
+            limits,
+        })
+    }
+}
+
+impl fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        writeln!(f, "listen: {}://{}:{}", scheme, self.bind, self.port)?;
+        if let Some(tls) = &self.tls {
+            writeln!(f, "tls: cert {}, key {}", tls.cert.display(), tls.key.display())?;
+        }
+        writeln!(f, "cors origins: {}", if self.cors_origins.is_empty() { "none".to_string() } else { self.cors_origins.join(", ") })?;
+        writeln!(f, "workers: {}", self.workers)?;
+        writeln!(f, "max request bytes: {}", self.max_request_bytes)?;
+        writeln!(f, "log level: {} ({})", self.log_level, self.log_format)?;
+        writeln!(f, "legacy routes: {}", if self.legacy_routes { "on (unauthenticated)" } else { "off" })?;
+        writeln!(f, "operator token: {}", if self.operator_token.is_some() { "set" } else { "none (POST /v1/sessions is refused)" })?;
+        writeln!(f, "datasets: {}", std::iter::once(DEFAULT_DATASET).chain(self.datasets.iter().map(String::as_str)).collect::<Vec<_>>().join(", "))?;
+        writeln!(f, "session store: {}", self.session_dir.as_ref().map_or("memory".to_string(), |d| d.display().to_string()))?;
+        write!(
+            f, "session limits: ttl {}s, {} sessions, {} inputs and {} noise bits per session, {} inputs and {} noise bits in total",
+            self.limits.ttl.as_secs(), self.limits.max_sessions, self.limits.max_input_len, self.limits.max_noise_bits,
+            self.limits.max_total_inputs, self.limits.max_total_noise_bits,
+        )
+    }
+}
+
+
+#[test]
+pub fn test_config_layers() {
+    // Parses flags only, so ZKDP_* variables of the machine running the tests cannot change the result
+    fn parse_flags(args: &[&str]) -> ConfigLayer {
+        use clap::{CommandFactory, FromArgMatches};
+        let command = ConfigLayer::command().mut_args(|arg| arg.env(None));
+        ConfigLayer::from_arg_matches(&command.try_get_matches_from(args).unwrap()).unwrap()
+    }
+
+    let file = ConfigLayer::from_toml(r#"
+        bind = "0.0.0.0"
+        port = 8443
+        cors_origins = ["http://localhost:5173"]
+        session_ttl_secs = 600
+        max_input_len = 10
+    "#).unwrap();
+    let cli = parse_flags(&["server", "--port", "9000", "--cors-origin", "https://a.example,https://b.example", "--legacy-routes", "--dataset", "census,survey"]);
+    let config = ServerConfig::resolve(cli.or(file)).unwrap();
+
+    assert_eq!(config.bind, "0.0.0.0"); // Only in the file
+    assert_eq!(config.port, 9000); // Flag beats file
+    assert_eq!(config.cors_origins, vec!["https://a.example", "https://b.example"]);
+    assert_eq!(config.limits.ttl, Duration::from_secs(600));
+    assert_eq!(config.limits.max_input_len, 10);
+    assert_eq!(config.limits.max_sessions, SessionLimits::default().max_sessions);
+    assert_eq!(config.max_request_bytes, 4 * SessionLimits::default().max_noise_bits + 1024);
+    assert!(config.tls.is_none());
+    assert!(config.legacy_routes);
+    assert_eq!(config.datasets, vec!["census", "survey"]);
+    assert_eq!(config.log_format, LogFormat::Text);
+
+    assert!(ConfigLayer::from_toml("prot = 1").is_err()); // Typos are reported, not ignored
+    assert!(ServerConfig::resolve(ConfigLayer { tls_cert: Some("cert.pem".into()), ..ConfigLayer::default() }).is_err());
+    assert_eq!(ServerConfig::resolve(ConfigLayer::default()).unwrap().port, DEFAULT_PORT);
+    assert_eq!(ServerConfig::resolve(ConfigLayer { log_format: Some("json".into()), ..ConfigLayer::default() }).unwrap().log_format, LogFormat::Json);
+    assert!(ServerConfig::resolve(ConfigLayer { log_format: Some("xml".into()), ..ConfigLayer::default() }).is_err());
+    assert!(ServerConfig::resolve(ConfigLayer { datasets: Some(vec!["census 2020".into()]), ..ConfigLayer::default() }).is_err());
+}
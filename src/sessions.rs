@@ -0,0 +1,751 @@
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use tracing::warn;
+use crate::binomial_runner::{BinomialRunner, Phase};
+use crate::continual_counting::{ContinualCounter, TreeNoise};
+use utoipa::ToSchema;
+
+// Session bookkeeping for the server. Sessions expire after a TTL without access, the number of sessions and the
+// number of input and noise bits are capped per session and over all sessions, and a SessionStore keeps the runners
+// across restarts.
+
+#[derive(Clone, Debug)]
+pub struct SessionLimits {
+    pub ttl: Duration,
+    pub max_sessions: usize,
+    pub max_input_len: usize, // Per session number of x_i
+    pub max_noise_bits: usize, // Per session n_b
+    pub max_total_inputs: usize, // Over all live sessions
+    pub max_total_noise_bits: usize,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60 * 60),
+            max_sessions: 1000,
+            max_input_len: 1_000_000,
+            max_noise_bits: 1_000_000,
+            max_total_inputs: 10_000_000,
+            max_total_noise_bits: 10_000_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    NotFound,
+    TooManySessions(usize),
+    InputTooLarge { len: usize, max: usize },
+    NoiseTooLarge { len: usize, max: usize },
+    Unauthorized,
+    Forbidden(Role),
+    InputsOpen, // A noise step was called while the clients may still submit inputs
+    TooManyClients(usize), // More client tokens than the session takes inputs
+    NoCounter, // The session has no continual counter yet
+    CounterStarted, // The session already has a continual counter, whose nodes later releases need
+    Store(io::Error),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::NotFound => write!(f, "Runner not found for this session"),
+            SessionError::TooManySessions(max) => write!(f, "Too many open sessions (limit {})", max),
+            SessionError::InputTooLarge { len, max } => write!(f, "Input of length {} exceeds the limit of {}", len, max),
+            SessionError::NoiseTooLarge { len, max } => write!(f, "{} noise bits exceed the limit of {}", len, max),
+            SessionError::Unauthorized => write!(f, "Missing or invalid capability token for this session"),
+            SessionError::Forbidden(role) => write!(f, "The {:?} role may not call this endpoint", role),
+            SessionError::InputsOpen => write!(f, "Input collection is still open, the noise steps start once it closes"),
+            SessionError::TooManyClients(max) => write!(f, "This session takes inputs from at most {} clients", max),
+            SessionError::NoCounter => write!(f, "This session has no continual counter"),
+            SessionError::CounterStarted => write!(f, "This session already has a continual counter"),
+            SessionError::Store(e) => write!(f, "Session store error: {}", e),
+        }
+    }
+}
+
+impl SessionError {
+
+    // Stable code for API clients, independent of the message wording
+    pub fn code(&self) -> &'static str {
+        match self {
+            SessionError::NotFound => "session_not_found",
+            SessionError::TooManySessions(_) => "too_many_sessions",
+            SessionError::InputTooLarge { .. } => "input_too_large",
+            SessionError::NoiseTooLarge { .. } => "noise_too_large",
+            SessionError::Unauthorized => "unauthorized",
+            SessionError::Forbidden(_) => "forbidden",
+            SessionError::InputsOpen => "inputs_open",
+            SessionError::TooManyClients(_) => "too_many_clients",
+            SessionError::NoCounter => "no_counter",
+            SessionError::CounterStarted => "counter_started",
+            SessionError::Store(_) => "store_error",
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Store(e)
+    }
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// Who is calling. Clients submit inputs, the prover supplies the private randomness and runs the mechanism,
+// auditors only read commitments and transcripts. In sessions with noise servers, each of them supplies its
+// share of the noise instead of the prover, see distributed_noise.rs. In sessions that collect inputs, each
+// aggregator supplies its share of the opening of the inputs, see submissions.rs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Client,
+    Prover,
+    Auditor,
+    NoiseServer(usize),
+    Aggregator(usize),
+}
+
+// Bearer tokens handed out once when a session is created, one per role. Client tokens are issued later,
+// one per client, by SessionManager::issue_client_tokens.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CapabilityTokens {
+    pub prover: String,
+    pub auditor: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub noise_servers: Vec<String>, // One per noise server, in server order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aggregators: Vec<String>, // One per aggregator, in aggregator order
+}
+
+// A client's token and the client ID it is bound to
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ClientToken {
+    pub client_id: String,
+    pub token: String,
+}
+
+pub(crate) fn token_hash(token: &str) -> String {
+    Sha3_256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Compares a stored token hash with the hash of a presented token. The time taken does not depend on where they differ.
+pub(crate) fn same_hash(stored: &str, presented: &str) -> bool {
+    !stored.is_empty() && bool::from(stored.as_bytes().ct_eq(presented.as_bytes()))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Only hashes of the tokens are kept, so a leaked session file does not leak access.
+// Sessions saved before tokens existed have none and can only be used through the legacy routes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TokenHashes {
+    prover: String,
+    auditor: String,
+    #[serde(default)]
+    noise_servers: Vec<String>,
+    #[serde(default)]
+    aggregators: Vec<String>,
+    #[serde(default)]
+    clients: HashMap<String, String>, // Token hash to client ID, looked up by hash as there may be millions
+}
+
+impl TokenHashes {
+
+    fn issue(noise_servers: usize, aggregators: usize) -> (TokenHashes, CapabilityTokens) {
+        let tokens = CapabilityTokens {
+            prover: random_token(),
+            auditor: random_token(),
+            noise_servers: (0..noise_servers).map(|_| random_token()).collect(),
+            aggregators: (0..aggregators).map(|_| random_token()).collect(),
+        };
+        let hashes = Self {
+            prover: token_hash(&tokens.prover),
+            auditor: token_hash(&tokens.auditor),
+            noise_servers: tokens.noise_servers.iter().map(|t| token_hash(t)).collect(),
+            aggregators: tokens.aggregators.iter().map(|t| token_hash(t)).collect(),
+            clients: HashMap::new(),
+        };
+        (hashes, tokens)
+    }
+
+    fn role(&self, token: &str) -> Option<Role> {
+        let hash = token_hash(token);
+        let noise_servers = self.noise_servers.iter().enumerate().map(|(j, h)| (Role::NoiseServer(j), h));
+        let aggregators = self.aggregators.iter().enumerate().map(|(j, h)| (Role::Aggregator(j), h));
+        [(Role::Prover, &self.prover), (Role::Auditor, &self.auditor)].into_iter()
+            .chain(noise_servers)
+            .chain(aggregators)
+            .find(|(_, h)| same_hash(h, &hash))
+            .map(|(role, _)| role)
+            .or_else(|| self.clients.contains_key(&hash).then_some(Role::Client))
+    }
+
+    fn client_id(&self, token: &str) -> Option<String> {
+        self.clients.get(&token_hash(token)).cloned()
+    }
+}
+
+// What a store keeps per session. The last access time is kept so the TTL still holds after a restart.
+#[derive(Deserialize)]
+pub struct StoredSession {
+    pub last_access: u64,
+    #[serde(default)]
+    pub tokens: TokenHashes,
+    pub runner: BinomialRunner,
+    #[serde(default)]
+    pub counter: Option<ContinualCounter>,
+}
+
+// Borrowed form of StoredSession, so saving does not copy the runner
+#[derive(Serialize)]
+pub struct StoredSessionRef<'a> {
+    pub last_access: u64,
+    pub tokens: &'a TokenHashes,
+    pub runner: &'a BinomialRunner,
+    pub counter: Option<&'a ContinualCounter>,
+}
+
+pub trait SessionStore: Send + Sync {
+    fn save(&self, session_id: &str, session: &StoredSessionRef) -> io::Result<()>;
+    fn load_all(&self) -> io::Result<Vec<(String, StoredSession)>>;
+    fn remove(&self, session_id: &str) -> io::Result<()>;
+}
+
+// Keeps nothing. Sessions live only as long as the process.
+pub struct MemoryStore;
+
+impl SessionStore for MemoryStore {
+    fn save(&self, _session_id: &str, _session: &StoredSessionRef) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn load_all(&self) -> io::Result<Vec<(String, StoredSession)>> {
+        Ok(Vec::new())
+    }
+
+    fn remove(&self, _session_id: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// One JSON file per session in a directory. Writes go to a temporary file first and are renamed into place.
+// The files hold the prover's secrets in the clear: the noise bits, their randomness and the sums of the inputs.
+// On Unix a new directory is created 0700 and the files are written 0600. Nothing is encrypted, so the directory
+// and its backups have to be protected like the server itself.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<FileStore> {
+        let dir = dir.into();
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, session_id: &str) -> io::Result<PathBuf> {
+        // Session IDs are UUIDs. Anything else could escape the directory.
+        if session_id.is_empty() || !session_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid session id"));
+        }
+        Ok(self.dir.join(format!("{}.json", session_id)))
+    }
+}
+
+fn read_session(path: &Path) -> Result<StoredSession, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+impl SessionStore for FileStore {
+    fn save(&self, session_id: &str, session: &StoredSessionRef) -> io::Result<()> {
+        let path = self.path(session_id)?;
+        let tmp = path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&tmp)?.write_all(&serde_json::to_vec(session)?)?;
+        fs::rename(tmp, path)
+    }
+
+    // A file that does not load, cut short by a crash or written by an incompatible version, is renamed to
+    // <session_id>.json.corrupt and skipped, so one bad session does not keep the server from starting.
+    fn load_all(&self) -> io::Result<Vec<(String, StoredSession)>> {
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(session_id) = path.file_stem().and_then(|s| s.to_str()) {
+                    match read_session(&path) {
+                        Ok(session) => sessions.push((session_id.to_string(), session)),
+                        Err(error) => {
+                            let aside = path.with_extension("json.corrupt");
+                            warn!(path = %path.display(), %error, "Skipped a session file that does not load");
+                            if let Err(error) = fs::rename(&path, &aside) {
+                                warn!(path = %path.display(), %error, "Could not move the session file aside");
+                            }
+                        },
+                    }
+                }
+            }
+        }
+        Ok(sessions)
+    }
+
+    fn remove(&self, session_id: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(session_id)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+const NUM_SHARDS: usize = 16;
+
+// A live session. The runner has its own lock, so heavy crypto on one session never blocks another.
+// The sizes are kept next to the lock so the global limits can be checked without waiting on busy runners.
+// A session may also keep a continual counter for running counts over its dataset, saved with the runner.
+pub struct Session {
+    last_access: AtomicU64,
+    num_inputs: AtomicUsize, // Including the inputs a collecting session may still take
+    num_noise_bits: AtomicUsize,
+    counter_noise_bits: AtomicUsize, // Committed for every node of the continual counter, counted with the runner's
+    phase: AtomicU8, // Index into Phase::ALL
+    tokens: RwLock<TokenHashes>, // Locked after the runner and the counter, written only to issue client tokens
+    runner: Mutex<BinomialRunner>,
+    counter: Mutex<Option<ContinualCounter>>, // Locked after the runner
+}
+
+impl Session {
+    fn new(last_access: u64, tokens: TokenHashes, runner: BinomialRunner, counter: Option<ContinualCounter>) -> Session {
+        Self {
+            last_access: AtomicU64::new(last_access),
+            tokens: RwLock::new(tokens),
+            num_inputs: AtomicUsize::new(runner.input_capacity()),
+            num_noise_bits: AtomicUsize::new(runner.num_noise_bits()),
+            counter_noise_bits: AtomicUsize::new(counter.as_ref().map_or(0, ContinualCounter::num_noise_bits)),
+            phase: AtomicU8::new(runner.phase() as u8),
+            runner: Mutex::new(runner),
+            counter: Mutex::new(counter),
+        }
+    }
+
+    fn is_expired(&self, now: u64, ttl: u64) -> bool {
+        now.saturating_sub(self.last_access.load(Ordering::Relaxed)) > ttl
+    }
+}
+
+type Shard = RwLock<HashMap<String, Arc<Session>>>;
+
+// Sessions are spread over NUM_SHARDS maps by the hash of their ID. A shard lock is only held to look up,
+// insert or remove an entry, never while a runner is in use.
+pub struct SessionManager {
+    shards: Vec<Shard>,
+    store: Box<dyn SessionStore>,
+    limits: SessionLimits,
+    admission: Mutex<()>, // Held from checking the global limits until what was checked is taken
+}
+
+impl SessionManager {
+
+    // Resumes every unexpired session found in the store
+    pub fn new(limits: SessionLimits, store: Box<dyn SessionStore>) -> Result<SessionManager, SessionError> {
+        let shards = (0..NUM_SHARDS).map(|_| RwLock::new(HashMap::new())).collect();
+        let manager = Self { shards, store, limits, admission: Mutex::new(()) };
+        for (session_id, stored) in manager.store.load_all()? {
+            let session = Arc::new(Session::new(stored.last_access, stored.tokens, stored.runner, stored.counter));
+            manager.shard(&session_id).write().unwrap().insert(session_id, session);
+        }
+        manager.evict_expired()?;
+        Ok(manager)
+    }
+
+    fn shard(&self, session_id: &str) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        session_id.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % NUM_SHARDS]
+    }
+
+    // Snapshot of the live sessions, taken one shard at a time
+    fn sessions(&self) -> Vec<(String, Arc<Session>)> {
+        self.shards.iter()
+            .flat_map(|shard| shard.read().unwrap().iter().map(|(id, s)| (id.clone(), s.clone())).collect::<Vec<_>>())
+            .collect()
+    }
+
+    pub fn limits(&self) -> &SessionLimits {
+        &self.limits
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of live sessions in each phase, without waiting on busy runners
+    pub fn phase_counts(&self) -> Vec<(Phase, usize)> {
+        let mut counts: Vec<(Phase, usize)> = Phase::ALL.iter().map(|phase| (*phase, 0)).collect();
+        for (_, session) in self.sessions() {
+            counts[session.phase.load(Ordering::Relaxed) as usize].1 += 1;
+        }
+        counts
+    }
+
+    // Checks the input length of a new session against the per-session and global limits. Only insert reserves them,
+    // this lets a request be refused before the work of building the runner.
+    pub fn check_input(&self, len: usize) -> Result<(), SessionError> {
+        if len > self.limits.max_input_len {
+            return Err(SessionError::InputTooLarge { len, max: self.limits.max_input_len });
+        }
+        let total: usize = self.sessions().iter().map(|(_, s)| s.num_inputs.load(Ordering::Relaxed)).sum();
+        if total + len > self.limits.max_total_inputs {
+            return Err(SessionError::InputTooLarge { len: total + len, max: self.limits.max_total_inputs });
+        }
+        Ok(())
+    }
+
+    // Checks that session_id may hold runner_bits noise bits in its runner and counter_bits in its continual
+    // counter, in place of the ones it has now
+    fn check_noise(&self, session_id: &str, runner_bits: usize, counter_bits: usize) -> Result<(), SessionError> {
+        let n_b = runner_bits.saturating_add(counter_bits);
+        if n_b > self.limits.max_noise_bits {
+            return Err(SessionError::NoiseTooLarge { len: n_b, max: self.limits.max_noise_bits });
+        }
+        let total: usize = self.sessions().iter()
+            .filter(|(id, _)| id.as_str() != session_id)
+            .map(|(_, s)| s.num_noise_bits.load(Ordering::Relaxed) + s.counter_noise_bits.load(Ordering::Relaxed))
+            .sum();
+        if total + n_b > self.limits.max_total_noise_bits {
+            return Err(SessionError::NoiseTooLarge { len: total + n_b, max: self.limits.max_total_noise_bits });
+        }
+        Ok(())
+    }
+
+    // Checks the noise of session_id like check_noise, and reserves n_b bits for it until the update that follows
+    // stores what the runner really holds. A session that does not exist yet is only checked, insert reserves its bits.
+    pub fn reserve_noise(&self, session_id: &str, n_b: usize) -> Result<(), SessionError> {
+        let _admission = self.admission.lock().unwrap();
+        let session = self.shard(session_id).read().unwrap().get(session_id).cloned();
+        let counter_bits = session.as_ref().map_or(0, |s| s.counter_noise_bits.load(Ordering::Relaxed));
+        self.check_noise(session_id, n_b, counter_bits)?;
+        if let Some(session) = session {
+            session.num_noise_bits.store(n_b, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    // Registers the runner and issues its capability tokens. The tokens are returned only here.
+    // The limits are checked and the session added under one lock, so concurrent inserts cannot overrun them.
+    pub fn insert(&self, runner: BinomialRunner) -> Result<(String, CapabilityTokens), SessionError> {
+        self.evict_expired()?;
+        let _admission = self.admission.lock().unwrap();
+        if self.len() >= self.limits.max_sessions {
+            return Err(SessionError::TooManySessions(self.limits.max_sessions));
+        }
+        let session_id = runner.get_session_id();
+        self.check_input(runner.input_capacity())?;
+        self.check_noise(&session_id, runner.num_noise_bits(), 0)?;
+        let last_access = now_secs();
+        let (hashes, tokens) = TokenHashes::issue(runner.get_noise_servers(), runner.get_aggregators());
+        self.store.save(&session_id, &StoredSessionRef { last_access, tokens: &hashes, runner: &runner, counter: None })?;
+        let session = Arc::new(Session::new(last_access, hashes, runner, None));
+        self.shard(&session_id).write().unwrap().insert(session_id.clone(), session);
+        Ok((session_id, tokens))
+    }
+
+    // Role the token grants on the session, if any. Does not refresh the TTL.
+    pub fn authorize(&self, session_id: &str, token: &str) -> Result<Role, SessionError> {
+        let session = self.shard(session_id).read().unwrap().get(session_id).cloned().ok_or(SessionError::NotFound)?;
+        let role = session.tokens.read().unwrap().role(token);
+        role.ok_or(SessionError::Unauthorized)
+    }
+
+    // Client ID a client token is bound to
+    pub fn client_id(&self, session_id: &str, token: &str) -> Result<String, SessionError> {
+        let session = self.shard(session_id).read().unwrap().get(session_id).cloned().ok_or(SessionError::NotFound)?;
+        let client_id = session.tokens.read().unwrap().client_id(token);
+        client_id.ok_or(SessionError::Unauthorized)
+    }
+
+    // Issues count more client tokens, each bound to a client ID of its own. A client can only submit under its
+    // ID, so one token cannot stand in for many clients. No more tokens are issued than the session takes inputs.
+    pub fn issue_client_tokens(&self, session_id: &str, count: usize) -> Result<Vec<ClientToken>, SessionError> {
+        let session = self.get(session_id)?;
+        let runner = session.runner.lock().unwrap();
+        let counter = session.counter.lock().unwrap();
+        let mut tokens = session.tokens.write().unwrap();
+        let max = runner.get_max_inputs();
+        if tokens.clients.len().saturating_add(count) > max {
+            return Err(SessionError::TooManyClients(max));
+        }
+        let issued: Vec<ClientToken> = (tokens.clients.len()..tokens.clients.len() + count)
+            .map(|n| ClientToken { client_id: format!("client-{}", n + 1), token: random_token() })
+            .collect();
+        tokens.clients.extend(issued.iter().map(|t| (token_hash(&t.token), t.client_id.clone())));
+        let last_access = session.last_access.load(Ordering::Relaxed);
+        self.store.save(session_id, &StoredSessionRef { last_access, tokens: &tokens, runner: &runner, counter: counter.as_ref() })?;
+        Ok(issued)
+    }
+
+    // Looks the session up and refreshes its TTL
+    fn get(&self, session_id: &str) -> Result<Arc<Session>, SessionError> {
+        let session = self.shard(session_id).read().unwrap().get(session_id).cloned().ok_or(SessionError::NotFound)?;
+        let now = now_secs();
+        if session.is_expired(now, self.limits.ttl.as_secs()) {
+            return Err(SessionError::NotFound);
+        }
+        session.last_access.store(now, Ordering::Relaxed);
+        Ok(session)
+    }
+
+    // Runs f on the runner without writing it back. Waits only for other requests on the same session.
+    pub fn read<R>(&self, session_id: &str, f: impl FnOnce(&BinomialRunner) -> R) -> Result<R, SessionError> {
+        let session = self.get(session_id)?;
+        let runner = session.runner.lock().unwrap();
+        Ok(f(&runner))
+    }
+
+    // Runs f on the runner and saves the result to the store
+    pub fn update<R>(&self, session_id: &str, f: impl FnOnce(&mut BinomialRunner) -> R) -> Result<R, SessionError> {
+        self.update_with(session_id, |runner| (f(runner), true))
+    }
+
+    // Same as update, for steps that may leave the stored state as it was: f also returns whether it changed
+    // anything, and the session is only saved if it did. A repeated sum or check returns false, and so does a
+    // batch of a submission stream, which is saved once at the end of the stream.
+    pub fn update_with<R>(&self, session_id: &str, f: impl FnOnce(&mut BinomialRunner) -> (R, bool)) -> Result<R, SessionError> {
+        let session = self.get(session_id)?;
+        let mut runner = session.runner.lock().unwrap();
+        let (result, changed) = f(&mut runner);
+        session.num_inputs.store(runner.input_capacity(), Ordering::Relaxed);
+        session.num_noise_bits.store(runner.num_noise_bits(), Ordering::Relaxed);
+        session.phase.store(runner.phase() as u8, Ordering::Relaxed);
+        if changed {
+            self.persist(session_id, &session, &runner, session.counter.lock().unwrap().as_ref())?;
+        }
+        Ok(result)
+    }
+
+    // Saves the session as it is, after steps that ran through update_with without saving
+    pub fn save(&self, session_id: &str) -> Result<(), SessionError> {
+        let session = self.get(session_id)?;
+        let runner = session.runner.lock().unwrap();
+        let counter = session.counter.lock().unwrap();
+        self.persist(session_id, &session, &runner, counter.as_ref())
+    }
+
+    // Writes the session to the store. The caller holds the runner and the counter.
+    fn persist(&self, session_id: &str, session: &Session, runner: &BinomialRunner, counter: Option<&ContinualCounter>) -> Result<(), SessionError> {
+        let last_access = session.last_access.load(Ordering::Relaxed);
+        self.store.save(session_id, &StoredSessionRef { last_access, tokens: &session.tokens.read().unwrap(), runner, counter })?;
+        Ok(())
+    }
+
+    // Starts the session's continual counter. Its noise bits per node are reserved against the noise limits
+    // alongside the runner's, as reserve_noise does for the runner, and stay reserved as long as the session.
+    pub fn start_counter(&self, session_id: &str, noise: TreeNoise) -> Result<(), SessionError> {
+        let session = self.get(session_id)?;
+        let runner = session.runner.lock().unwrap();
+        let mut counter = session.counter.lock().unwrap();
+        if counter.is_some() {
+            return Err(SessionError::CounterStarted);
+        }
+        {
+            let _admission = self.admission.lock().unwrap();
+            self.check_noise(session_id, session.num_noise_bits.load(Ordering::Relaxed), noise.num_noise_bits())?;
+            session.counter_noise_bits.store(noise.num_noise_bits(), Ordering::Relaxed);
+        }
+        *counter = Some(ContinualCounter::new(noise));
+        self.persist(session_id, &session, &runner, counter.as_ref())
+    }
+
+    // Runs f on the session's continual counter and saves it with the runner if f ingested a batch or finalised a
+    // node. The nodes and their openings are needed for every later release, so they are kept as long as the session.
+    pub fn update_counter<R>(&self, session_id: &str, f: impl FnOnce(&mut ContinualCounter) -> R) -> Result<R, SessionError> {
+        let session = self.get(session_id)?;
+        let runner = session.runner.lock().unwrap();
+        let mut counter = session.counter.lock().unwrap();
+        let counter = counter.as_mut().ok_or(SessionError::NoCounter)?;
+        let before = (counter.time(), counter.awaiting_coins());
+        let result = f(counter);
+        if (counter.time(), counter.awaiting_coins()) != before {
+            self.persist(session_id, &session, &runner, Some(&*counter))?;
+        }
+        Ok(result)
+    }
+
+    pub fn remove(&self, session_id: &str) -> Result<(), SessionError> {
+        self.shard(session_id).write().unwrap().remove(session_id).ok_or(SessionError::NotFound)?;
+        self.store.remove(session_id)?;
+        Ok(())
+    }
+
+    // Drops every session not accessed within the TTL, returns how many were dropped.
+    // A request already holding an expired session finishes, but its runner is gone afterwards.
+    pub fn evict_expired(&self) -> Result<usize, SessionError> {
+        let (now, ttl) = (now_secs(), self.limits.ttl.as_secs());
+        let mut evicted = 0;
+        for shard in self.shards.iter() {
+            let expired: Vec<String> = shard.write().unwrap()
+                .extract_if(|_, s| s.is_expired(now, ttl))
+                .map(|(id, _)| id)
+                .collect();
+            for session_id in expired.iter() {
+                self.store.remove(session_id)?;
+            }
+            evicted += expired.len();
+        }
+        Ok(evicted)
+    }
+}
+
+
+#[test]
+pub fn test_session_limits_and_resume() {
+    let dir = std::env::temp_dir().join(format!("zkdp-sessions-{}", uuid::Uuid::new_v4()));
+    let limits = SessionLimits { max_sessions: 2, max_input_len: 100, max_noise_bits: 50, ..SessionLimits::default() };
+    let manager = SessionManager::new(limits.clone(), Box::new(FileStore::new(&dir).unwrap())).unwrap();
+
+    assert!(matches!(manager.insert(BinomialRunner::new(&[1; 101])), Err(SessionError::InputTooLarge { .. })));
+    let (id, tokens) = manager.insert(BinomialRunner::new(&[1, 0, 1, 1])).unwrap();
+    assert_eq!(manager.authorize(&id, &tokens.prover).unwrap(), Role::Prover);
+    assert!(matches!(manager.authorize(&id, "not-a-token"), Err(SessionError::Unauthorized)));
+    assert!(matches!(manager.issue_client_tokens(&id, 1), Err(SessionError::TooManyClients(0)))); // Its inputs are in
+    manager.insert(BinomialRunner::new(&[0; 10])).unwrap();
+    assert!(matches!(manager.insert(BinomialRunner::new(&[0; 10])), Err(SessionError::TooManySessions(2))));
+
+    assert!(manager.reserve_noise(&id, 51).is_err());
+    manager.reserve_noise(&id, 8).unwrap();
+    manager.update(&id, |runner| runner.input_randomness(&[1, 0, 0, 1, 1, 0, 1, 0])).unwrap();
+    let xor_bits = manager.read(&id, |runner| runner.get_xor_bits()).unwrap();
+    // The counter's noise bits per node are reserved with the runner's 8, against the per-session limit of 50
+    assert!(matches!(manager.update_counter(&id, |cc| cc.time()), Err(SessionError::NoCounter)));
+    assert!(matches!(manager.start_counter(&id, TreeNoise::Binomial { n_b: 43 }), Err(SessionError::NoiseTooLarge { len: 51, max: 50 })));
+    manager.start_counter(&id, TreeNoise::Binomial { n_b: 8 }).unwrap();
+    assert!(matches!(manager.start_counter(&id, TreeNoise::Binomial { n_b: 8 }), Err(SessionError::CounterStarted)));
+    assert!(manager.reserve_noise(&id, 43).is_err());
+    manager.update_counter(&id, |cc| {
+        for day in 0..3 {
+            assert!(cc.ingest(&[1, 0, 1]));
+            cc.take_coin_seed([day; 32]).unwrap();
+        }
+    }).unwrap();
+
+    // A new manager on the same directory picks up where the last one stopped. A file cut short is moved aside.
+    drop(manager);
+    fs::write(dir.join(format!("{}.json", uuid::Uuid::new_v4())), b"{\"last_access\": 1, \"run").unwrap();
+    let manager = SessionManager::new(limits.clone(), Box::new(FileStore::new(&dir).unwrap())).unwrap();
+    assert_eq!(manager.len(), 2);
+    assert_eq!(fs::read_dir(&dir).unwrap().filter(|e| e.as_ref().unwrap().path().to_string_lossy().ends_with(".json.corrupt")).count(), 1);
+    assert_eq!(manager.authorize(&id, &tokens.auditor).unwrap(), Role::Auditor); // Tokens survive the restart
+    assert_eq!(manager.read(&id, |runner| runner.get_xor_bits()).unwrap(), xor_bits);
+    let release = manager.update_counter(&id, |counter| {
+        assert_eq!(counter.time(), 3);
+        assert!(counter.ingest(&[1, 1, 0]));
+        counter.take_coin_seed([3; 32]).unwrap();
+        counter.release_current().unwrap()
+    }).unwrap();
+    assert!(manager.update_counter(&id, |counter| counter.verify_release(&release)).unwrap());
+    manager.update(&id, |runner| { runner.compute_sum().unwrap(); runner.commit_pedersons(); }).unwrap();
+    assert!(manager.read(&id, |runner| runner.get_lhs() == runner.get_rhs()).unwrap());
+
+    // Sessions older than the TTL are dropped from memory and from disk
+    let manager = SessionManager::new(limits, Box::new(FileStore::new(&dir).unwrap())).unwrap();
+    manager.shard(&id).read().unwrap()[&id].last_access.store(0, Ordering::Relaxed);
+    assert!(matches!(manager.read(&id, |_| ()), Err(SessionError::NotFound)));
+    assert_eq!(manager.evict_expired().unwrap(), 1);
+    assert!(!dir.join(format!("{}.json", id)).exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// Load test: while one session is stuck inside a long update, other sessions are created, updated and read
+#[test]
+pub fn test_concurrent_sessions_progress() {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let manager = Arc::new(SessionManager::new(SessionLimits::default(), Box::new(MemoryStore)).unwrap());
+    let (busy, _) = manager.insert(BinomialRunner::new(&[1; 16])).unwrap();
+    let (started_tx, started_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+
+    let holder = {
+        let manager = manager.clone();
+        std::thread::spawn(move || {
+            manager.update(&busy, |_| {
+                started_tx.send(()).unwrap();
+                release_rx.recv_timeout(Duration::from_secs(30)).unwrap();
+            }).unwrap();
+        })
+    };
+    started_rx.recv().unwrap();
+
+    // With a global lock these would wait for the busy session, and the release below would never be sent
+    let (done_tx, done_rx) = mpsc::channel();
+    let workers: Vec<_> = (0..4).map(|_| {
+        let (manager, done_tx) = (manager.clone(), done_tx.clone());
+        std::thread::spawn(move || {
+            let (id, _) = manager.insert(BinomialRunner::new(&[1, 0, 1, 0])).unwrap();
+            manager.update(&id, |runner| runner.input_randomness(&[1; 32])).unwrap();
+            manager.update(&id, |runner| { runner.compute_sum().unwrap(); runner.commit_pedersons(); }).unwrap();
+            done_tx.send(manager.read(&id, |runner| runner.get_lhs() == runner.get_rhs()).unwrap()).unwrap();
+        })
+    }).collect();
+    for _ in 0..4 {
+        assert!(done_rx.recv_timeout(Duration::from_secs(30)).unwrap());
+    }
+
+    release_tx.send(()).unwrap();
+    holder.join().unwrap();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+// Sessions created at the same time are admitted one after the other, so together they stay within the global limits
+#[test]
+pub fn test_concurrent_inserts_respect_limits() {
+    let limits = SessionLimits { max_total_inputs: 40, max_total_noise_bits: 20, ..SessionLimits::default() };
+    let manager = SessionManager::new(limits, Box::new(MemoryStore)).unwrap();
+    let runners: Vec<BinomialRunner> = (0..8).map(|_| BinomialRunner::collecting(10, None, 2)).collect();
+    let created: Vec<String> = std::thread::scope(|scope| {
+        let manager = &manager;
+        let inserts: Vec<_> = runners.into_iter().map(|runner| scope.spawn(move || manager.insert(runner))).collect();
+        inserts.into_iter().filter_map(|insert| insert.join().unwrap().ok().map(|(id, _)| id)).collect()
+    });
+    assert_eq!(created.len(), 4);
+
+    // Reserved noise counts against the limit before the step that takes it has run
+    manager.reserve_noise(&created[0], 15).unwrap();
+    assert!(matches!(manager.reserve_noise(&created[1], 10), Err(SessionError::NoiseTooLarge { .. })));
+    manager.reserve_noise(&created[1], 5).unwrap();
+}
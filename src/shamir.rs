@@ -0,0 +1,214 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use crate::algebra::{Field, ScalarField};
+use crate::generic_commitments::{Commitment, CurveCommitment, SecretSharing};
+
+// t-of-n Shamir sharing: the dealer hides the secret as f(0) of a random polynomial f of degree t - 1 and hands
+// share i the point (i, f(i)). Any t shares give f(0) by Lagrange interpolation, t - 1 shares say nothing about it.
+// Over the scalar field of curve25519, the dealer can also publish commitments to the coefficients so every server
+// checks its share against them: Feldman's g^a_j, or Pedersen's g^a_j h^b_j with a second blinding polynomial,
+// which also hides the secret.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShamirShare<E> {
+    pub index: u64, // x-coordinate, 1 to n
+    pub value: E,
+}
+
+// Share of Pedersen's verifiable sharing: f(i) and the blinding b(i)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PedersenShare {
+    pub index: u64,
+    pub value: Scalar,
+    pub blinding: Scalar,
+}
+
+impl PedersenShare {
+
+    pub fn share(&self) -> ShamirShare<Scalar> {
+        ShamirShare { index: self.index, value: self.value }
+    }
+}
+
+pub struct Shamir<F: Field = ScalarField> {
+    pub field: F,
+    pub threshold: usize,
+}
+
+impl Shamir<ScalarField> {
+
+    pub fn new(threshold: usize) -> Shamir<ScalarField> {
+        Self::with_field(ScalarField, threshold)
+    }
+}
+
+impl<F: Field> Shamir<F> {
+
+    pub fn with_field(field: F, threshold: usize) -> Shamir<F> {
+        assert!(threshold >= 1);
+        Self { field, threshold }
+    }
+
+    // The t - 1 random coefficients of g, for f(x) = secret + x g(x)
+    fn random_tail(&self) -> Vec<F::Elem> {
+        (1..self.threshold).map(|_| self.field.random()).collect()
+    }
+
+    // secret + x g(x) for the coefficients of g, lowest first
+    fn evaluate(&self, secret: &F::Elem, tail: &[F::Elem], x: u64) -> F::Elem {
+        let x = self.field.element(x);
+        let g = tail.iter().rev().fold(self.field.zero(), |acc, c| self.field.add(&self.field.mul(&acc, &x), c));
+        self.field.add(secret, &self.field.mul(&x, &g))
+    }
+
+    // f(0) from at least threshold shares with distinct nonzero indices, None otherwise. Extra shares are ignored.
+    pub fn try_reconstruct(&self, shares: &[ShamirShare<F::Elem>]) -> Option<F::Elem> {
+        let shares = shares.get(..self.threshold)?;
+        let indices: Vec<u64> = shares.iter().map(|s| s.index).collect();
+        let coefficients = lagrange_at_zero(&self.field, &indices)?;
+        Some(shares.iter().zip(coefficients.iter()).fold(self.field.zero(), |acc, (s, l)| self.field.add(&acc, &self.field.mul(&s.value, l))))
+    }
+}
+
+// Lagrange coefficients l_i at 0 for the points x_i, so f(0) = sum_i l_i f(x_i) for f of degree below their number.
+// None if an index is 0 or repeated.
+pub fn lagrange_at_zero<F: Field>(field: &F, indices: &[u64]) -> Option<Vec<F::Elem>> {
+    if indices.iter().enumerate().any(|(i, x)| *x == 0 || indices[..i].contains(x)) {
+        return None;
+    }
+    indices.iter().map(|x_i| {
+        let (num, den) = indices.iter().filter(|x_j| *x_j != x_i).fold((field.one(), field.one()), |(num, den), x_j| {
+            let x_j_elem = field.element(*x_j);
+            let diff = field.sub(&x_j_elem, &field.element(*x_i));
+            (field.mul(&num, &x_j_elem), field.mul(&den, &diff))
+        });
+        Some(field.mul(&num, &field.inv(&den)?))
+    }).collect()
+}
+
+impl<F: Field> SecretSharing for Shamir<F> {
+    type Secret = F::Elem;
+    type Share = ShamirShare<F::Elem>;
+
+    fn share(&self, secret: &F::Elem, num_shares: usize) -> Vec<ShamirShare<F::Elem>> {
+        assert!(self.threshold <= num_shares);
+        let tail = self.random_tail();
+        (1..=num_shares as u64).map(|index| ShamirShare { index, value: self.evaluate(secret, &tail, index) }).collect()
+    }
+
+    // Panics on fewer than threshold shares or repeated indices, see try_reconstruct
+    fn reconstruct(&self, shares: &[ShamirShare<F::Elem>]) -> F::Elem {
+        self.try_reconstruct(shares).expect("Need threshold shares with distinct indices")
+    }
+}
+
+// Sum of commitments[j] * x^j, the commitment to f(x) when commitments[j] commits to coefficient j
+fn evaluate_commitments(commitments: &[RistrettoPoint], x: u64) -> RistrettoPoint {
+    let x = Scalar::from(x);
+    let mut points = commitments.iter().rev();
+    let top = *points.next().expect("At least the commitment to the secret");
+    points.fold(top, |acc, com| acc * x + com)
+}
+
+impl Shamir<ScalarField> {
+
+    // Feldman's verifiable sharing: the shares and the commitments g * a_j to the coefficients of f, a_0 being the
+    // secret. g * secret is public, so the secret must have enough entropy on its own.
+    pub fn feldman_share(&self, com: &CurveCommitment, secret: &Scalar, num_shares: usize) -> (Vec<ShamirShare<Scalar>>, Vec<RistrettoPoint>) {
+        assert!(self.threshold <= num_shares);
+        let tail = self.random_tail();
+        let shares = (1..=num_shares as u64).map(|index| ShamirShare { index, value: self.evaluate(secret, &tail, index) }).collect();
+        let commitments = std::iter::once(secret).chain(tail.iter()).map(|a| com.commit(*a, Scalar::zero())).collect();
+        (shares, commitments)
+    }
+
+    // Pedersen's verifiable sharing: the shares of f and of a blinding polynomial b, and the commitments
+    // g * a_j + h * b_j to their coefficients
+    pub fn pedersen_share(&self, com: &CurveCommitment, secret: &Scalar, num_shares: usize) -> (Vec<PedersenShare>, Vec<RistrettoPoint>) {
+        assert!(self.threshold <= num_shares);
+        let blinding = com.sample_randomness();
+        let (tail, blinding_tail) = (self.random_tail(), self.random_tail());
+        let shares = (1..=num_shares as u64).map(|index| PedersenShare {
+            index,
+            value: self.evaluate(secret, &tail, index),
+            blinding: self.evaluate(&blinding, &blinding_tail, index),
+        }).collect();
+        let coefficients = std::iter::once((secret, &blinding)).chain(tail.iter().zip(blinding_tail.iter()));
+        let commitments = coefficients.map(|(a, b)| com.commit(*a, *b)).collect();
+        (shares, commitments)
+    }
+}
+
+// Server side check of a share of feldman_share. The servers agree on the threshold beforehand: a dealer who
+// publishes more commitments shares a polynomial of higher degree, whose shares all verify while different sets of
+// threshold shares reconstruct different secrets.
+pub fn feldman_verify(com: &CurveCommitment, threshold: usize, share: &ShamirShare<Scalar>, commitments: &[RistrettoPoint]) -> bool {
+    threshold >= 1 && commitments.len() == threshold && com.commit(share.value, Scalar::zero()) == evaluate_commitments(commitments, share.index)
+}
+
+// Server side check of a share of pedersen_share, with the same check of the degree
+pub fn pedersen_verify(com: &CurveCommitment, threshold: usize, share: &PedersenShare, commitments: &[RistrettoPoint]) -> bool {
+    threshold >= 1 && commitments.len() == threshold && com.commit(share.value, share.blinding) == evaluate_commitments(commitments, share.index)
+}
+
+
+#[test]
+pub fn test_shamir_reconstruct() {
+    use crate::algebra::BigNumField;
+    use crate::public_parameters::PublicParams;
+
+    let shamir = Shamir::new(3);
+    let secret = ScalarField.random();
+    let shares = shamir.share(&secret, 5);
+
+    // Any 3 of the 5 shares, in any order
+    for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let chosen: Vec<_> = subset.iter().map(|i| shares[*i]).collect();
+        assert_eq!(shamir.reconstruct(&chosen), secret);
+    }
+    let two = Shamir::new(2);
+    assert_ne!(two.reconstruct(&shares[..2]), secret);
+    assert_eq!(shamir.try_reconstruct(&shares[..2]), None);
+    assert_eq!(shamir.try_reconstruct(&[shares[0], shares[1], shares[1]]), None);
+
+    // Same over the OpenSSL backend
+    let public_param = PublicParams::new(128, 5).unwrap();
+    let shamir = Shamir::with_field(BigNumField::new(&public_param.q), 2);
+    let secret = shamir.field.element(42);
+    let shares = shamir.share(&secret, 4);
+    assert_eq!(shamir.reconstruct(&shares[2..]), secret);
+}
+
+#[test]
+pub fn test_verifiable_sharing() {
+    use crate::curve_parameters::CurveParams;
+
+    let params = CurveParams::new();
+    let com = CurveCommitment::new(params.g, params.h);
+    let shamir = Shamir::new(3);
+    let secret = ScalarField.random();
+
+    let (shares, commitments) = shamir.feldman_share(&com, &secret, 5);
+    assert!(shares.iter().all(|share| feldman_verify(&com, 3, share, &commitments)));
+    let mut bad = shares[1];
+    bad.value += Scalar::one();
+    assert!(!feldman_verify(&com, 3, &bad, &commitments));
+    assert_eq!(shamir.reconstruct(&shares[2..]), secret);
+
+    let (shares, commitments) = shamir.pedersen_share(&com, &secret, 5);
+    assert!(shares.iter().all(|share| pedersen_verify(&com, 3, share, &commitments)));
+    let mut bad = shares[3];
+    bad.blinding += Scalar::one();
+    assert!(!pedersen_verify(&com, 3, &bad, &commitments));
+    let chosen: Vec<_> = shares[..3].iter().map(PedersenShare::share).collect();
+    assert_eq!(shamir.reconstruct(&chosen), secret);
+
+    // A dealer sharing a polynomial of degree 3 instead of 2: its shares are consistent with its commitments, but
+    // two sets of 3 shares disagree on the secret, so the servers must refuse it
+    let (shares, commitments) = Shamir::new(4).feldman_share(&com, &secret, 5);
+    assert!(shares.iter().all(|share| feldman_verify(&com, 4, share, &commitments)));
+    assert!(!shares.iter().any(|share| feldman_verify(&com, 3, share, &commitments)));
+    assert_ne!(shamir.reconstruct(&shares[..3]), shamir.reconstruct(&shares[2..]));
+    let (shares, commitments) = Shamir::new(4).pedersen_share(&com, &secret, 5);
+    assert!(!shares.iter().any(|share| pedersen_verify(&com, 3, share, &commitments)));
+}
@@ -1,5 +1,11 @@
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
+use crate::sigma_protocols::FiatShamirProof;
+use serde::{Deserialize, Serialize};
+
+// OR-proof that com opens to 0 or 1. Branch 0 shows d0 c^{e0} = h^{v0}, branch 1 shows d1 c^{e1} = g^{e1} h^{v1},
+// and e = e0 + e1 is the Fiat-Shamir challenge.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct  ProofScalar{
     pub com: RistrettoPoint,
     pub e0 : Scalar, 
@@ -11,6 +17,23 @@ pub struct  ProofScalar{
     pub d1: RistrettoPoint,
 }
 
+impl ProofScalar{
+
+    // Layout of the generic 1-out-of-2 proof: commitment [d0, d1], response [e0, e1, v0, v1]
+    pub fn from_fiat_shamir(com: RistrettoPoint, proof: &FiatShamirProof)->ProofScalar{
+        let (e0, e1, v0, v1) = (proof.response[0], proof.response[1], proof.response[2], proof.response[3]);
+        ProofScalar{com, e0, e1, e: proof.challenge, v0, v1, d0: proof.commitment[0], d1: proof.commitment[1]}
+    }
+
+    pub fn to_fiat_shamir(&self)->FiatShamirProof{
+        FiatShamirProof{
+            commitment: vec![self.d0, self.d1],
+            challenge: self.e,
+            response: vec![self.e0, self.e1, self.v0, self.v1],
+        }
+    }
+}
+
 // // Proof system for a vector of OR's and AND's
 // pub struct  Proof{
 //     pub coms: Vec<BigNum>,
@@ -0,0 +1,635 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use subtle::{Choice, ConditionallySelectable};
+use crate::generic_commitments::{Commitment, CurveCommitment};
+use crate::rng::{random_scalar, CryptoRngCore};
+use crate::transcript::Transcript;
+
+// Composable sigma protocols for Pedersen relations on Ristretto.
+// The base case is a linear relation (Maurer): prove knowledge of w_1..w_m with P_j = sum_i w_i G_{j,i} for every row j.
+// Openings, equality of committed values, linear relations between commitments and discrete logs are all instances.
+// Relations compose with AND (shared challenge) and k-out-of-n OR (Cramer-Damgard-Schoenmakers),
+// and `prove` / `verify` compile any of them to a non-interactive proof with a Fiat-Shamir transcript.
+
+// Witness tree mirroring the shape of the relation
+#[derive(Clone)]
+pub enum Witness {
+    Scalars(Vec<Scalar>),
+    And(Vec<Witness>),
+    Or(Vec<Option<Witness>>), // None for the branches the prover cannot open
+}
+
+// What the prover keeps between its first message and its response
+pub enum ProverState {
+    Scalars(Vec<Scalar>),
+    And(Vec<ProverState>),
+    Or(Vec<BranchState>),
+}
+
+pub enum BranchState {
+    Real(ProverState),
+    Simulated(Scalar, Vec<Scalar>), // challenge and response picked by the simulator
+}
+
+pub trait SigmaProtocol: Send + Sync {
+
+    // Number of group elements in the first message
+    fn commitment_len(&self) -> usize;
+
+    // Number of scalars in the response
+    fn response_len(&self) -> usize;
+
+    // Binds the statement (generators and public points) to the Fiat-Shamir transcript
+    fn append_statement(&self, transcript: &mut Transcript);
+
+    // First message. Returns None if the witness does not have the shape of the relation.
+    fn prover_commit(&self, witness: &Witness, rng: &mut dyn CryptoRngCore) -> Option<(ProverState, Vec<RistrettoPoint>)>;
+
+    fn prover_respond(&self, witness: &Witness, state: ProverState, challenge: &Scalar) -> Vec<Scalar>;
+
+    // Special honest-verifier zero-knowledge simulator: an accepting (commitment, response) for a given challenge
+    fn simulate(&self, challenge: &Scalar, rng: &mut dyn CryptoRngCore) -> (Vec<RistrettoPoint>, Vec<Scalar>);
+
+    fn verify(&self, commitment: &[RistrettoPoint], challenge: &Scalar, response: &[Scalar]) -> bool;
+}
+
+fn multiscalar(scalars: &[Scalar], points: &[RistrettoPoint]) -> RistrettoPoint {
+    scalars.iter().zip(points.iter()).map(|(s, p)| s * p).sum()
+}
+
+// P_j = sum_i w_i G_{j,i}
+pub struct LinearRelation {
+    pub generators: Vec<Vec<RistrettoPoint>>, // one row of generators per equation, one column per witness
+    pub targets: Vec<RistrettoPoint>,
+}
+
+impl LinearRelation {
+
+    pub fn new(generators: Vec<Vec<RistrettoPoint>>, targets: Vec<RistrettoPoint>) -> LinearRelation {
+        assert_eq!(generators.len(), targets.len());
+        Self { generators, targets }
+    }
+
+    fn num_witnesses(&self) -> usize {
+        self.generators.first().map(|row| row.len()).unwrap_or(0)
+    }
+
+    // P = w base
+    pub fn dlog(base: RistrettoPoint, target: RistrettoPoint) -> LinearRelation {
+        Self::new(vec![vec![base]], vec![target])
+    }
+
+    // c = Com(x, r); witness [x, r]
+    pub fn opening(com: &CurveCommitment, c: RistrettoPoint) -> LinearRelation {
+        Self::new(vec![vec![com.g, com.h]], vec![c])
+    }
+
+    // c1 = Com(x, r1) and c2 = Com(x, r2); witness [x, r1, r2]
+    pub fn equality(com: &CurveCommitment, c1: RistrettoPoint, c2: RistrettoPoint) -> LinearRelation {
+        let zero = RistrettoPoint::identity();
+        Self::new(vec![vec![com.g, com.h, zero], vec![com.g, zero, com.h]], vec![c1, c2])
+    }
+
+    // The value committed in `target` is sum_i a_i x_i + b, where x_i is committed in coms[i].
+    // Holds iff target - sum_i a_i coms[i] - b g is a multiple of h; witness [r_target - sum_i a_i r_i]
+    pub fn linear_combination(com: &CurveCommitment, coms: &[RistrettoPoint], coeffs: &[Scalar], constant: Scalar, target: RistrettoPoint) -> LinearRelation {
+        assert_eq!(coms.len(), coeffs.len());
+        let p = target - multiscalar(coeffs, coms) - constant * com.g;
+        Self::dlog(com.h, p)
+    }
+}
+
+impl SigmaProtocol for LinearRelation {
+
+    fn commitment_len(&self) -> usize {
+        self.targets.len()
+    }
+
+    fn response_len(&self) -> usize {
+        self.num_witnesses()
+    }
+
+    fn append_statement(&self, transcript: &mut Transcript) {
+        transcript.append_u64(b"linear-rows", self.targets.len() as u64);
+        transcript.append_u64(b"linear-cols", self.num_witnesses() as u64);
+        for (row, target) in self.generators.iter().zip(self.targets.iter()) {
+            for g in row.iter() {
+                transcript.append_point(b"G", g);
+            }
+            transcript.append_point(b"P", target);
+        }
+    }
+
+    fn prover_commit(&self, witness: &Witness, rng: &mut dyn CryptoRngCore) -> Option<(ProverState, Vec<RistrettoPoint>)> {
+        match witness {
+            Witness::Scalars(w) if w.len() == self.num_witnesses() => {
+                let k: Vec<Scalar> = (0..w.len()).map(|_| random_scalar(rng)).collect();
+                let a = self.generators.iter().map(|row| multiscalar(&k, row)).collect();
+                Some((ProverState::Scalars(k), a))
+            },
+            _ => None,
+        }
+    }
+
+    fn prover_respond(&self, witness: &Witness, state: ProverState, challenge: &Scalar) -> Vec<Scalar> {
+        match (witness, state) {
+            (Witness::Scalars(w), ProverState::Scalars(k)) => {
+                k.iter().zip(w.iter()).map(|(k_i, w_i)| k_i + challenge * w_i).collect()
+            },
+            _ => panic!("witness and prover state do not match the linear relation"),
+        }
+    }
+
+    fn simulate(&self, challenge: &Scalar, rng: &mut dyn CryptoRngCore) -> (Vec<RistrettoPoint>, Vec<Scalar>) {
+        let z: Vec<Scalar> = (0..self.num_witnesses()).map(|_| random_scalar(rng)).collect();
+        let a = self.generators.iter()
+                                .zip(self.targets.iter())
+                                .map(|(row, p)| multiscalar(&z, row) - challenge * p)
+                                .collect();
+        (a, z)
+    }
+
+    fn verify(&self, commitment: &[RistrettoPoint], challenge: &Scalar, response: &[Scalar]) -> bool {
+        if commitment.len() != self.commitment_len() || response.len() != self.response_len() {
+            return false;
+        }
+        self.generators.iter()
+                        .zip(self.targets.iter())
+                        .zip(commitment.iter())
+                        .all(|((row, p), a)| multiscalar(response, row) == a + challenge * p)
+    }
+}
+
+// All sub-relations hold, proven with one shared challenge
+pub struct And {
+    pub parts: Vec<Box<dyn SigmaProtocol>>,
+}
+
+impl And {
+    pub fn new(parts: Vec<Box<dyn SigmaProtocol>>) -> And {
+        Self { parts }
+    }
+}
+
+impl SigmaProtocol for And {
+
+    fn commitment_len(&self) -> usize {
+        self.parts.iter().map(|p| p.commitment_len()).sum()
+    }
+
+    fn response_len(&self) -> usize {
+        self.parts.iter().map(|p| p.response_len()).sum()
+    }
+
+    fn append_statement(&self, transcript: &mut Transcript) {
+        transcript.append_u64(b"and", self.parts.len() as u64);
+        for part in self.parts.iter() {
+            part.append_statement(transcript);
+        }
+    }
+
+    fn prover_commit(&self, witness: &Witness, rng: &mut dyn CryptoRngCore) -> Option<(ProverState, Vec<RistrettoPoint>)> {
+        let ws = match witness {
+            Witness::And(ws) if ws.len() == self.parts.len() => ws,
+            _ => return None,
+        };
+        let mut states = Vec::new();
+        let mut commitment = Vec::new();
+        for (part, w) in self.parts.iter().zip(ws.iter()) {
+            let (state, a) = part.prover_commit(w, rng)?;
+            states.push(state);
+            commitment.extend(a);
+        }
+        Some((ProverState::And(states), commitment))
+    }
+
+    fn prover_respond(&self, witness: &Witness, state: ProverState, challenge: &Scalar) -> Vec<Scalar> {
+        match (witness, state) {
+            (Witness::And(ws), ProverState::And(states)) => {
+                self.parts.iter()
+                            .zip(ws.iter())
+                            .zip(states)
+                            .flat_map(|((part, w), state)| part.prover_respond(w, state, challenge))
+                            .collect()
+            },
+            _ => panic!("witness and prover state do not match the AND composition"),
+        }
+    }
+
+    fn simulate(&self, challenge: &Scalar, rng: &mut dyn CryptoRngCore) -> (Vec<RistrettoPoint>, Vec<Scalar>) {
+        let mut commitment = Vec::new();
+        let mut response = Vec::new();
+        for part in self.parts.iter() {
+            let (a, z) = part.simulate(challenge, rng);
+            commitment.extend(a);
+            response.extend(z);
+        }
+        (commitment, response)
+    }
+
+    fn verify(&self, commitment: &[RistrettoPoint], challenge: &Scalar, response: &[Scalar]) -> bool {
+        if commitment.len() != self.commitment_len() || response.len() != self.response_len() {
+            return false;
+        }
+        let mut a_off = 0;
+        let mut z_off = 0;
+        for part in self.parts.iter() {
+            let (a_len, z_len) = (part.commitment_len(), part.response_len());
+            if !part.verify(&commitment[a_off..a_off + a_len], challenge, &response[z_off..z_off + z_len]) {
+                return false;
+            }
+            a_off += a_len;
+            z_off += z_len;
+        }
+        true
+    }
+}
+
+// At least k of the n branches hold (CDS). The response starts with the n branch challenges.
+// For k = 1 the branch challenges add up to the main challenge, which is the classic OR-proof.
+// For k > 1 they are shares of the main challenge: (0, e), (1, e_1), ..., (n, e_n) lie on a polynomial
+// of degree n - k, so the prover is free to pick exactly the n - k challenges of the branches it simulates.
+pub struct Or {
+    pub k: usize,
+    pub branches: Vec<Box<dyn SigmaProtocol>>,
+}
+
+// Evaluates at x the polynomial through the points (xs[i], ys[i]) by Lagrange interpolation
+fn interpolate(xs: &[Scalar], ys: &[Scalar], x: Scalar) -> Scalar {
+    let mut acc = Scalar::zero();
+    for (i, (x_i, y_i)) in xs.iter().zip(ys.iter()).enumerate() {
+        let mut num = Scalar::one();
+        let mut den = Scalar::one();
+        for (j, x_j) in xs.iter().enumerate() {
+            if i != j {
+                num *= x - x_j;
+                den *= x_i - x_j;
+            }
+        }
+        acc += y_i * num * den.invert();
+    }
+    acc
+}
+
+impl Or {
+
+    pub fn new(k: usize, branches: Vec<Box<dyn SigmaProtocol>>) -> Or {
+        assert!(k >= 1 && k <= branches.len());
+        Self { k, branches }
+    }
+
+    // Given the main challenge and the challenges fixed for the simulated branches, fills in the rest
+    fn complete_challenges(&self, challenge: &Scalar, fixed: &[Option<Scalar>]) -> Vec<Scalar> {
+        if self.k == 1 {
+            let free: Scalar = fixed.iter().flatten().sum();
+            return fixed.iter().map(|e| e.unwrap_or(challenge - free)).collect();
+        }
+        let mut xs = vec![Scalar::zero()];
+        let mut ys = vec![*challenge];
+        for (i, e) in fixed.iter().enumerate() {
+            if let Some(e) = e {
+                xs.push(Scalar::from((i + 1) as u64));
+                ys.push(*e);
+            }
+        }
+        fixed.iter()
+                .enumerate()
+                .map(|(i, e)| e.unwrap_or_else(|| interpolate(&xs, &ys, Scalar::from((i + 1) as u64))))
+                .collect()
+    }
+
+    fn check_challenges(&self, challenge: &Scalar, challenges: &[Scalar]) -> bool {
+        let n = self.branches.len();
+        if self.k == 1 {
+            return challenges.iter().sum::<Scalar>() == *challenge;
+        }
+        // The first n - k + 1 points fix the polynomial, every other branch challenge must lie on it
+        let free = n - self.k;
+        let mut xs = vec![Scalar::zero()];
+        let mut ys = vec![*challenge];
+        for (i, e) in challenges.iter().enumerate().take(free) {
+            xs.push(Scalar::from((i + 1) as u64));
+            ys.push(*e);
+        }
+        challenges.iter()
+                    .enumerate()
+                    .skip(free)
+                    .all(|(i, e)| interpolate(&xs, &ys, Scalar::from((i + 1) as u64)) == *e)
+    }
+}
+
+impl SigmaProtocol for Or {
+
+    fn commitment_len(&self) -> usize {
+        self.branches.iter().map(|b| b.commitment_len()).sum()
+    }
+
+    fn response_len(&self) -> usize {
+        self.branches.len() + self.branches.iter().map(|b| b.response_len()).sum::<usize>()
+    }
+
+    fn append_statement(&self, transcript: &mut Transcript) {
+        transcript.append_u64(b"or-k", self.k as u64);
+        transcript.append_u64(b"or-n", self.branches.len() as u64);
+        for branch in self.branches.iter() {
+            branch.append_statement(transcript);
+        }
+    }
+
+    fn prover_commit(&self, witness: &Witness, rng: &mut dyn CryptoRngCore) -> Option<(ProverState, Vec<RistrettoPoint>)> {
+        let ws = match witness {
+            Witness::Or(ws) if ws.len() == self.branches.len() => ws,
+            _ => return None,
+        };
+        // Open exactly k branches for real, simulate the others
+        let mut real = 0;
+        let mut states = Vec::new();
+        let mut commitment = Vec::new();
+        for (branch, w) in self.branches.iter().zip(ws.iter()) {
+            match w {
+                Some(w) if real < self.k => {
+                    let (state, a) = branch.prover_commit(w, rng)?;
+                    states.push(BranchState::Real(state));
+                    commitment.extend(a);
+                    real += 1;
+                },
+                _ => {
+                    let e = random_scalar(rng);
+                    let (a, z) = branch.simulate(&e, rng);
+                    states.push(BranchState::Simulated(e, z));
+                    commitment.extend(a);
+                },
+            }
+        }
+        if real < self.k {
+            return None;
+        }
+        Some((ProverState::Or(states), commitment))
+    }
+
+    fn prover_respond(&self, witness: &Witness, state: ProverState, challenge: &Scalar) -> Vec<Scalar> {
+        let (ws, states) = match (witness, state) {
+            (Witness::Or(ws), ProverState::Or(states)) => (ws, states),
+            _ => panic!("witness and prover state do not match the OR composition"),
+        };
+        let fixed: Vec<Option<Scalar>> = states.iter().map(|s| match s {
+            BranchState::Simulated(e, _) => Some(*e),
+            BranchState::Real(_) => None,
+        }).collect();
+        let challenges = self.complete_challenges(challenge, &fixed);
+
+        let mut response = challenges.clone();
+        for (((branch, w), state), e) in self.branches.iter().zip(ws.iter()).zip(states).zip(challenges.iter()) {
+            match (state, w) {
+                (BranchState::Real(state), Some(w)) => response.extend(branch.prover_respond(w, state, e)),
+                (BranchState::Simulated(_, z), _) => response.extend(z),
+                (BranchState::Real(_), None) => unreachable!(),
+            }
+        }
+        response
+    }
+
+    fn simulate(&self, challenge: &Scalar, rng: &mut dyn CryptoRngCore) -> (Vec<RistrettoPoint>, Vec<Scalar>) {
+        let n = self.branches.len();
+        let fixed: Vec<Option<Scalar>> = (0..n).map(|i| if i < n - self.k { Some(random_scalar(rng)) } else { None }).collect();
+        let challenges = self.complete_challenges(challenge, &fixed);
+
+        let mut commitment = Vec::new();
+        let mut response = challenges.clone();
+        for (branch, e) in self.branches.iter().zip(challenges.iter()) {
+            let (a, z) = branch.simulate(e, rng);
+            commitment.extend(a);
+            response.extend(z);
+        }
+        (commitment, response)
+    }
+
+    fn verify(&self, commitment: &[RistrettoPoint], challenge: &Scalar, response: &[Scalar]) -> bool {
+        if commitment.len() != self.commitment_len() || response.len() != self.response_len() {
+            return false;
+        }
+        let n = self.branches.len();
+        let (challenges, response) = response.split_at(n);
+        if !self.check_challenges(challenge, challenges) {
+            return false;
+        }
+        let mut a_off = 0;
+        let mut z_off = 0;
+        for (branch, e) in self.branches.iter().zip(challenges.iter()) {
+            let (a_len, z_len) = (branch.commitment_len(), branch.response_len());
+            if !branch.verify(&commitment[a_off..a_off + a_len], e, &response[z_off..z_off + z_len]) {
+                return false;
+            }
+            a_off += a_len;
+            z_off += z_len;
+        }
+        true
+    }
+}
+
+// The committed value is 0 or 1: c = r h, or c - g = r h
+pub fn bit_relation(com: &CurveCommitment, c: RistrettoPoint) -> Or {
+    Or::new(1, vec![
+        Box::new(LinearRelation::dlog(com.h, c)),
+        Box::new(LinearRelation::dlog(com.h, c - com.g)),
+    ])
+}
+
+// prove_with_rng on bit_relation for a commitment to `bit` under r, without branching on the bit or indexing by it.
+// The honest first message k h and the simulated ones of both branches are all computed, and the bit only picks
+// among them with conditional selects. The three scalars are drawn in the order Or::prover_commit draws them for
+// that bit (k, e, z for a 0 and e, z, k for a 1), so the proofs are the same as those of prove_with_rng.
+pub fn prove_bit_with_rng<R: RngCore + CryptoRng>(com: &CurveCommitment, bit: Choice, r: Scalar, transcript: &mut Transcript, rng: &mut R) -> (RistrettoPoint, FiatShamirProof) {
+    let c = com.commit(Scalar::conditional_select(&Scalar::zero(), &Scalar::one(), bit), r);
+    let relation = bit_relation(com, c);
+    let draws = [random_scalar(rng), random_scalar(rng), random_scalar(rng)];
+    let k = Scalar::conditional_select(&draws[0], &draws[2], bit);
+    let e_sim = Scalar::conditional_select(&draws[1], &draws[0], bit);
+    let z_sim = Scalar::conditional_select(&draws[2], &draws[1], bit);
+
+    // Branch 0 is honest for a 0 and simulated for a 1, branch 1 the other way round
+    let honest = k * com.h;
+    let simulated = [z_sim * com.h - e_sim * c, z_sim * com.h - e_sim * (c - com.g)];
+    let commitment = vec![
+        RistrettoPoint::conditional_select(&honest, &simulated[0], bit),
+        RistrettoPoint::conditional_select(&simulated[1], &honest, bit),
+    ];
+    let challenge = fiat_shamir_challenge(&relation, &commitment, transcript);
+    let e_honest = challenge - e_sim;
+    let z_honest = k + e_honest * r;
+    let response = vec![
+        Scalar::conditional_select(&e_honest, &e_sim, bit),
+        Scalar::conditional_select(&e_sim, &e_honest, bit),
+        Scalar::conditional_select(&z_honest, &z_sim, bit),
+        Scalar::conditional_select(&z_sim, &z_honest, bit),
+    ];
+    (c, FiatShamirProof { commitment, challenge, response })
+}
+
+// Non-interactive proof produced by the Fiat-Shamir compiler
+#[derive(Clone, Debug)]
+pub struct FiatShamirProof {
+    pub commitment: Vec<RistrettoPoint>,
+    pub challenge: Scalar,
+    pub response: Vec<Scalar>,
+}
+
+fn fiat_shamir_challenge(protocol: &dyn SigmaProtocol, commitment: &[RistrettoPoint], transcript: &mut Transcript) -> Scalar {
+    protocol.append_statement(transcript);
+    for a in commitment.iter() {
+        transcript.append_point(b"A", a);
+    }
+    transcript.challenge_scalar(b"e")
+}
+
+// Returns None if the witness does not satisfy the shape of the relation
+pub fn prove(protocol: &dyn SigmaProtocol, witness: &Witness, transcript: &mut Transcript) -> Option<FiatShamirProof> {
+    prove_with_rng(protocol, witness, transcript, &mut OsRng)
+}
+
+// Same as prove, with the prover's nonces and simulated branches drawn from rng
+pub fn prove_with_rng<R: RngCore + CryptoRng>(protocol: &dyn SigmaProtocol, witness: &Witness, transcript: &mut Transcript, rng: &mut R) -> Option<FiatShamirProof> {
+    let (state, commitment) = protocol.prover_commit(witness, rng)?;
+    let challenge = fiat_shamir_challenge(protocol, &commitment, transcript);
+    let response = protocol.prover_respond(witness, state, &challenge);
+    Some(FiatShamirProof { commitment, challenge, response })
+}
+
+pub fn verify(protocol: &dyn SigmaProtocol, proof: &FiatShamirProof, transcript: &mut Transcript) -> bool {
+    let challenge = fiat_shamir_challenge(protocol, &proof.commitment, transcript);
+    challenge == proof.challenge && protocol.verify(&proof.commitment, &proof.challenge, &proof.response)
+}
+
+
+#[cfg(test)]
+fn test_transcript() -> Transcript {
+    Transcript::with_context(b"zkdp/test/sigma", b"test-session", 0)
+}
+
+#[test]
+pub fn test_opening_and_equality() {
+    use crate::curve_parameters::CurveParams;
+
+    let com = CurveParams::new().commitment();
+    let x = Scalar::from(42u64);
+    let (r1, r2) = (com.sample_randomness(), com.sample_randomness());
+    let (c1, c2) = (com.commit(x, r1), com.commit(x, r2));
+
+    let opening = LinearRelation::opening(&com, c1);
+    let proof = prove(&opening, &Witness::Scalars(vec![x, r1]), &mut test_transcript()).unwrap();
+    assert!(verify(&opening, &proof, &mut test_transcript()));
+
+    let equality = LinearRelation::equality(&com, c1, c2);
+    let proof = prove(&equality, &Witness::Scalars(vec![x, r1, r2]), &mut test_transcript()).unwrap();
+    assert!(verify(&equality, &proof, &mut test_transcript()));
+
+    // Commitments to different values cannot be proven equal
+    let c3 = com.commit(x + Scalar::one(), r2);
+    let equality = LinearRelation::equality(&com, c1, c3);
+    let proof = prove(&equality, &Witness::Scalars(vec![x, r1, r2]), &mut test_transcript()).unwrap();
+    assert!(!verify(&equality, &proof, &mut test_transcript()));
+}
+
+#[test]
+pub fn test_linear_combination_and_composition() {
+    use crate::curve_parameters::CurveParams;
+
+    let com = CurveParams::new().commitment();
+    let xs = [Scalar::from(3u64), Scalar::from(5u64)];
+    let rs = [com.sample_randomness(), com.sample_randomness()];
+    let coms: Vec<RistrettoPoint> = xs.iter().zip(rs.iter()).map(|(x, r)| com.commit(*x, *r)).collect();
+    let coeffs = [Scalar::from(2u64), Scalar::from(7u64)];
+
+    // target commits to 2*3 + 7*5 + 1 = 42
+    let r_t = com.sample_randomness();
+    let target = com.commit(Scalar::from(42u64), r_t);
+    let relation = LinearRelation::linear_combination(&com, &coms, &coeffs, Scalar::one(), target);
+    let w = r_t - coeffs[0] * rs[0] - coeffs[1] * rs[1];
+    let proof = prove(&relation, &Witness::Scalars(vec![w]), &mut test_transcript()).unwrap();
+    assert!(verify(&relation, &proof, &mut test_transcript()));
+
+    // AND of an opening and the linear relation
+    let and = And::new(vec![
+        Box::new(LinearRelation::opening(&com, coms[0])),
+        Box::new(LinearRelation::linear_combination(&com, &coms, &coeffs, Scalar::one(), target)),
+    ]);
+    let witness = Witness::And(vec![Witness::Scalars(vec![xs[0], rs[0]]), Witness::Scalars(vec![w])]);
+    let proof = prove(&and, &witness, &mut test_transcript()).unwrap();
+    assert!(verify(&and, &proof, &mut test_transcript()));
+    assert!(!verify(&and, &proof, &mut Transcript::with_context(b"zkdp/test/sigma", b"test-session", 1)));
+}
+
+#[test]
+pub fn test_k_out_of_n() {
+    use crate::curve_parameters::CurveParams;
+    use crate::rng::ProtocolRng;
+
+    let com = CurveParams::new().commitment();
+    let rs: Vec<Scalar> = (0..4).map(|_| com.sample_randomness()).collect();
+    // Branch i holds if the i-th commitment opens to 0. Only branches 1 and 3 do.
+    let values = [Scalar::from(9u64), Scalar::zero(), Scalar::from(4u64), Scalar::zero()];
+    let coms: Vec<RistrettoPoint> = values.iter().zip(rs.iter()).map(|(x, r)| com.commit(*x, *r)).collect();
+    let branches = || -> Vec<Box<dyn SigmaProtocol>> {
+        coms.iter().map(|c| Box::new(LinearRelation::dlog(com.h, *c)) as Box<dyn SigmaProtocol>).collect()
+    };
+    let witness = Witness::Or(vec![None, Some(Witness::Scalars(vec![rs[1]])), None, Some(Witness::Scalars(vec![rs[3]]))]);
+
+    for k in 1..=2 {
+        let or = Or::new(k, branches());
+        let proof = prove(&or, &witness, &mut test_transcript()).unwrap();
+        assert!(verify(&or, &proof, &mut test_transcript()));
+
+        // The simulator produces accepting conversations for any challenge
+        let e = random_scalar(&mut OsRng);
+        let (a, z) = or.simulate(&e, &mut OsRng);
+        assert!(or.verify(&a, &e, &z));
+
+        // A seeded prover gives the same proof twice
+        let seeded = |seed| prove_with_rng(&or, &witness, &mut test_transcript(), &mut ProtocolRng::seeded(seed)).unwrap();
+        let (p1, p2) = (seeded(1), seeded(1));
+        assert_eq!((p1.commitment, p1.response), (p2.commitment.clone(), p2.response.clone()));
+        assert_ne!(seeded(2).commitment, p2.commitment);
+    }
+
+    // Two witnesses are not enough for 3-out-of-4
+    assert!(prove(&Or::new(3, branches()), &witness, &mut test_transcript()).is_none());
+}
+
+#[test]
+pub fn test_bit_proofs() {
+    use crate::curve_parameters::CurveParams;
+    use crate::participants::Board;
+    use crate::transcript::PRIVATE_BIT_LABEL;
+
+    let params = CurveParams::new();
+    let com = params.commitment();
+    let verifier = Board::new(params.g, params.h);
+    let ctx = |i| Transcript::with_context(PRIVATE_BIT_LABEL, b"test-session", i);
+
+    let r = com.sample_randomness();
+    let proof0 = com.create_proof_0(r, &mut ctx(0));
+    let proof1 = com.create_proof_1(r, &mut ctx(1));
+    assert_eq!(proof0.com, com.commit(Scalar::zero(), r));
+    assert_eq!(proof1.com, com.commit(Scalar::one(), r));
+    assert!(verifier.verify(&proof0, &mut ctx(0)));
+    assert!(verifier.verify(&proof1, &mut ctx(1)));
+
+    // The constant-time prover gives the proofs of the generic one, from the same randomness
+    for bit in [0u8, 1] {
+        let c = com.commit(Scalar::from(bit as u64), r);
+        let witness = Witness::Or(if bit == 0 { vec![Some(Witness::Scalars(vec![r])), None] } else { vec![None, Some(Witness::Scalars(vec![r]))] });
+        let generic = prove_with_rng(&bit_relation(&com, c), &witness, &mut ctx(3), &mut crate::rng::ProtocolRng::seeded(9)).unwrap();
+        let (c_ct, proof) = prove_bit_with_rng(&com, bit.into(), r, &mut ctx(3), &mut crate::rng::ProtocolRng::seeded(9));
+        assert_eq!(c_ct, c);
+        assert_eq!((proof.commitment, proof.challenge, proof.response), (generic.commitment, generic.challenge, generic.response));
+    }
+
+    // A commitment to 2 has no opening for either branch, so a prover has to simulate both and fails
+    let c = com.commit(Scalar::from(2u64), r);
+    let relation = bit_relation(&com, c);
+    assert!(prove(&relation, &Witness::Or(vec![None, None]), &mut ctx(2)).is_none());
+    let proof = prove(&relation, &Witness::Or(vec![Some(Witness::Scalars(vec![r])), None]), &mut ctx(2)).unwrap();
+    assert!(!verify(&relation, &proof, &mut ctx(2)));
+}
@@ -0,0 +1,176 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use utoipa::ToSchema;
+use subtle::ConstantTimeEq;
+use crate::curve_parameters::CurveParams;
+use crate::generic_commitments::{Commitment, CurveCommitment};
+use crate::rng::random_scalar;
+use crate::secrets::bit_to_scalar;
+use crate::sigma_ff::ProofScalar;
+use crate::transcript::{Transcript, CLIENT_INPUT_LABEL};
+
+// Inputs committed and proven by the clients themselves. Each client sends the session the commitment to its bit x
+// with an OR-proof that it is a bit, and splits the opening (x, r) into additive shares, one for each of the
+// non-colluding aggregators. The session gets commitments to the shares, which add up to the commitment to x, and
+// aggregator j gets share j directly from the client. Once collection closes every aggregator sums its shares over
+// the accepted clients, and the sums of the aggregators open the sum of the inputs. Neither the server nor any
+// coalition that misses one aggregator sees a client's x. The server never picks r, so it cannot move a client's
+// commitment, and the proofs are checked before a submission counts towards the aggregate.
+
+// Most aggregators per session, each holds a capability token
+pub const MAX_AGGREGATORS: usize = 16;
+
+// Scalars and points are serialized as arrays of 32 bytes, as in the session store
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct InputSubmission {
+    pub client_id: String, // Assigned with the client's token
+    #[schema(value_type = Object)]
+    pub proof: ProofScalar, // proof.com is the commitment to x under r
+    #[schema(value_type = Vec<Vec<u8>>)]
+    pub share_coms: Vec<RistrettoPoint>, // Commitment to aggregator j's share of (x, r) at j, adding up to proof.com
+}
+
+// Aggregator j's additive share of a client's opening, or its sum over the accepted clients
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct OpeningShare {
+    #[schema(value_type = Vec<u8>)]
+    pub value: Scalar,
+    #[schema(value_type = Vec<u8>)]
+    pub randomness: Scalar,
+}
+
+// Longest client ID accepted, so a submission stays a bounded line of NDJSON
+pub const MAX_CLIENT_ID_LEN: usize = 128;
+
+// Transcript of the OR-proof of one client, bound to the session and the client ID so a proof cannot be
+// replayed in another session or under another ID
+pub fn input_transcript(session_id: &str, client_id: &str) -> Transcript {
+    let mut transcript = Transcript::with_context(CLIENT_INPUT_LABEL, session_id.as_bytes(), 0);
+    transcript.append_message(b"client", client_id.as_bytes());
+    transcript
+}
+
+impl InputSubmission {
+
+    // Client side: commits to x under fresh randomness, proves it is a bit and splits the opening between the
+    // aggregators. The submission goes to the session, share j only to aggregator j.
+    pub fn new(session_id: &str, client_id: &str, x: bool, aggregators: usize) -> (InputSubmission, Vec<OpeningShare>) {
+        Self::new_with_rng(session_id, client_id, x, aggregators, &mut OsRng)
+    }
+
+    pub fn new_with_rng<R: RngCore + CryptoRng>(session_id: &str, client_id: &str, x: bool, aggregators: usize, rng: &mut R) -> (InputSubmission, Vec<OpeningShare>) {
+        let params = CurveParams::new();
+        let com = CurveCommitment::new(params.g, params.h);
+        let r = com.sample_randomness_with_rng(rng);
+        let mut transcript = input_transcript(session_id, client_id);
+        let proof = com.create_bit_proof((x as u8).ct_eq(&1), r, &mut transcript, rng);
+
+        let mut shares: Vec<OpeningShare> = (1..aggregators).map(|_| OpeningShare { value: random_scalar(rng), randomness: random_scalar(rng) }).collect();
+        let (value, randomness) = shares.iter().fold((bit_to_scalar(x as u8), r), |(v, r), share| (v - share.value, r - share.randomness));
+        shares.push(OpeningShare { value, randomness });
+        let share_coms = shares.iter().map(|share| com.commit(share.value, share.randomness)).collect();
+        (Self { client_id: client_id.to_string(), proof, share_coms }, shares)
+    }
+}
+
+// One aggregator, holding the shares it got from the clients until collection closes. A client that submitted
+// more than once may have sent several, the one that opens the accepted submission counts.
+pub struct InputAggregator {
+    index: usize,
+    shares: HashMap<String, Vec<OpeningShare>>,
+}
+
+impl InputAggregator {
+
+    pub fn new(index: usize) -> InputAggregator {
+        Self { index, shares: HashMap::new() }
+    }
+
+    // A client's share, as sent to this aggregator alone
+    pub fn receive(&mut self, client_id: &str, share: OpeningShare) {
+        self.shares.entry(client_id.to_string()).or_default().push(share);
+    }
+
+    // Sum of the shares of the accepted clients, each checked against the commitments the client submitted to the
+    // session. Err lists the clients whose share is missing or does not open its commitment, without them the sum
+    // would not open the aggregate.
+    pub fn aggregate(&self, accepted: &[AcceptedInput]) -> Result<OpeningShare, Vec<String>> {
+        let params = CurveParams::new();
+        let com = CurveCommitment::new(params.g, params.h);
+        let mut sum = OpeningShare { value: Scalar::zero(), randomness: Scalar::zero() };
+        let mut missing = Vec::new();
+        for input in accepted {
+            let mut shares = self.shares.get(&input.client_id).into_iter().flatten();
+            match input.share_coms.get(self.index).and_then(|share_com| shares.find(|s| com.open(s.value, s.randomness, *share_com))) {
+                Some(share) => {
+                    sum.value += share.value;
+                    sum.randomness += share.randomness;
+                },
+                None => missing.push(input.client_id.clone()),
+            }
+        }
+        if missing.is_empty() { Ok(sum) } else { Err(missing) }
+    }
+}
+
+// A submission the session counted, as the aggregators see it
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct AcceptedInput {
+    pub client_id: String,
+    #[schema(value_type = Vec<Vec<u8>>)]
+    pub share_coms: Vec<RistrettoPoint>,
+}
+
+// Why a submission was not counted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubmissionError {
+    InputsClosed,
+    InvalidClientId,
+    DuplicateClient,
+    InvalidOpening, // The share commitments do not add up to the commitment, or there is not one per aggregator
+    InvalidProof,
+    InputsOpen, // An aggregator sent its share while clients may still submit
+    NotAggregated, // The session takes no client inputs, or the index is not one of its aggregators
+    AlreadyShared,
+    InvalidShare, // The share does not open the sum of the aggregator's share commitments
+}
+
+impl fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubmissionError::InputsClosed => write!(f, "Input collection of this session is closed"),
+            SubmissionError::InvalidClientId => write!(f, "Client IDs must be 1 to {} bytes long and the one issued with the token", MAX_CLIENT_ID_LEN),
+            SubmissionError::DuplicateClient => write!(f, "This client already submitted an input"),
+            SubmissionError::InvalidOpening => write!(f, "The share commitments do not add up to the commitment of the input"),
+            SubmissionError::InvalidProof => write!(f, "The OR-proof of the commitment does not verify"),
+            SubmissionError::InputsOpen => write!(f, "Input collection of this session is still open"),
+            SubmissionError::NotAggregated => write!(f, "This session has no such aggregator"),
+            SubmissionError::AlreadyShared => write!(f, "This aggregator already sent its share"),
+            SubmissionError::InvalidShare => write!(f, "The share does not open the share commitments of this aggregator"),
+        }
+    }
+}
+
+impl SubmissionError {
+
+    // Stable code for API clients, independent of the message wording
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubmissionError::InputsClosed => "inputs_closed",
+            SubmissionError::InvalidClientId => "invalid_client_id",
+            SubmissionError::DuplicateClient => "duplicate_client",
+            SubmissionError::InvalidOpening => "invalid_opening",
+            SubmissionError::InvalidProof => "invalid_proof",
+            SubmissionError::InputsOpen => "inputs_open",
+            SubmissionError::NotAggregated => "not_aggregated",
+            SubmissionError::AlreadyShared => "already_shared",
+            SubmissionError::InvalidShare => "invalid_share",
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {}
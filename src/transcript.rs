@@ -0,0 +1,122 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use sha3::{Digest, Sha3_512};
+use crate::curve_parameters::{DOMAIN_SEPARATION_TAG, PROTOCOL_VERSION};
+
+// Fiat-Shamir transcript over SHA3-512. Every message is framed as len(label) || label || len(msg) || msg,
+// so two different sequences of appends can never hash to the same state. A challenge is derived from
+// the current state and then absorbed back, so later challenges depend on earlier ones.
+
+// Protocol step labels. A proof made for one step never verifies as a proof for another.
+pub const PRIVATE_BIT_LABEL: &[u8] = b"zkdp/binomial/private-bit";
+pub const BERNOULLI_COIN_LABEL: &[u8] = b"zkdp/bernoulli/coin";
+pub const TREE_NOISE_LABEL: &[u8] = b"zkdp/continual/node-noise";
+pub const VECTOR_NOISE_LABEL: &[u8] = b"zkdp/binomial/bit-vector";
+pub const PUBLIC_COIN_LABEL: &[u8] = b"zkdp/binomial/public-coins";
+pub const CLIENT_INPUT_LABEL: &[u8] = b"zkdp/binomial/client-input";
+pub const TREE_COIN_LABEL: &[u8] = b"zkdp/continual/public-coins";
+
+#[derive(Clone)]
+pub struct Transcript {
+    hasher: Sha3_512,
+}
+
+impl Transcript {
+
+    // Starts a transcript bound to the crate's domain separation tag, protocol version and the protocol step
+    pub fn new(protocol_label: &[u8]) -> Transcript {
+        let mut transcript = Self { hasher: Sha3_512::new() };
+        transcript.append_message(b"dst", DOMAIN_SEPARATION_TAG.as_bytes());
+        transcript.append_u64(b"version", PROTOCOL_VERSION as u64);
+        transcript.append_message(b"protocol", protocol_label);
+        transcript
+    }
+
+    // Transcript for the proof at position `index` of the session `session_id`
+    pub fn with_context(protocol_label: &[u8], session_id: &[u8], index: u64) -> Transcript {
+        let mut transcript = Self::new(protocol_label);
+        transcript.append_message(b"session", session_id);
+        transcript.append_u64(b"index", index);
+        transcript
+    }
+
+    pub fn append_message(&mut self, label: &[u8], message: &[u8]) {
+        self.hasher.update((label.len() as u64).to_le_bytes());
+        self.hasher.update(label);
+        self.hasher.update((message.len() as u64).to_le_bytes());
+        self.hasher.update(message);
+    }
+
+    pub fn append_u64(&mut self, label: &[u8], x: u64) {
+        self.append_message(label, &x.to_le_bytes());
+    }
+
+    pub fn append_point(&mut self, label: &[u8], point: &RistrettoPoint) {
+        self.append_message(label, point.compress().as_bytes());
+    }
+
+    pub fn append_scalar(&mut self, label: &[u8], scalar: &Scalar) {
+        self.append_message(label, scalar.as_bytes());
+    }
+
+    // Absorbs the Pedersen generators the proof is made against
+    pub fn append_generators(&mut self, g: &RistrettoPoint, h: &RistrettoPoint) {
+        self.append_point(b"g", g);
+        self.append_point(b"h", h);
+    }
+
+    pub fn challenge_scalar(&mut self, label: &[u8]) -> Scalar {
+        let mut hasher = self.hasher.clone();
+        hasher.update((label.len() as u64).to_le_bytes());
+        hasher.update(label);
+        let result: [u8; 64] = hasher.finalize().into();
+        let e = Scalar::from_bytes_mod_order_wide(&result);
+        self.append_scalar(label, &e);
+        e
+    }
+}
+
+
+#[test]
+pub fn test_transcript_binding() {
+    let challenge = |session: &[u8], index: u64| Transcript::with_context(PRIVATE_BIT_LABEL, session, index).challenge_scalar(b"e");
+
+    assert_eq!(challenge(b"session-a", 0), challenge(b"session-a", 0));
+    assert_ne!(challenge(b"session-a", 0), challenge(b"session-b", 0));
+    assert_ne!(challenge(b"session-a", 0), challenge(b"session-a", 1));
+    assert_ne!(
+        Transcript::with_context(PRIVATE_BIT_LABEL, b"s", 0).challenge_scalar(b"e"),
+        Transcript::with_context(BERNOULLI_COIN_LABEL, b"s", 0).challenge_scalar(b"e")
+    );
+
+    // Framing: moving bytes between the label and the message changes the challenge
+    let mut t1 = Transcript::new(PRIVATE_BIT_LABEL);
+    t1.append_message(b"ab", b"c");
+    let mut t2 = Transcript::new(PRIVATE_BIT_LABEL);
+    t2.append_message(b"a", b"bc");
+    assert_ne!(t1.challenge_scalar(b"e"), t2.challenge_scalar(b"e"));
+}
+
+#[test]
+pub fn test_or_proof_replay_rejected() {
+    use crate::curve_parameters::CurveParams;
+    use crate::generic_commitments::Commitment;
+    use crate::participants::Board;
+
+    let params = CurveParams::new();
+    let com = params.commitment();
+    let verifier = Board::new(params.g, params.h);
+
+    let r = com.sample_randomness();
+    let proof = com.create_proof_1(r, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"session-a", 7));
+    assert!(verifier.verify(&proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"session-a", 7)));
+
+    // Same proof replayed at another position, in another session, or for another protocol step
+    assert!(!verifier.verify(&proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"session-a", 8)));
+    assert!(!verifier.verify(&proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"session-b", 7)));
+    assert!(!verifier.verify(&proof, &mut Transcript::with_context(BERNOULLI_COIN_LABEL, b"session-a", 7)));
+
+    // Same proof checked against other generators
+    let other = Board::new(params.g, params.g + params.h);
+    assert!(!other.verify(&proof, &mut Transcript::with_context(PRIVATE_BIT_LABEL, b"session-a", 7)));
+}
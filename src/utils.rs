@@ -3,23 +3,6 @@
 use openssl::bn::{BigNum, BigNumContext};
 use openssl::error::ErrorStack;
 
-pub fn additive_share(secret: &BigNum, q: &BigNum, num_shares: usize, ctx: &mut BigNumContext)->Vec<BigNum>{
-    
-    let mut shares = Vec::new();
-    for _ in 1..(num_shares){            
-        let tmp = gen_random(q).unwrap(); 
-        shares.push(tmp);
-    }
-
-    let secret = &BigNum::new().unwrap() + secret;
-    let total = shares.iter().fold(BigNum::new().unwrap(), |acc, x| &acc + x);
-    let mut last_share = BigNum::new().unwrap();
-    _ = last_share.mod_sub(&secret, &total, q, ctx);
-        
-    shares.push(last_share);
-    return shares;
-}
-
 pub fn gen_random(limit: &BigNum) -> Result<BigNum, ErrorStack> {
    
     // generate random bignum between 1, limit-1
@@ -58,20 +41,15 @@ pub fn calculate_q(p: &BigNum) -> Result<BigNum, ErrorStack> {
     // Ok(q)
 }
 
+// Debug-level dumps of vectors, shown with RUST_LOG=debug
 pub fn print_vec<T>(vec: &Vec<T>)where T: std::fmt::Display{
-    print!("[ ");
-    for elem in vec.iter(){
-        print!("{}, ", elem);
-    }
-    println!(" ]");
+    let elems: Vec<String> = vec.iter().map(|elem| elem.to_string()).collect();
+    tracing::debug!("[ {} ]", elems.join(", "));
 }
 
 pub fn print_array<T>(vec: &[T;32])where T: std::fmt::Display{
-    print!("[ ");
-    for elem in vec.iter(){
-        print!("{}, ", elem);
-    }
-    println!(" ]");
+    let elems: Vec<String> = vec.iter().map(|elem| elem.to_string()).collect();
+    tracing::debug!("[ {} ]", elems.join(", "));
 }
 
 pub fn mod_exp(g: &BigNum, x: &BigNum, q: &BigNum, ctx: &mut BigNumContext)->BigNum{
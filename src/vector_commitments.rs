@@ -0,0 +1,389 @@
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{Identity, MultiscalarMul, VartimeMultiscalarMul};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use crate::curve_parameters::CurveParams;
+use crate::rng::random_scalar;
+use crate::transcript::Transcript;
+
+// Vector Pedersen commitments Com(b_1..b_n; r) = sum_i b_i G_i + r h, and a Bulletproofs-style argument that
+// every committed entry is a bit and that a public weighted sum of the entries, sum_i w_i b_i, is the value
+// committed in V = v g + gamma h. With w = 1 this is a proof of the sum. With w_i = 1 - 2 c_i for public coins c
+// it proves the sum of the XORed bits b_i ^ c_i without committing to them one by one.
+// The proof is 4 + 2 log(n) points and 5 scalars, against n points and n OR-proofs for per-bit commitments.
+
+fn inner_product(a: &[Scalar], b: &[Scalar]) -> Scalar {
+    a.iter().zip(b.iter()).map(|(a_i, b_i)| a_i * b_i).sum()
+}
+
+// 1, x, x^2, ..., x^{n-1}
+fn powers(x: Scalar, n: usize) -> Vec<Scalar> {
+    let mut out = Vec::with_capacity(n);
+    let mut acc = Scalar::one();
+    for _ in 0..n {
+        out.push(acc);
+        acc *= x;
+    }
+    out
+}
+
+// Proof that P = <a, G> + <b, H> + <a, b> Q with log(n) rounds of halving (Bulletproofs, protocol 2)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InnerProductProof {
+    pub l_vec: Vec<RistrettoPoint>,
+    pub r_vec: Vec<RistrettoPoint>,
+    pub a: Scalar,
+    pub b: Scalar,
+}
+
+impl InnerProductProof {
+
+    fn create(transcript: &mut Transcript, q: &RistrettoPoint, g_vec: &[RistrettoPoint], h_vec: &[RistrettoPoint], a: &[Scalar], b: &[Scalar]) -> InnerProductProof {
+        let mut g = g_vec.to_vec();
+        let mut h = h_vec.to_vec();
+        let mut a = a.to_vec();
+        let mut b = b.to_vec();
+        let mut l_vec = Vec::new();
+        let mut r_vec = Vec::new();
+
+        while a.len() > 1 {
+            let n = a.len() / 2;
+            let (a_lo, a_hi) = a.split_at(n);
+            let (b_lo, b_hi) = b.split_at(n);
+            let (g_lo, g_hi) = g.split_at(n);
+            let (h_lo, h_hi) = h.split_at(n);
+
+            let c_l = inner_product(a_lo, b_hi);
+            let c_r = inner_product(a_hi, b_lo);
+            let l = RistrettoPoint::multiscalar_mul(
+                a_lo.iter().chain(b_hi.iter()).chain(std::iter::once(&c_l)),
+                g_hi.iter().chain(h_lo.iter()).chain(std::iter::once(q)),
+            );
+            let r = RistrettoPoint::multiscalar_mul(
+                a_hi.iter().chain(b_lo.iter()).chain(std::iter::once(&c_r)),
+                g_lo.iter().chain(h_hi.iter()).chain(std::iter::once(q)),
+            );
+            transcript.append_point(b"L", &l);
+            transcript.append_point(b"R", &r);
+            l_vec.push(l);
+            r_vec.push(r);
+
+            let u = transcript.challenge_scalar(b"u");
+            let u_inv = u.invert();
+            a = (0..n).map(|i| a_lo[i] * u + a_hi[i] * u_inv).collect();
+            b = (0..n).map(|i| b_lo[i] * u_inv + b_hi[i] * u).collect();
+            g = (0..n).map(|i| RistrettoPoint::vartime_multiscalar_mul(&[u_inv, u], &[g_lo[i], g_hi[i]])).collect();
+            h = (0..n).map(|i| RistrettoPoint::vartime_multiscalar_mul(&[u, u_inv], &[h_lo[i], h_hi[i]])).collect();
+        }
+
+        InnerProductProof { l_vec, r_vec, a: a[0], b: b[0] }
+    }
+
+    // Replays the transcript and returns the challenges u_j together with the coefficients s_i
+    // such that the folded generators are G = sum_i s_i G_i and H = sum_i s_i^{-1} H_i
+    fn verification_scalars(&self, transcript: &mut Transcript, n: usize) -> Option<(Vec<Scalar>, Vec<Scalar>)> {
+        let rounds = self.l_vec.len();
+        if self.r_vec.len() != rounds || n != 1 << rounds {
+            return None;
+        }
+        let mut u = Vec::with_capacity(rounds);
+        for (l, r) in self.l_vec.iter().zip(self.r_vec.iter()) {
+            transcript.append_point(b"L", l);
+            transcript.append_point(b"R", r);
+            u.push(transcript.challenge_scalar(b"u"));
+        }
+        let u_inv: Vec<Scalar> = u.iter().map(|u_j| u_j.invert()).collect();
+        // The first round splits on the top bit of the index
+        let s = (0..n).map(|i| {
+            (0..rounds).fold(Scalar::one(), |acc, j| {
+                if (i >> (rounds - 1 - j)) & 1 == 1 { acc * u[j] } else { acc * u_inv[j] }
+            })
+        }).collect();
+        Some((u, s))
+    }
+}
+
+// Bit-vector proof. a_r_com commits to b - 1 on the H generators, so that C + a_r_com is the usual A of a range proof.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BitVectorProof {
+    pub a_r_com: RistrettoPoint,
+    pub s: RistrettoPoint,
+    pub t1: RistrettoPoint,
+    pub t2: RistrettoPoint,
+    pub tau_x: Scalar,
+    pub mu: Scalar,
+    pub t_hat: Scalar,
+    pub ipp: InnerProductProof,
+}
+
+impl BitVectorProof {
+
+    // Number of group elements a verifier receives on top of C and V
+    pub fn num_points(&self) -> usize {
+        4 + self.ipp.l_vec.len() + self.ipp.r_vec.len()
+    }
+}
+
+pub struct VectorCommitment {
+    pub g: RistrettoPoint,
+    pub h: RistrettoPoint,
+    pub g_vec: Vec<RistrettoPoint>,
+    pub h_vec: Vec<RistrettoPoint>,
+    pub u: RistrettoPoint,
+}
+
+impl VectorCommitment {
+
+    // Commitment key for vectors of up to n entries, padded to a power of two for the inner-product argument
+    pub fn new(n: usize) -> VectorCommitment {
+        let params = CurveParams::with_generators(n.max(1).next_power_of_two());
+        Self::from_params(&params)
+    }
+
+    pub fn from_params(params: &CurveParams) -> VectorCommitment {
+        Self {
+            g: params.g,
+            h: params.h,
+            g_vec: params.generators.clone(),
+            h_vec: params.h_generators.clone(),
+            u: params.ipp_generator(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.g_vec.len()
+    }
+
+    pub fn commit(&self, message: &[Scalar], randomness: Scalar) -> RistrettoPoint {
+        assert!(message.len() <= self.capacity());
+        RistrettoPoint::multiscalar_mul(
+            message.iter().chain(std::iter::once(&randomness)),
+            self.g_vec.iter().take(message.len()).chain(std::iter::once(&self.h)),
+        )
+    }
+
+    // Commitment to a scalar with the same g and h, as in CurveCommitment
+    pub fn commit_scalar(&self, message: Scalar, randomness: Scalar) -> RistrettoPoint {
+        message * self.g + randomness * self.h
+    }
+
+    fn padded_len(n: usize) -> usize {
+        n.max(1).next_power_of_two()
+    }
+
+    fn append_statement(&self, transcript: &mut Transcript, c: &RistrettoPoint, v: &RistrettoPoint, weights: &[Scalar]) {
+        transcript.append_generators(&self.g, &self.h);
+        transcript.append_u64(b"n", weights.len() as u64);
+        for w in weights.iter() {
+            transcript.append_scalar(b"w", w);
+        }
+        transcript.append_point(b"C", c);
+        transcript.append_point(b"V", v);
+    }
+
+    // Proves that every entry of bits (committed in C with randomness r) is 0 or 1 and that
+    // sum_i weights_i bits_i is committed in V = v g + gamma h. Returns None if there are not enough generators.
+    pub fn prove_bits(&self, transcript: &mut Transcript, bits: &[Scalar], r: Scalar, weights: &[Scalar], gamma: Scalar) -> Option<(BitVectorProof, RistrettoPoint, RistrettoPoint)> {
+        self.prove_bits_with_rng(transcript, bits, r, weights, gamma, &mut OsRng)
+    }
+
+    // Same as prove_bits, with the blinding of the proof drawn from rng
+    pub fn prove_bits_with_rng<R: RngCore + CryptoRng>(&self, transcript: &mut Transcript, bits: &[Scalar], r: Scalar, weights: &[Scalar], gamma: Scalar, rng: &mut R) -> Option<(BitVectorProof, RistrettoPoint, RistrettoPoint)> {
+        let n = Self::padded_len(bits.len());
+        if bits.len() != weights.len() || n > self.capacity() {
+            return None;
+        }
+        let (g_vec, h_vec) = (&self.g_vec[..n], &self.h_vec[..n]);
+
+        let mut a_l = bits.to_vec();
+        a_l.resize(n, Scalar::zero());
+        let a_r: Vec<Scalar> = a_l.iter().map(|a| a - Scalar::one()).collect();
+        let mut w = weights.to_vec();
+        w.resize(n, Scalar::zero());
+
+        let c = self.commit(bits, r);
+        let v = inner_product(&a_l, &w);
+        let v_com = self.commit_scalar(v, gamma);
+        self.append_statement(transcript, &c, &v_com, &w);
+
+        let alpha_r = random_scalar(rng);
+        let a_r_com = RistrettoPoint::multiscalar_mul(a_r.iter().chain(std::iter::once(&alpha_r)), h_vec.iter().chain(std::iter::once(&self.h)));
+        let alpha = r + alpha_r;
+
+        let s_l: Vec<Scalar> = (0..n).map(|_| random_scalar(rng)).collect();
+        let s_r: Vec<Scalar> = (0..n).map(|_| random_scalar(rng)).collect();
+        let rho = random_scalar(rng);
+        let s = RistrettoPoint::multiscalar_mul(
+            s_l.iter().chain(s_r.iter()).chain(std::iter::once(&rho)),
+            g_vec.iter().chain(h_vec.iter()).chain(std::iter::once(&self.h)),
+        );
+
+        transcript.append_point(b"A_R", &a_r_com);
+        transcript.append_point(b"S", &s);
+        let y = transcript.challenge_scalar(b"y");
+        let z = transcript.challenge_scalar(b"z");
+        let z2 = z * z;
+
+        // l(X) = (a_L - z 1) + s_L X and r(X) = y^n o (a_R + z 1 + s_R X) + z^2 w
+        let y_n = powers(y, n);
+        let l0: Vec<Scalar> = a_l.iter().map(|a| a - z).collect();
+        let l1 = s_l;
+        let r0: Vec<Scalar> = (0..n).map(|i| y_n[i] * (a_r[i] + z) + z2 * w[i]).collect();
+        let r1: Vec<Scalar> = (0..n).map(|i| y_n[i] * s_r[i]).collect();
+
+        let t1 = inner_product(&l0, &r1) + inner_product(&l1, &r0);
+        let t2 = inner_product(&l1, &r1);
+        let (tau1, tau2) = (random_scalar(rng), random_scalar(rng));
+        let t1_com = self.commit_scalar(t1, tau1);
+        let t2_com = self.commit_scalar(t2, tau2);
+        transcript.append_point(b"T1", &t1_com);
+        transcript.append_point(b"T2", &t2_com);
+        let x = transcript.challenge_scalar(b"x");
+
+        let l: Vec<Scalar> = (0..n).map(|i| l0[i] + l1[i] * x).collect();
+        let r_vec: Vec<Scalar> = (0..n).map(|i| r0[i] + r1[i] * x).collect();
+        let t_hat = inner_product(&l, &r_vec);
+        let tau_x = tau2 * x * x + tau1 * x + z2 * gamma;
+        let mu = alpha + rho * x;
+        transcript.append_scalar(b"tau_x", &tau_x);
+        transcript.append_scalar(b"mu", &mu);
+        transcript.append_scalar(b"t_hat", &t_hat);
+
+        // Inner-product argument for <l, r> = t_hat over G and H' = y^{-i} H_i
+        let q = transcript.challenge_scalar(b"w") * self.u;
+        let y_inv_n = powers(y.invert(), n);
+        let h_prime: Vec<RistrettoPoint> = h_vec.iter().zip(y_inv_n.iter()).map(|(h, y_inv)| h * y_inv).collect();
+        let ipp = InnerProductProof::create(transcript, &q, g_vec, &h_prime, &l, &r_vec);
+
+        Some((BitVectorProof { a_r_com, s, t1: t1_com, t2: t2_com, tau_x, mu, t_hat, ipp }, c, v_com))
+    }
+
+    // Proof of the plain sum of a committed bit vector
+    pub fn prove_sum(&self, transcript: &mut Transcript, bits: &[Scalar], r: Scalar, gamma: Scalar) -> Option<(BitVectorProof, RistrettoPoint, RistrettoPoint)> {
+        self.prove_bits(transcript, bits, r, &vec![Scalar::one(); bits.len()], gamma)
+    }
+
+    pub fn verify_bits(&self, transcript: &mut Transcript, c: &RistrettoPoint, v_com: &RistrettoPoint, weights: &[Scalar], proof: &BitVectorProof) -> bool {
+        let n = Self::padded_len(weights.len());
+        if n > self.capacity() {
+            return false;
+        }
+        let mut w = weights.to_vec();
+        w.resize(n, Scalar::zero());
+        self.append_statement(transcript, c, v_com, &w);
+
+        transcript.append_point(b"A_R", &proof.a_r_com);
+        transcript.append_point(b"S", &proof.s);
+        let y = transcript.challenge_scalar(b"y");
+        let z = transcript.challenge_scalar(b"z");
+        let z2 = z * z;
+        transcript.append_point(b"T1", &proof.t1);
+        transcript.append_point(b"T2", &proof.t2);
+        let x = transcript.challenge_scalar(b"x");
+        transcript.append_scalar(b"tau_x", &proof.tau_x);
+        transcript.append_scalar(b"mu", &proof.mu);
+        transcript.append_scalar(b"t_hat", &proof.t_hat);
+        let q_scalar = transcript.challenge_scalar(b"w");
+
+        // t_hat g + tau_x h = z^2 V + delta(y, z) g + x T1 + x^2 T2
+        let y_n = powers(y, n);
+        let sum_y: Scalar = y_n.iter().sum();
+        let sum_w: Scalar = w.iter().sum();
+        let delta = (z - z2) * sum_y - z2 * z * sum_w;
+        let lhs = self.commit_scalar(proof.t_hat, proof.tau_x);
+        let rhs = RistrettoPoint::vartime_multiscalar_mul(&[z2, delta, x, x * x], &[*v_com, self.g, proof.t1, proof.t2]);
+        if lhs != rhs {
+            return false;
+        }
+
+        // P = C + A_R + x S - z <1, G> + <z + z^2 w_i y^{-i}, H> - mu h + t_hat Q must equal the folded
+        // inner-product commitment a G' + b H' + a b Q after adding u_j^2 L_j + u_j^{-2} R_j
+        let (u, s) = match proof.ipp.verification_scalars(transcript, n) {
+            Some(v) => v,
+            None => return false,
+        };
+        let mut s_inv = s.clone();
+        Scalar::batch_invert(&mut s_inv);
+        let y_inv_n = powers(y.invert(), n);
+        let (a, b) = (proof.ipp.a, proof.ipp.b);
+
+        let g_scalars = s.iter().map(|s_i| -z - a * s_i);
+        let h_scalars = (0..n).map(|i| z + (z2 * w[i] - b * s_inv[i]) * y_inv_n[i]);
+        let u_sq = u.iter().map(|u_j| u_j * u_j);
+        let u_inv_sq = u.iter().map(|u_j| { let u_inv = u_j.invert(); u_inv * u_inv });
+
+        let check = RistrettoPoint::vartime_multiscalar_mul(
+            [Scalar::one(), Scalar::one(), x, -proof.mu, (proof.t_hat - a * b) * q_scalar].into_iter()
+                .chain(g_scalars)
+                .chain(h_scalars)
+                .chain(u_sq)
+                .chain(u_inv_sq),
+            [*c, proof.a_r_com, proof.s, self.h, self.u].iter()
+                .chain(self.g_vec[..n].iter())
+                .chain(self.h_vec[..n].iter())
+                .chain(proof.ipp.l_vec.iter())
+                .chain(proof.ipp.r_vec.iter()),
+        );
+        check == RistrettoPoint::identity()
+    }
+}
+
+
+#[cfg(test)]
+fn test_transcript() -> Transcript {
+    Transcript::with_context(crate::transcript::VECTOR_NOISE_LABEL, b"test-session", 0)
+}
+
+#[test]
+pub fn test_bit_vector_sum() {
+    use crate::rand::Rng;
+    let mut rng = rand::thread_rng();
+    let n = 1000;
+    let vc = VectorCommitment::new(n);
+    let bits: Vec<Scalar> = (0..n).map(|_| Scalar::from(rng.gen_bool(0.5) as u64)).collect();
+    let (r, gamma) = (random_scalar(&mut OsRng), random_scalar(&mut OsRng));
+
+    let (proof, c, v_com) = vc.prove_sum(&mut test_transcript(), &bits, r, gamma).unwrap();
+    assert_eq!(c, vc.commit(&bits, r));
+    assert_eq!(v_com, vc.commit_scalar(bits.iter().sum(), gamma));
+    assert!(vc.verify_bits(&mut test_transcript(), &c, &v_com, &vec![Scalar::one(); n], &proof));
+    assert_eq!(proof.num_points(), 4 + 2 * 10); // 1000 entries pad to 2^10
+
+    // Claiming another sum fails
+    let wrong_v = v_com + vc.g;
+    assert!(!vc.verify_bits(&mut test_transcript(), &c, &wrong_v, &vec![Scalar::one(); n], &proof));
+}
+
+#[test]
+pub fn test_bit_vector_weighted_xor() {
+    use crate::rand::Rng;
+    let mut rng = rand::thread_rng();
+    let n = 64;
+    let vc = VectorCommitment::new(n);
+    let bits: Vec<u64> = (0..n).map(|_| rng.gen_bool(0.5) as u64).collect();
+    let coins: Vec<u64> = (0..n).map(|_| rng.gen_bool(0.5) as u64).collect();
+    let scalars: Vec<Scalar> = bits.iter().map(|b| Scalar::from(*b)).collect();
+    let weights: Vec<Scalar> = coins.iter().map(|c| Scalar::one() - Scalar::from(2 * c)).collect();
+    let (r, gamma) = (random_scalar(&mut OsRng), random_scalar(&mut OsRng));
+
+    let (proof, c, v_com) = vc.prove_bits(&mut test_transcript(), &scalars, r, &weights, gamma).unwrap();
+    assert!(vc.verify_bits(&mut test_transcript(), &c, &v_com, &weights, &proof));
+
+    // sum_i b_i ^ c_i = <b, w> + #{c_i = 1}
+    let xor_sum: u64 = bits.iter().zip(coins.iter()).map(|(b, c)| b ^ c).sum();
+    let ones: u64 = coins.iter().sum();
+    assert_eq!(v_com + Scalar::from(ones) * vc.g, vc.commit_scalar(Scalar::from(xor_sum), gamma));
+}
+
+#[test]
+pub fn test_bit_vector_rejects_non_bits() {
+    let n = 8;
+    let vc = VectorCommitment::new(n);
+    let mut bits = vec![Scalar::one(); n];
+    bits[3] = Scalar::from(2u64); // Not a bit, the sum is still consistent
+    let (r, gamma) = (random_scalar(&mut OsRng), random_scalar(&mut OsRng));
+
+    let (proof, c, v_com) = vc.prove_sum(&mut test_transcript(), &bits, r, gamma).unwrap();
+    assert!(!vc.verify_bits(&mut test_transcript(), &c, &v_com, &vec![Scalar::one(); n], &proof));
+}
@@ -1,16 +1,30 @@
 use openssl::bn::{BigNum, BigNumContext};
-use crate::utils::{gen_random};
+use crate::algebra::{BigNumField, Field};
+use crate::generic_commitments::{AdditiveSharing, SecretSharing};
+use crate::beaver_triple::BeaverTriple;
+use crate::heavy_hitters::{HeavyHitterReport, LevelSketch};
+use crate::idpf;
 
 pub struct Client{
     num_servers: usize,
     num_candidates: u32,
     q: BigNum,
+    sharing: AdditiveSharing<BigNumField>,
     pub kappa: BigNum    
 }
 pub struct Share{
     pub shares: Vec<BigNum>,
 }
 
+// Everything a client sends the Poplar servers for one vote, see poplar::validate_vote
+pub struct PoplarVote{
+    pub vote: Vec<Share>, // One-hot
+    pub kvote: Vec<Share>, // The vote scaled by kappa
+    pub triple: BeaverTriple, // Client-generated
+    pub ucase_a: Share, // kappa - 2a
+    pub ucase_b: Share, // a^2 + b + c - a kappa
+}
+
 
 impl Client{
 
@@ -18,16 +32,16 @@ impl Client{
        
         
         let q = &BigNum::new().unwrap() + _q;
-        
-        let kappa = gen_random(_q).unwrap();
-        Self{num_servers, num_candidates, q, kappa}
+        let sharing = AdditiveSharing{ field: BigNumField::new(_q) };
+        let kappa = sharing.field.random();
+        Self{num_servers, num_candidates, q, sharing, kappa}
     }
 
     pub fn generate_fake_vote(&self)->u32{
         return 1;
     } 
         
-    pub fn vote(&self, vote: u32, ctx: &mut BigNumContext)->Vec::<Share>{
+    pub fn vote(&self, vote: u32)->Vec::<Share>{
 
         if vote > self.num_candidates -1 {
             panic!("crash and burn");
@@ -36,16 +50,16 @@ impl Client{
         let mut encoded_vote = Vec::<Share>::with_capacity(self.num_candidates as usize);        
         for i in 0..self.num_candidates as usize{
             if i as u32 == vote{
-                encoded_vote.push(self.share(&BigNum::from_u32(1).unwrap(), ctx));
+                encoded_vote.push(self.share(&BigNum::from_u32(1).unwrap()));
             }
             else{
-                encoded_vote.push(self.share(&BigNum::from_u32(0).unwrap(), ctx));
+                encoded_vote.push(self.share(&BigNum::from_u32(0).unwrap()));
             }            
         }
         return encoded_vote;
     }
 
-    pub fn poplar_vote(&self, vote: u32, ctx: &mut BigNumContext)->Vec::<Share>{
+    pub fn poplar_vote(&self, vote: u32)->Vec::<Share>{
 
         if vote > self.num_candidates -1 {
             panic!("crash and burn");
@@ -54,10 +68,10 @@ impl Client{
         let mut encoded_vote = Vec::<Share>::with_capacity(self.num_candidates as usize);        
         for i in 0..self.num_candidates as usize{
             if i as u32 == vote{
-                encoded_vote.push(self.share(&self.kappa, ctx));
+                encoded_vote.push(self.share(&self.kappa));
             }
             else{
-                encoded_vote.push(self.share(&BigNum::from_u32(0).unwrap(), ctx));
+                encoded_vote.push(self.share(&BigNum::from_u32(0).unwrap()));
             }            
         }
         return encoded_vote;
@@ -73,32 +87,34 @@ impl Client{
         let tmp = &(&(a*a) + b) + c; // a^2 + b +c 
         _ = ucase_b.mod_sub(&tmp, &(a*&self.kappa), &self.q, ctx); // a^2 + b +c - a*kappa
 
-        return (self.share(&ucase_a, ctx), self.share(&ucase_b, ctx));
+        return (self.share(&ucase_a), self.share(&ucase_b));
         
     }
 
-    pub fn share(&self, _secret: &BigNum, ctx: &mut BigNumContext)->Share{
-
-        let mut shares = Vec::new();
-
-        for _ in 1..(self.num_servers){            
-            let tmp = gen_random(&self.q).unwrap(); 
-
-            shares.push(tmp);
-        }
-        
-        let secret = &BigNum::new().unwrap() + _secret;
-        let total = shares.iter().fold(BigNum::new().unwrap(), |acc, x| &acc + x);
-        let mut last_share = BigNum::new().unwrap();
-        _ = last_share.mod_sub(&secret, &total, &self.q, ctx);
-        
-        shares.push(last_share);
+    pub fn poplar_submission(&self, vote: u32, ctx: &mut BigNumContext)->PoplarVote{
 
-        return Share{ 
-            shares: shares
-        };
+        let (triple, [a, b, c]) = BeaverTriple::client_generated(self.num_servers, &self.q);
+        let (ucase_a, ucase_b) = self.get_ab_for_poplar(&a, &b, &c, ctx);
+        PoplarVote{ vote: self.vote(vote), kvote: self.poplar_vote(vote), triple, ucase_a, ucase_b }
     }
 
+    // Heavy-hitters report for the bit-string alpha, IDPF keys with output (1, kappa) on every prefix and a fresh
+    // triple per level. Needs two servers.
+    pub fn heavy_hitter_report(&self, alpha: &[bool], ctx: &mut BigNumContext)->HeavyHitterReport{
+
+        assert_eq!(self.num_servers, 2);
+        let betas: Vec<idpf::Output> = alpha.iter().map(|_| [BigNum::from_u32(1).unwrap(), self.kappa.to_owned().unwrap()]).collect();
+        let keys = idpf::gen(alpha, &betas, &self.q, ctx);
+        let levels = alpha.iter().map(|_| {
+            let (triple, [a, b, c]) = BeaverTriple::client_generated(self.num_servers, &self.q);
+            let (ucase_a, ucase_b) = self.get_ab_for_poplar(&a, &b, &c, ctx);
+            LevelSketch{ triple, ucase_a, ucase_b }
+        }).collect();
+        HeavyHitterReport{ keys, levels }
+    }
 
+    pub fn share(&self, secret: &BigNum)->Share{
 
-}
\ No newline at end of file
+        Share{ shares: self.sharing.share(secret, self.num_servers) }
+    }
+}
@@ -0,0 +1,592 @@
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use dp_client::binomial_runner::BinomialRunner;
+use dp_client::curve_parameters::{CurveParams, DOMAIN_SEPARATION_TAG, PROTOCOL_VERSION};
+use dp_client::generic_commitments::{Commitment, CurveCommitment};
+use dp_client::rng::{random_scalar, ProtocolRng};
+use dp_client::sigma_ff::ProofScalar;
+use dp_client::sigma_protocols::{self, prove_with_rng, And, FiatShamirProof, LinearRelation, Or, SigmaProtocol, Witness};
+use dp_client::transcript::{Transcript, PRIVATE_BIT_LABEL, PUBLIC_COIN_LABEL, VECTOR_NOISE_LABEL};
+use dp_client::vector_commitments::{BitVectorProof, InnerProductProof, VectorCommitment};
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use subtle::ConstantTimeEq;
+
+// Known-answer vectors. Every fixture under tests/vectors is regenerated from its seeds and has to match byte for
+// byte, so a refactor cannot silently change a commitment, a proof or the Fiat-Shamir hash. Each fixture is then
+// checked again from the JSON alone, the way a verifier in another language reads it (see tests/vectors/README.md).
+// After a deliberate format change, rewrite them with ZKDP_UPDATE_VECTORS=1 cargo test --test known_answers.
+
+const UPDATE_VAR: &str = "ZKDP_UPDATE_VECTORS";
+const SESSION: &str = "kat-session";
+const SIGMA_LABEL: &str = "zkdp/test-vectors/sigma";
+
+// Compares the generated vectors with the fixture, or rewrites the fixture. Returns what is on disk. Rewriting is
+// refused under CI, where a leaked UPDATE_VAR would make every comparison pass.
+fn check_fixture(name: &str, generated: Value) -> Value {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors").join(name);
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        assert!(std::env::var_os("CI").is_none(), "{} is set under CI, refusing to rewrite {}", UPDATE_VAR, name);
+        std::fs::write(&path, serde_json::to_string_pretty(&generated).unwrap() + "\n").unwrap();
+        return generated;
+    }
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let fixture: Value = serde_json::from_str(&text).unwrap();
+    assert!(fixture == generated, "{} no longer matches the code. If the change is intended, rerun with {}=1", name, UPDATE_VAR);
+    fixture
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(v: &Value) -> Vec<u8> {
+    let s = v.as_str().expect("hex string");
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("hex digit")).collect()
+}
+
+fn bytes32(v: &Value) -> [u8; 32] {
+    unhex(v).try_into().expect("32 bytes")
+}
+
+fn point_json(p: &RistrettoPoint) -> Value {
+    json!(hex(p.compress().as_bytes()))
+}
+
+fn scalar_json(s: &Scalar) -> Value {
+    json!(hex(s.as_bytes()))
+}
+
+fn points_json(ps: &[RistrettoPoint]) -> Value {
+    Value::Array(ps.iter().map(point_json).collect())
+}
+
+fn scalars_json(ss: &[Scalar]) -> Value {
+    Value::Array(ss.iter().map(scalar_json).collect())
+}
+
+fn point(v: &Value) -> RistrettoPoint {
+    CompressedRistretto(bytes32(v)).decompress().expect("canonical point")
+}
+
+fn scalar(v: &Value) -> Scalar {
+    Scalar::from_canonical_bytes(bytes32(v)).expect("canonical scalar")
+}
+
+fn points(v: &Value) -> Vec<RistrettoPoint> {
+    v.as_array().expect("array of points").iter().map(point).collect()
+}
+
+fn scalars(v: &Value) -> Vec<Scalar> {
+    v.as_array().expect("array of scalars").iter().map(scalar).collect()
+}
+
+fn str_of(v: &Value) -> &str {
+    v.as_str().expect("string")
+}
+
+fn u64_of(v: &Value) -> u64 {
+    v.as_u64().expect("integer")
+}
+
+// The runner reports points and scalars as the decimal value of their little-endian bytes
+fn decimal_to_hex(s: &str) -> Value {
+    let mut bytes = BigUint::parse_bytes(s.as_bytes(), 10).expect("decimal").to_bytes_le();
+    bytes.resize(32, 0);
+    json!(hex(&bytes))
+}
+
+fn params_json() -> Value {
+    let params = CurveParams::new();
+    json!({ "dst": DOMAIN_SEPARATION_TAG, "version": PROTOCOL_VERSION, "g": point_json(&params.g), "h": point_json(&params.h) })
+}
+
+// The generators in the fixture are the ones this build derives
+fn fixture_commitment(fixture: &Value) -> CurveCommitment {
+    let params = &fixture["params"];
+    assert_eq!((str_of(&params["dst"]), u64_of(&params["version"])), (DOMAIN_SEPARATION_TAG, PROTOCOL_VERSION as u64));
+    let com = CurveCommitment::new(point(&params["g"]), point(&params["h"]));
+    let derived = CurveParams::new();
+    assert_eq!((com.g, com.h), (derived.g, derived.h));
+    com
+}
+
+fn bit_proof_json(proof: &ProofScalar) -> Value {
+    json!({
+        "com": point_json(&proof.com),
+        "d0": point_json(&proof.d0),
+        "d1": point_json(&proof.d1),
+        "e": scalar_json(&proof.e),
+        "e0": scalar_json(&proof.e0),
+        "e1": scalar_json(&proof.e1),
+        "v0": scalar_json(&proof.v0),
+        "v1": scalar_json(&proof.v1),
+    })
+}
+
+fn bit_proof(v: &Value) -> ProofScalar {
+    ProofScalar {
+        com: point(&v["com"]),
+        d0: point(&v["d0"]),
+        d1: point(&v["d1"]),
+        e: scalar(&v["e"]),
+        e0: scalar(&v["e0"]),
+        e1: scalar(&v["e1"]),
+        v0: scalar(&v["v0"]),
+        v1: scalar(&v["v1"]),
+    }
+}
+
+fn label(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn context(protocol: &str, session: &str, index: u64) -> Transcript {
+    Transcript::with_context(protocol.as_bytes(), session.as_bytes(), index)
+}
+
+
+// The Fiat-Shamir hash on its own: a script of appends, each challenge with its expected value
+#[test]
+pub fn test_transcript_vectors() {
+    fn run(steps: &[Value]) -> Vec<Value> {
+        let mut transcript: Option<Transcript> = None;
+        steps.iter().map(|step| {
+            let label = step["label"].as_str().unwrap_or_default().as_bytes();
+            let mut out = step.clone();
+            match str_of(&step["op"]) {
+                "new" => transcript = Some(Transcript::new(str_of(&step["protocol"]).as_bytes())),
+                "with_context" => transcript = Some(context(str_of(&step["protocol"]), str_of(&step["session"]), u64_of(&step["index"]))),
+                op => {
+                    let t = transcript.as_mut().expect("a transcript is started first");
+                    match op {
+                        "append_message" => t.append_message(label, &unhex(&step["message"])),
+                        "append_u64" => t.append_u64(label, u64_of(&step["value"])),
+                        "append_point" => t.append_point(label, &point(&step["point"])),
+                        "append_scalar" => t.append_scalar(label, &scalar(&step["scalar"])),
+                        "challenge" => out["expected"] = scalar_json(&t.challenge_scalar(label)),
+                        _ => panic!("unknown transcript step {}", op),
+                    }
+                },
+            }
+            out
+        }).collect()
+    }
+
+    let params = CurveParams::new();
+    let script = |steps: Vec<Value>| json!({ "steps": run(&steps) });
+    let generated = json!({
+        "description": "Fiat-Shamir transcript over SHA3-512. Each challenge is checked against expected.",
+        "vectors": [
+            script(vec![
+                json!({ "op": "new", "protocol": "zkdp/test-vectors/transcript" }),
+                json!({ "op": "challenge", "label": "e" }),
+                json!({ "op": "challenge", "label": "e" }),
+            ]),
+            script(vec![
+                json!({ "op": "with_context", "protocol": label(PRIVATE_BIT_LABEL), "session": SESSION, "index": 3 }),
+                json!({ "op": "append_message", "label": "msg", "message": hex(b"hello") }),
+                json!({ "op": "append_message", "label": "empty", "message": "" }),
+                json!({ "op": "append_u64", "label": "n", "value": 1u64 << 40 }),
+                json!({ "op": "append_point", "label": "g", "point": point_json(&params.g) }),
+                json!({ "op": "append_scalar", "label": "s", "scalar": scalar_json(&Scalar::from(42u64)) }),
+                json!({ "op": "challenge", "label": "x" }),
+                json!({ "op": "append_point", "label": "h", "point": point_json(&params.h) }),
+                json!({ "op": "challenge", "label": "y" }),
+            ]),
+        ],
+    });
+
+    let fixture = check_fixture("transcript.json", generated);
+    for vector in fixture["vectors"].as_array().unwrap() {
+        let steps = vector["steps"].as_array().unwrap();
+        assert_eq!(&run(steps), steps);
+    }
+}
+
+// Pedersen commitments g x + h r, and the sum of commitments to bits opening to the sum of the bits
+#[test]
+pub fn test_commitment_vectors() {
+    let com = CurveParams::new().commitment();
+    let single: Vec<Value> = [(1, 0u64), (2, 1), (3, 5), (4, 1 << 40)].iter().map(|&(seed, message)| {
+        let r = com.sample_randomness_with_rng(&mut ProtocolRng::seeded(seed));
+        json!({ "seed": seed, "message": message, "randomness": scalar_json(&r), "commitment": point_json(&com.commit(Scalar::from(message), r)) })
+    }).collect();
+
+    let bits = [1u64, 0, 1, 1, 0, 1];
+    let mut rng = ProtocolRng::seeded(5);
+    let rs: Vec<Scalar> = bits.iter().map(|_| random_scalar(&mut rng)).collect();
+    let coms: Vec<RistrettoPoint> = bits.iter().zip(rs.iter()).map(|(b, r)| com.commit(Scalar::from(*b), *r)).collect();
+    let sum = json!({
+        "seed": 5,
+        "bits": bits,
+        "randomness": scalars_json(&rs),
+        "commitments": points_json(&coms),
+        "sum": bits.iter().sum::<u64>(),
+        "sum_randomness": scalar_json(&rs.iter().sum()),
+        "sum_commitment": point_json(&coms.iter().sum()),
+    });
+
+    let generated = json!({
+        "description": "Pedersen commitments g message + h randomness on Ristretto, and a bit-sum check",
+        "params": params_json(),
+        "vectors": single,
+        "bit_sums": [sum],
+    });
+
+    let fixture = check_fixture("commitments.json", generated);
+    let com = fixture_commitment(&fixture);
+    for v in fixture["vectors"].as_array().unwrap() {
+        assert!(com.open(Scalar::from(u64_of(&v["message"])), scalar(&v["randomness"]), point(&v["commitment"])));
+    }
+    for v in fixture["bit_sums"].as_array().unwrap() {
+        let bits: Vec<u64> = v["bits"].as_array().unwrap().iter().map(u64_of).collect();
+        let coms = points(&v["commitments"]);
+        for ((b, r), c) in bits.iter().zip(scalars(&v["randomness"])).zip(coms.iter()) {
+            assert!(com.open(Scalar::from(*b), r, *c));
+        }
+        assert_eq!(coms.iter().sum::<RistrettoPoint>(), point(&v["sum_commitment"]));
+        assert!(com.open(Scalar::from(u64_of(&v["sum"])), scalar(&v["sum_randomness"]), point(&v["sum_commitment"])));
+    }
+}
+
+// OR-proofs that a commitment opens to 0 or 1, made by CurveCommitment::create_bit_proof
+#[test]
+pub fn test_or_proof_vectors() {
+    let com = CurveParams::new().commitment();
+    let protocol = label(PRIVATE_BIT_LABEL);
+    let valid: Vec<(u64, u64, ProofScalar, Scalar)> = [(10u64, 0u64), (11, 1), (12, 1), (13, 0)].iter().enumerate().map(|(index, &(seed, bit))| {
+        let mut rng = ProtocolRng::seeded(seed);
+        let r = com.sample_randomness_with_rng(&mut rng);
+        let mut transcript = context(&protocol, SESSION, index as u64);
+        let proof = com.create_bit_proof((bit as u8).ct_eq(&1), r, &mut transcript, &mut rng);
+        (seed, bit, proof, r)
+    }).collect();
+
+    let mut vectors: Vec<Value> = valid.iter().enumerate().map(|(index, (seed, bit, proof, r))| json!({
+        "seed": seed,
+        "protocol": protocol,
+        "session": SESSION,
+        "index": index,
+        "bit": bit,
+        "randomness": scalar_json(r),
+        "proof": bit_proof_json(proof),
+        "valid": true,
+    })).collect();
+    // The first proof replayed at another index, and with a response changed
+    let mut replayed = vectors[0].clone();
+    replayed["index"] = json!(1);
+    replayed["valid"] = json!(false);
+    let mut tampered = vectors[1].clone();
+    tampered["proof"]["v0"] = scalar_json(&(valid[1].2.v0 + Scalar::one()));
+    tampered["valid"] = json!(false);
+    vectors.extend([replayed, tampered]);
+
+    let generated = json!({
+        "description": "OR-proofs that com opens to 0 or 1, with the generators g and h absorbed after the context",
+        "params": params_json(),
+        "vectors": vectors,
+    });
+
+    let fixture = check_fixture("or_proofs.json", generated);
+    let com = fixture_commitment(&fixture);
+    for v in fixture["vectors"].as_array().unwrap() {
+        let proof = bit_proof(&v["proof"]);
+        let mut transcript = context(str_of(&v["protocol"]), str_of(&v["session"]), u64_of(&v["index"]));
+        assert_eq!(com.verify_bit_proof(&proof, &mut transcript), v["valid"].as_bool().unwrap(), "{}", v);
+        assert!(com.open(Scalar::from(u64_of(&v["bit"])), scalar(&v["randomness"]), proof.com));
+    }
+}
+
+// Relation of sigma_protocols as it appears in the fixture
+enum Statement {
+    Linear(LinearRelation),
+    And(Vec<Statement>),
+    Or(usize, Vec<Statement>),
+}
+
+impl Statement {
+
+    fn protocol(&self) -> Box<dyn SigmaProtocol> {
+        match self {
+            Statement::Linear(rel) => Box::new(LinearRelation::new(rel.generators.clone(), rel.targets.clone())),
+            Statement::And(parts) => Box::new(And::new(parts.iter().map(Statement::protocol).collect())),
+            Statement::Or(k, branches) => Box::new(Or::new(*k, branches.iter().map(Statement::protocol).collect())),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Statement::Linear(rel) => json!({
+                "type": "linear",
+                "generators": Value::Array(rel.generators.iter().map(|row| points_json(row)).collect()),
+                "targets": points_json(&rel.targets),
+            }),
+            Statement::And(parts) => json!({ "type": "and", "parts": parts.iter().map(Statement::to_json).collect::<Vec<_>>() }),
+            Statement::Or(k, branches) => json!({ "type": "or", "k": k, "branches": branches.iter().map(Statement::to_json).collect::<Vec<_>>() }),
+        }
+    }
+
+    fn from_json(v: &Value) -> Statement {
+        let list = |key: &str| v[key].as_array().unwrap().iter().map(Statement::from_json).collect();
+        match str_of(&v["type"]) {
+            "linear" => Statement::Linear(LinearRelation::new(v["generators"].as_array().unwrap().iter().map(points).collect(), points(&v["targets"]))),
+            "and" => Statement::And(list("parts")),
+            "or" => Statement::Or(u64_of(&v["k"]) as usize, list("branches")),
+            t => panic!("unknown relation {}", t),
+        }
+    }
+}
+
+fn proof_json(proof: &FiatShamirProof) -> Value {
+    json!({ "commitment": points_json(&proof.commitment), "challenge": scalar_json(&proof.challenge), "response": scalars_json(&proof.response) })
+}
+
+// Every relation of sigma_protocols, compiled with the Fiat-Shamir transform
+#[test]
+pub fn test_sigma_proof_vectors() {
+    let com = CurveParams::new().commitment();
+    type Case<'a> = (&'a str, Box<dyn Fn(&mut ProtocolRng) -> (Statement, Witness) + 'a>);
+    let cases: Vec<Case> = vec![
+        ("dlog", Box::new(|rng| {
+            let w = random_scalar(rng);
+            (Statement::Linear(LinearRelation::dlog(com.h, w * com.h)), Witness::Scalars(vec![w]))
+        })),
+        ("opening", Box::new(|rng| {
+            let (x, r) = (Scalar::from(42u64), random_scalar(rng));
+            (Statement::Linear(LinearRelation::opening(&com, com.commit(x, r))), Witness::Scalars(vec![x, r]))
+        })),
+        ("equality", Box::new(|rng| {
+            let (x, r1, r2) = (Scalar::from(7u64), random_scalar(rng), random_scalar(rng));
+            let relation = LinearRelation::equality(&com, com.commit(x, r1), com.commit(x, r2));
+            (Statement::Linear(relation), Witness::Scalars(vec![x, r1, r2]))
+        })),
+        ("linear_combination", Box::new(|rng| {
+            // x_t = 2 x_1 + 5 x_2 + 1
+            let (r1, r2, r_t) = (random_scalar(rng), random_scalar(rng), random_scalar(rng));
+            let coms = [com.commit(Scalar::from(3u64), r1), com.commit(Scalar::from(4u64), r2)];
+            let coeffs = [Scalar::from(2u64), Scalar::from(5u64)];
+            let target = com.commit(Scalar::from(27u64), r_t);
+            let relation = LinearRelation::linear_combination(&com, &coms, &coeffs, Scalar::one(), target);
+            (Statement::Linear(relation), Witness::Scalars(vec![r_t - coeffs[0] * r1 - coeffs[1] * r2]))
+        })),
+        ("and", Box::new(|rng| {
+            let (x, r1, r2) = (Scalar::from(9u64), random_scalar(rng), random_scalar(rng));
+            let (c1, c2) = (com.commit(x, r1), com.commit(x, r2));
+            let statement = Statement::And(vec![
+                Statement::Linear(LinearRelation::opening(&com, c1)),
+                Statement::Linear(LinearRelation::equality(&com, c1, c2)),
+            ]);
+            (statement, Witness::And(vec![Witness::Scalars(vec![x, r1]), Witness::Scalars(vec![x, r1, r2])]))
+        })),
+        ("or_1_of_2", Box::new(|rng| {
+            // The relation of bit_relation for a commitment to 1
+            let r = random_scalar(rng);
+            let c = com.commit(Scalar::one(), r);
+            let branches = vec![Statement::Linear(LinearRelation::dlog(com.h, c)), Statement::Linear(LinearRelation::dlog(com.h, c - com.g))];
+            (Statement::Or(1, branches), Witness::Or(vec![None, Some(Witness::Scalars(vec![r]))]))
+        })),
+        ("or_2_of_4", Box::new(|rng| {
+            // Branch i holds if the i-th commitment opens to 0, only branches 1 and 3 do
+            let rs: Vec<Scalar> = (0..4).map(|_| random_scalar(rng)).collect();
+            let values = [9u64, 0, 4, 0];
+            let branches = values.iter().zip(rs.iter())
+                .map(|(x, r)| Statement::Linear(LinearRelation::dlog(com.h, com.commit(Scalar::from(*x), *r))))
+                .collect();
+            (Statement::Or(2, branches), Witness::Or(vec![None, Some(Witness::Scalars(vec![rs[1]])), None, Some(Witness::Scalars(vec![rs[3]]))]))
+        })),
+    ];
+
+    let vectors: Vec<Value> = cases.iter().enumerate().map(|(index, (name, case))| {
+        let seed = 20 + index as u64;
+        let mut rng = ProtocolRng::seeded(seed);
+        let (statement, witness) = case(&mut rng);
+        let proof = prove_with_rng(&*statement.protocol(), &witness, &mut context(SIGMA_LABEL, SESSION, index as u64), &mut rng).unwrap();
+        json!({
+            "name": name,
+            "seed": seed,
+            "protocol": SIGMA_LABEL,
+            "session": SESSION,
+            "index": index,
+            "statement": statement.to_json(),
+            "proof": proof_json(&proof),
+        })
+    }).collect();
+
+    let generated = json!({
+        "description": "Sigma protocols for linear relations on Ristretto with AND and k-out-of-n OR, made non-interactive with Fiat-Shamir",
+        "params": params_json(),
+        "vectors": vectors,
+    });
+
+    let fixture = check_fixture("sigma_proofs.json", generated);
+    fixture_commitment(&fixture);
+    for v in fixture["vectors"].as_array().unwrap() {
+        let protocol = Statement::from_json(&v["statement"]).protocol();
+        let mut proof = FiatShamirProof { commitment: points(&v["proof"]["commitment"]), challenge: scalar(&v["proof"]["challenge"]), response: scalars(&v["proof"]["response"]) };
+        let transcript = || context(str_of(&v["protocol"]), str_of(&v["session"]), u64_of(&v["index"]));
+        assert!(sigma_protocols::verify(&*protocol, &proof, &mut transcript()), "{}", v["name"]);
+        proof.response[0] += Scalar::one();
+        assert!(!sigma_protocols::verify(&*protocol, &proof, &mut transcript()), "{}", v["name"]);
+    }
+}
+
+fn bit_vector_proof_json(proof: &BitVectorProof) -> Value {
+    json!({
+        "a_r_com": point_json(&proof.a_r_com),
+        "s": point_json(&proof.s),
+        "t1": point_json(&proof.t1),
+        "t2": point_json(&proof.t2),
+        "tau_x": scalar_json(&proof.tau_x),
+        "mu": scalar_json(&proof.mu),
+        "t_hat": scalar_json(&proof.t_hat),
+        "ipp": { "l_vec": points_json(&proof.ipp.l_vec), "r_vec": points_json(&proof.ipp.r_vec), "a": scalar_json(&proof.ipp.a), "b": scalar_json(&proof.ipp.b) },
+    })
+}
+
+fn bit_vector_proof(v: &Value) -> BitVectorProof {
+    BitVectorProof {
+        a_r_com: point(&v["a_r_com"]),
+        s: point(&v["s"]),
+        t1: point(&v["t1"]),
+        t2: point(&v["t2"]),
+        tau_x: scalar(&v["tau_x"]),
+        mu: scalar(&v["mu"]),
+        t_hat: scalar(&v["t_hat"]),
+        ipp: InnerProductProof { l_vec: points(&v["ipp"]["l_vec"]), r_vec: points(&v["ipp"]["r_vec"]), a: scalar(&v["ipp"]["a"]), b: scalar(&v["ipp"]["b"]) },
+    }
+}
+
+// Logarithmic proofs that a committed vector holds bits and that V commits to their weighted sum
+#[test]
+pub fn test_bit_vector_vectors() {
+    let n = 8;
+    let vc = VectorCommitment::new(n);
+    let protocol = label(VECTOR_NOISE_LABEL);
+    let one = Scalar::one();
+    let xor_weights = |coins: &[u64]| -> Vec<Scalar> { coins.iter().map(|c| one - Scalar::from(2 * c)).collect() };
+    let cases: Vec<(&str, Vec<u64>, Vec<Scalar>)> = vec![
+        ("sum", vec![1, 0, 1, 1, 0, 0, 1, 0], vec![one; 8]),
+        ("xor_with_coins_padded", vec![1, 1, 0, 1, 0], xor_weights(&[0, 1, 1, 0, 1])),
+    ];
+
+    let vectors: Vec<Value> = cases.iter().enumerate().map(|(index, (name, bits, weights))| {
+        let seed = 30 + index as u64;
+        let mut rng = ProtocolRng::seeded(seed);
+        let (r, gamma) = (random_scalar(&mut rng), random_scalar(&mut rng));
+        let bit_scalars: Vec<Scalar> = bits.iter().map(|b| Scalar::from(*b)).collect();
+        let mut transcript = context(&protocol, SESSION, index as u64);
+        let (proof, c, v) = vc.prove_bits_with_rng(&mut transcript, &bit_scalars, r, weights, gamma, &mut rng).unwrap();
+        json!({
+            "name": name,
+            "seed": seed,
+            "protocol": protocol,
+            "session": SESSION,
+            "index": index,
+            "bits": bits,
+            "weights": scalars_json(weights),
+            "randomness": scalar_json(&r),
+            "gamma": scalar_json(&gamma),
+            "c": point_json(&c),
+            "v": point_json(&v),
+            "proof": bit_vector_proof_json(&proof),
+        })
+    }).collect();
+
+    let mut params = params_json();
+    params["n"] = json!(n);
+    params["g_vec"] = points_json(&vc.g_vec);
+    params["h_vec"] = points_json(&vc.h_vec);
+    params["u"] = point_json(&vc.u);
+    let generated = json!({
+        "description": "Bit-vector proofs: C = <bits, G> + r h holds bits and V = <weights, bits> g + gamma h, padded to n",
+        "params": params,
+        "vectors": vectors,
+    });
+
+    let fixture = check_fixture("bit_vector.json", generated);
+    let params = &fixture["params"];
+    let vc = VectorCommitment::new(u64_of(&params["n"]) as usize);
+    assert_eq!((vc.g, vc.h, vc.u), (point(&params["g"]), point(&params["h"]), point(&params["u"])));
+    assert_eq!((vc.g_vec.clone(), vc.h_vec.clone()), (points(&params["g_vec"]), points(&params["h_vec"])));
+    for v in fixture["vectors"].as_array().unwrap() {
+        let (c, v_com, weights) = (point(&v["c"]), point(&v["v"]), scalars(&v["weights"]));
+        let bits: Vec<Scalar> = v["bits"].as_array().unwrap().iter().map(|b| Scalar::from(u64_of(b))).collect();
+        assert_eq!(vc.commit(&bits, scalar(&v["randomness"])), c);
+        let value: Scalar = bits.iter().zip(weights.iter()).map(|(b, w)| b * w).sum();
+        assert_eq!(vc.commit_scalar(value, scalar(&v["gamma"])), v_com);
+        let transcript = || context(str_of(&v["protocol"]), str_of(&v["session"]), u64_of(&v["index"]));
+        let mut proof = bit_vector_proof(&v["proof"]);
+        assert!(vc.verify_bits(&mut transcript(), &c, &v_com, &weights, &proof), "{}", v["name"]);
+        proof.t_hat += Scalar::one();
+        assert!(!vc.verify_bits(&mut transcript(), &c, &v_com, &weights, &proof), "{}", v["name"]);
+    }
+}
+
+// A whole BinomialRunner session with the per-bit OR-proofs, checked the way an auditor with only the
+// published transcript would: the proofs, the public coins, the XOR commitments and the final opening
+#[test]
+pub fn test_binomial_run_vector() {
+    let seed = 2024;
+    let inputs = [1u8, 0, 1, 1, 0, 1, 1, 0];
+    let noise = [0u8, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 0];
+    let mut br = BinomialRunner::new_with_rng(&inputs, None, ProtocolRng::seeded(seed));
+    br.input_randomness(&noise);
+    let result = br.replay_sum().expect("A seeded run");
+    br.commit_pedersons();
+    assert!(br.verify());
+
+    let generated = json!({
+        "description": "BinomialRunner session seeded with ChaCha20: inputs, noise bits with OR-proofs, public coins and the release",
+        "params": params_json(),
+        "seed": seed,
+        "inputs": inputs,
+        "noise_bits": noise,
+        "session_id": br.get_session_id(),
+        "input_commitments": br.get_x_commits().iter().map(|c| decimal_to_hex(c)).collect::<Vec<_>>(),
+        "private_bit_proofs": br.get_private_proofs().iter().map(bit_proof_json).collect::<Vec<_>>(),
+        "coin_seed": hex(&br.get_coin_seed()),
+        "public_coins": br.get_public_random(),
+        "xor_commitments": br.get_xor_commits().iter().map(|c| decimal_to_hex(c)).collect::<Vec<_>>(),
+        "result": result,
+        "z": decimal_to_hex(&br.get_z()),
+        "release_commitment": decimal_to_hex(&br.get_lhs()),
+    });
+
+    let fixture = check_fixture("binomial_run.json", generated);
+    let com = fixture_commitment(&fixture);
+    let session = str_of(&fixture["session_id"]);
+    let proofs: Vec<ProofScalar> = fixture["private_bit_proofs"].as_array().unwrap().iter().map(bit_proof).collect();
+    for (i, proof) in proofs.iter().enumerate() {
+        assert!(com.verify_bit_proof(proof, &mut context(&label(PRIVATE_BIT_LABEL), session, i as u64)));
+    }
+
+    // Coins: challenges "coins" of the transcript over the seed and the bit commitments, low 31 bytes, low bit first
+    let mut transcript = context(&label(PUBLIC_COIN_LABEL), session, 0);
+    transcript.append_message(b"seed", &unhex(&fixture["coin_seed"]));
+    for proof in proofs.iter() {
+        transcript.append_point(b"com", &proof.com);
+    }
+    let mut coins = Vec::new();
+    while coins.len() < proofs.len() {
+        let e = transcript.challenge_scalar(b"coins");
+        for byte in &e.as_bytes()[..31] {
+            coins.extend((0..8).map(|k| (byte >> k) & 1));
+        }
+    }
+    coins.truncate(proofs.len());
+    let published: Vec<u8> = fixture["public_coins"].as_array().unwrap().iter().map(|c| u64_of(c) as u8).collect();
+    assert_eq!(coins, published);
+
+    // A one coin turns Com(b, r) into g - Com(b, r) = Com(1 - b, -r)
+    let xor_commitments = points(&fixture["xor_commitments"]);
+    for ((proof, coin), xor) in proofs.iter().zip(coins.iter()).zip(xor_commitments.iter()) {
+        assert_eq!(*xor, if *coin == 1 { com.g - proof.com } else { proof.com });
+    }
+
+    let release = point(&fixture["release_commitment"]);
+    assert!(com.open(Scalar::from(u64_of(&fixture["result"])), scalar(&fixture["z"]), release));
+    let total: RistrettoPoint = points(&fixture["input_commitments"]).iter().chain(xor_commitments.iter()).sum();
+    assert_eq!(total, release);
+}